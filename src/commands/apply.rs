@@ -1,367 +1,2779 @@
-use anyhow::Result;
-use md5;
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::Path;
-use std::process::Command;
-use tempfile::NamedTempFile;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::INITIAL_MIGRATION_FILE_NAME;
+use super::balance::unbalanced;
+use super::disabled::Disabled;
+use super::drop::{drop_first_sql, recreate_view_sql, signatures_sql};
+use super::init::{
+    create_directory_structure, restore_custom_format_dump, snapshot_schema, STANDARD_DIRS,
+};
+use super::interpolate::interpolate;
+use super::metadata::{order_by_dependencies, Metadata};
+use super::plan::{state_guard_sql, Plan};
+use super::psql::{
+    applied_marker, execute_sql, log_command, redact, restore_line, timing_marker, RESTORE,
+    TRANSACTION_BREAK,
+};
+use super::source::{is_archive, sql_name, Bundle, Origin, Source};
+use super::trigger::{split_bindings, Binding};
+use crate::DEFAULT_PGM_PATH;
 
-pub fn apply(pgm_dir_path: &str, dry_run: bool, fake: bool) -> Result<()> {
-    // Compile the SQL
-    let sql = if fake {
-        build_fake(pgm_dir_path).expect("Failed to compile fake SQL")
+/// The categories of objects `apply` manages, named after their directories.
+pub const CATEGORIES: [&str; 8] = [
+    "extensions",
+    "migrations",
+    "functions",
+    "triggers",
+    "constraints",
+    "views",
+    "grants",
+    "comments",
+];
+
+/// The levels [`ApplyOptions::messages`] can be set to, from the most to the
+/// fewest messages.
+pub const MESSAGE_LEVELS: [&str; 5] = ["debug", "log", "notice", "warning", "error"];
+
+/// Whether the notices pgm raises for each file are shown at the message
+/// `level`.
+pub(crate) fn shows_notices(level: &str) -> bool {
+    let position = |level| MESSAGE_LEVELS.iter().position(|l| *l == level);
+    position(level) <= position("notice")
+}
+
+/// Fails on a level not in [`MESSAGE_LEVELS`].
+pub(crate) fn check_message_level(level: &str) -> Result<()> {
+    if !MESSAGE_LEVELS.contains(&level) {
+        return Err(anyhow::anyhow!(
+            "Invalid message level '{}'; use one of {}",
+            level,
+            MESSAGE_LEVELS.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// The phases [`compile`] applies the files in, in their default
+/// [`ApplyOptions::order`]. Functions and triggers are created in their phase
+/// without checking the bodies of the functions, as those may use tables the
+/// migrations only create later, then re-run with their bodies checked, and
+/// the triggers bound to their tables, in `checks`.
+pub const PHASES: [&str; 9] = [
+    "extensions",
+    "baselines",
+    "functions",
+    "triggers",
+    "migrations",
+    "constraints",
+    "views",
+    "checks",
+    "grants",
+];
+
+/// Fails unless `order` lists each of the [`PHASES`] once, with `checks`
+/// after `functions` and `triggers`, and `baselines` before `migrations`.
+pub(crate) fn check_order(order: &[String]) -> Result<()> {
+    let position = |phase: &str| order.iter().position(|p| p == phase);
+    let mut problems = Vec::new();
+    for (i, phase) in order.iter().enumerate() {
+        let duplicate = format!("'{}' is listed more than once", phase);
+        if !PHASES.contains(&phase.as_str()) {
+            problems.push(format!("unknown phase '{}'", phase));
+        } else if order[..i].contains(phase) && !problems.contains(&duplicate) {
+            problems.push(duplicate);
+        }
+    }
+    for phase in PHASES {
+        if position(phase).is_none() {
+            problems.push(format!("'{}' is missing", phase));
+        }
+    }
+    for (before, after) in [
+        ("functions", "checks"),
+        ("triggers", "checks"),
+        ("baselines", "migrations"),
+    ] {
+        if let (Some(b), Some(a)) = (position(before), position(after)) {
+            if b > a {
+                problems.push(format!("'{}' must come before '{}'", before, after));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Invalid apply order {}: {}; the default is {}",
+            order.join(","),
+            problems.join(", "),
+            PHASES.join(",")
+        ));
+    }
+    Ok(())
+}
+
+/// The key of the advisory lock every apply takes, "pgm" in ASCII, so that
+/// concurrent applies to the same database run one after the other.
+const ADVISORY_LOCK_KEY: i64 = 0x70676d;
+
+/// Options for [`build`] and [`apply`].
+#[derive(Debug, Clone)]
+pub struct ApplyOptions {
+    /// The pgm directory
+    pub path: String,
+    /// Prints the SQL instead of applying it
+    pub dry_run: bool,
+    /// Only updates the pgm_ tables without executing the actual SQL
+    pub fake: bool,
+    /// The categories to apply, out of [`CATEGORIES`]. Categories left out are
+    /// not touched at all, so applying migrations without the functions they
+    /// depend on is up to the caller.
+    pub categories: Vec<String>,
+    /// Fails instead of warning when an applied migration has been edited, or
+    /// when a function, view or trigger creates, alters or drops a table
+    pub strict: bool,
+    /// Fails before applying anything unless the migration numbers run from
+    /// 1 up without gaps or duplicates
+    pub strict_order: bool,
+    /// Runs each migration file larger than this many bytes, such as a large
+    /// baseline, in a transaction and `psql` run of its own, recorded in
+    /// `pgm_migration` along with it. What comes before it is committed
+    /// first, so the apply is no longer all or nothing.
+    pub max_migration_size: Option<u64>,
+    /// Keeps the first, in path order, of the migration files resolving to
+    /// the same name and skips the others with a warning, instead of failing
+    pub skip_duplicates: bool,
+    /// The order of the [`PHASES`] the files are applied in, e.g. to create
+    /// the views before the migrations that use them
+    pub order: Vec<String>,
+    /// If not empty, only files matching one of these globs are applied
+    pub include: Vec<String>,
+    /// Files matching one of these globs are left out
+    pub exclude: Vec<String>,
+    /// `(name, value)` pairs passed to psql as `-v name=value`, and readable
+    /// in the compiled SQL with `current_setting('pgm.name')`
+    pub variables: Vec<(String, String)>,
+    /// Shows a progress bar instead of the applied/skipped lines when stdout
+    /// is a terminal
+    pub progress: bool,
+    /// Leaves out the NOTICEs psql prints for each file. They are still
+    /// counted in the [`ApplySummary`].
+    pub silent: bool,
+    /// Compiles the files of this bundle instead of the directory at `path`,
+    /// which is then only used to name them in messages
+    pub bundle: Option<Bundle>,
+    /// How long to wait for another apply to finish, or for any other lock,
+    /// before failing. Waits indefinitely if `None`.
+    pub lock_timeout: Option<Duration>,
+    /// Raises a notice with the time each applied object took, collected in
+    /// [`ApplySummary::timings`]
+    pub timing: bool,
+    /// Only applies if the SQL and the pgm tables are still as when this plan
+    /// was made by [`plan`](super::plan()), failing before changing anything
+    /// otherwise
+    pub plan: Option<Plan>,
+    /// Connects to this database instead of using the PG* environment variables
+    pub database_url: Option<String>,
+    /// Strips comments from the compiled SQL. Defaults to stripping them,
+    /// except on `dry_run`, where the SQL is meant to be read.
+    pub minify: Option<bool>,
+    /// Keeps the `-- RUN`/`-- DONE` lines around each file when minifying, to
+    /// find the file an error comes from in the compiled SQL
+    pub pretty: bool,
+    /// Compiles a pgm directory without any files instead of failing, which
+    /// usually means `path` is wrong
+    pub allow_empty: bool,
+    /// Migrations to record as applied without running them, each given by
+    /// its name, file name or number, e.g. one that was run by hand. The
+    /// other files are applied as usual.
+    pub fake_migrations: Vec<String>,
+    /// Migrations to run again although they were applied, e.g. after
+    /// reverting their changes by hand, each given as in `fake_migrations`.
+    /// Their records are deleted right before they run, in the same
+    /// transaction, and recorded anew.
+    pub rerun_migrations: Vec<String>,
+    /// The `client_min_messages` to run with, out of [`MESSAGE_LEVELS`]. Above
+    /// `notice`, the notices pgm raises for each file are not shown either,
+    /// so the [`ApplySummary`] cannot count them.
+    pub messages: String,
+    /// Recreates the standard subdirectories missing from the pgm directory,
+    /// which are otherwise warned about
+    pub repair: bool,
+    /// A shell command to run after a successful apply, except a fake one,
+    /// with the [`ApplySummary`] counts in `PGM_*` environment variables. It
+    /// runs after the `hooks/post_apply.sql` file of the pgm directory, if
+    /// there is one.
+    pub post_apply: Option<String>,
+    /// Dumps the schema of the database to a timestamped file in
+    /// `.pgm-snapshots/` of the pgm directory before applying, to restore
+    /// by hand if needed. With a bundle, the directory is made in the current
+    /// directory.
+    pub snapshot: bool,
+    /// Keeps the temporary file with the SQL psql runs, instead of deleting
+    /// it, and logs its path, e.g. to look up the line an error points at
+    pub keep_temp: bool,
+    /// Fails the apply when a post-apply hook fails, instead of warning. The
+    /// changes are committed either way.
+    pub post_apply_fatal: bool,
+    /// Runs the files as this role, with `SET ROLE`, so the objects they
+    /// create are owned by it, e.g. when deploying as a superuser
+    pub role: Option<String>,
+    /// Compiles a plain schema script of the files in the order they are
+    /// applied, without the pgm tables, the checks of what was applied or
+    /// the `DO` block, e.g. to hand to a DBA. It can only be printed with
+    /// `dry_run`, as it would re-run everything.
+    pub dump_only: bool,
+    /// A file to append a JSON line to after each successful apply, except a
+    /// fake one, with the time, the pgm version, the [`ApplySummary`] counts
+    /// and the duration, e.g. to chart deploy times
+    pub metrics_file: Option<PathBuf>,
+    /// Applies only this object file, relative to `path`, e.g.
+    /// `functions/charge.sql`, with the same checks of its hash and records
+    /// as a full apply. The other files are not read, so the objects it
+    /// depends on must already be applied.
+    pub file: Option<PathBuf>,
+    /// Measures where the time of [`apply`] goes, into the
+    /// [`ApplySummary::profile`], e.g. to tell slow disks from a slow database
+    pub profile: bool,
+    /// Skips checking that the quotes, comments and parentheses of each
+    /// object file are balanced, for a file the check gets wrong
+    pub skip_balance_check: bool,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_PGM_PATH.to_string(),
+            dry_run: false,
+            fake: false,
+            categories: CATEGORIES.iter().map(|c| c.to_string()).collect(),
+            strict: false,
+            strict_order: false,
+            skip_duplicates: false,
+            max_migration_size: None,
+            order: PHASES.iter().map(|p| p.to_string()).collect(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            variables: Vec::new(),
+            progress: false,
+            silent: false,
+            bundle: None,
+            lock_timeout: None,
+            timing: false,
+            plan: None,
+            database_url: None,
+            minify: None,
+            pretty: false,
+            allow_empty: false,
+            fake_migrations: Vec::new(),
+            rerun_migrations: Vec::new(),
+            role: None,
+            repair: false,
+            post_apply: None,
+            snapshot: false,
+            keep_temp: false,
+            post_apply_fatal: false,
+            messages: "notice".to_string(),
+            dump_only: false,
+            metrics_file: None,
+            file: None,
+            profile: false,
+            skip_balance_check: false,
+        }
+    }
+}
+
+/// Compiles the SQL that [`apply`] would run for `options`, with comments
+/// kept as set by [`ApplyOptions::minify`]. The SQL starts with a
+/// header recording the pgm version, the time and the [`checksum`] of the
+/// rest of the SQL.
+pub fn build(options: &ApplyOptions) -> Result<String> {
+    let body = build_body(options)?;
+    Ok(format!("{}{}", header(&body), body))
+}
+
+/// Returns the hex-encoded sha256 of compiled SQL without its header. It only
+/// depends on the files compiled, so it can be used to verify an artifact.
+pub fn checksum(body: &str) -> String {
+    Sha256::digest(body.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub(crate) fn header(body: &str) -> String {
+    format!(
+        "-- Compiled by pgm {}\n-- Compiled at {}\n-- sha256: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        checksum(body)
+    )
+}
+
+/// Returns where `options` has the files compiled from.
+pub(crate) fn source(options: &ApplyOptions) -> Source<'_> {
+    match &options.bundle {
+        Some(bundle) => Source::bundle(PathBuf::from(&options.path), bundle),
+        None => Source::directory(PathBuf::from(&options.path)),
+    }
+}
+
+pub(crate) fn build_body(options: &ApplyOptions) -> Result<String> {
+    Ok(build_body_profiled(options)?.0)
+}
+
+/// Compiles the body as [`build_body`] does, and times reading, checking and
+/// compiling the files for a [`Profile`] still missing the execute time.
+fn build_body_profiled(options: &ApplyOptions) -> Result<(String, Profile)> {
+    let started = Instant::now();
+    let single_file_options;
+    let (options, only) = match &options.file {
+        Some(file) => {
+            let (category, path) = single_file(&source(options), file)?;
+            single_file_options = ApplyOptions {
+                categories: vec![category.to_string()],
+                ..options.clone()
+            };
+            (&single_file_options, Some((category, path)))
+        }
+        None => (options, None),
+    };
+    let source = source(options);
+    let mut filter = FileFilter::new(source.root(), &options.include, &options.exclude)?;
+    filter.only = only.as_ref().map(|(_, path)| path.clone());
+    let variables = variables_sql(&options.variables, !options.dump_only)?;
+    if let Some(role) = &options.role {
+        check_role(role)?;
+    }
+    check_message_level(&options.messages)?;
+    if options.timing && !shows_notices(&options.messages) {
+        return Err(anyhow::anyhow!(
+            "Timing relies on notices, which are not shown with messages at '{}'",
+            options.messages
+        ));
+    }
+    check_structure(&source, options)?;
+    if !options.allow_empty && filter.only.is_none() && source.exists() && is_empty(&source)? {
+        return Err(anyhow::anyhow!(
+            "No objects found in '{}'; did you create any? Pass --allow-empty to apply anyway",
+            source.root().display()
+        ));
+    }
+    check_order(&options.order)?;
+    filter.disabled = Disabled::read(&source)?;
+    // The checks of a single file leave the rest of its category alone
+    if filter.only.is_none() {
+        filter.skipped =
+            check_duplicate_names(&source, &options.categories, options.skip_duplicates)?;
+    }
+    if options.strict_order
+        && options.categories.iter().any(|c| c == "migrations")
+        && source.has_dir("migrations")
+    {
+        check_migration_order(&source)?;
+    }
+    let read_started = Instant::now();
+    let files = match only {
+        Some((category, path)) => vec![SqlFile {
+            category: category.to_string(),
+            content: source.read(&path)?,
+            path,
+        }],
+        None => read_files(&source, &options.categories)?,
+    };
+    let read = read_started.elapsed();
+    check_metadata(&files)?;
+    check_once_only_ddl(&files, options.strict)?;
+    if !options.skip_balance_check {
+        check_balance(&files, &filter)?;
+    }
+    let markers = Markers::for_files(&files);
+    let check = started.elapsed() - read;
+    let compile_started = Instant::now();
+    let comments = match (options.minify.unwrap_or(!options.dry_run), options.pretty) {
+        (false, _) => Comments::Keep,
+        (true, true) => Comments::Markers,
+        (true, false) => Comments::Strip,
+    };
+    let body = if options.dump_only {
+        compile_dump(
+            &source,
+            comments,
+            &markers,
+            &options.categories,
+            &options.order,
+            &filter,
+            options.role.as_deref(),
+        )
+        .context("Failed to compile SQL")
+    } else if options.fake {
+        build_fake(
+            &source,
+            &options.categories,
+            &filter,
+            options.lock_timeout,
+            options.role.as_deref(),
+            &options.messages,
+        )
+        .context("Failed to compile fake SQL")
     } else {
-        build(pgm_dir_path, !dry_run).expect("Failed to compile SQL")
+        compile(&source, comments, &markers, &filter, options).context("Failed to compile SQL")
+    }?;
+    let compile = compile_started.elapsed();
+
+    let mut largest_files = files
+        .iter()
+        .map(|file| {
+            let path = file.path.strip_prefix(source.root()).unwrap_or(&file.path);
+            (path.display().to_string(), file.content.len())
+        })
+        .collect::<Vec<_>>();
+    largest_files.sort_by_key(|(_, bytes)| std::cmp::Reverse(*bytes));
+    largest_files.truncate(10);
+    let profile = Profile {
+        read,
+        check,
+        compile,
+        execute: Duration::ZERO,
+        largest_files,
     };
+    Ok((
+        format!("{}{}\n", variables, body.trim_end_matches('\n')),
+        profile,
+    ))
+}
 
-    // Print the SQL and exit if dry-run
-    if dry_run {
-        println!("{}", sql);
+/// Resolves the [`ApplyOptions::file`] `file` to its category and its path in
+/// the pgm directory of `source`. Migrations cannot be applied on their own,
+/// as the ones before them would be skipped.
+fn single_file(source: &Source, file: &Path) -> Result<(&'static str, PathBuf)> {
+    let relative = file.strip_prefix(source.root()).unwrap_or(file);
+    let category = relative
+        .parent()
+        .and_then(|parent| parent.to_str())
+        .and_then(|parent| CATEGORIES.iter().find(|category| **category == parent));
+    let is_sql = relative
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| sql_name(name, false))
+        .is_some();
+    let category = match category {
+        Some(&"migrations") => {
+            return Err(anyhow::anyhow!(
+                "'{}' is a migration; only object files can be applied on their own",
+                file.display()
+            ))
+        }
+        Some(category) if is_sql => *category,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "'{}' is not a SQL file in one of the {} directories",
+                file.display(),
+                CATEGORIES[..].join(", ")
+            ))
+        }
+    };
+    let path = source.root().join(relative);
+    if !source.has_file(&path)? {
+        return Err(anyhow::anyhow!("File '{}' not found", path.display()));
+    }
+    Ok((category, path))
+}
+
+/// Warns about the standard subdirectories of the categories being applied
+/// that are missing from the pgm directory, as `build` skips such a category
+/// as if there were nothing to do, or recreates them on
+/// [`ApplyOptions::repair`].
+fn check_structure(source: &Source, options: &ApplyOptions) -> Result<()> {
+    let Origin::Directory(root) = &source.origin else {
         return Ok(());
-    } else {
-        execute_sql(&sql)
+    };
+    if !root.is_dir() {
+        return Ok(());
+    }
+    if options.repair {
+        for dir in STANDARD_DIRS {
+            if !root.join(dir).is_dir() {
+                log::info!("Recreating the missing {}/ directory", dir);
+            }
+        }
+        return create_directory_structure(&root.to_string_lossy());
+    }
+    for dir in STANDARD_DIRS {
+        if options.categories.iter().any(|c| c == dir) && !root.join(dir).is_dir() {
+            log::warn!(
+                "No {dir}/ directory found in '{}'; nothing to apply for {dir}. Pass --repair to recreate it",
+                root.display()
+            );
+        }
     }
+    Ok(())
+}
+
+/// A file of the categories being applied, or the [`ApplyOptions::file`],
+/// read once by [`build_body`] for the checks and the [`Markers`].
+struct SqlFile {
+    category: String,
+    path: PathBuf,
+    content: String,
 }
 
-fn execute_sql(sql: &str) -> Result<()> {
-    // Check if psql exists
-    if !Command::new("psql").arg("--version").output().is_ok() {
+/// Reads the files of `categories` in `source`, in the order `build`
+/// compiles them.
+fn read_files(source: &Source, categories: &[String]) -> Result<Vec<SqlFile>> {
+    let mut files = Vec::new();
+    for category in categories {
+        for (_, path) in category_files(source, category)? {
+            files.push(SqlFile {
+                category: category.clone(),
+                content: source.read(&path)?,
+                path,
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Whether the pgm directory has no files in any category.
+fn is_empty(source: &Source) -> Result<bool> {
+    for category in CATEGORIES {
+        if !category_files(source, category)?.is_empty() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// psql does not interpolate its variables inside the dollar-quoted DO block,
+/// so each one is first copied into a `pgm.<name>` setting, `local` to the
+/// transaction unless the SQL runs outside of one.
+fn variables_sql(variables: &[(String, String)], local: bool) -> Result<String> {
+    let mut sql = String::new();
+    for (name, _) in variables {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(anyhow::anyhow!(
+                "Invalid variable name '{}'; use only letters, digits and underscores",
+                name
+            ));
+        }
+        sql.push_str(&format!(
+            "SELECT set_config('pgm.{name}', :'{name}', {local});\n"
+        ));
+    }
+    Ok(sql)
+}
+
+/// Fails on a role name that is not made of letters, digits, underscores and
+/// hyphens, which rules out anything but a name in the SQL.
+fn check_role(role: &str) -> Result<()> {
+    if role.is_empty()
+        || !role
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
         return Err(anyhow::anyhow!(
-            "psql not found. Please ensure it is installed and in your PATH."
+            "Invalid role name '{}'; use only letters, digits, underscores and hyphens",
+            role
         ));
     }
+    Ok(())
+}
 
-    // Create a temporary file
-    let mut temp_file = NamedTempFile::new().expect("Failed to create temporary file");
-    temp_file
-        .write_all(sql.as_bytes())
-        .expect("Failed to write SQL to temporary file");
+/// Switches to `role`, if any, for the rest of the transaction, or of the
+/// session unless `local`.
+fn set_role_sql(role: Option<&str>, local: bool) -> String {
+    match role {
+        Some(role) if local => format!("SET LOCAL ROLE \"{role}\";\n"),
+        Some(role) => format!("SET ROLE \"{role}\";\n"),
+        None => String::new(),
+    }
+}
 
-    // Construct the psql command
-    let mut command = Command::new("psql");
-    command.args(&[
-        "-f",
-        temp_file.path().to_str().unwrap(),
-        "-v",
-        "ON_ERROR_STOP=1",
-    ]);
+/// Switches back from the role of [`set_role_sql`].
+fn reset_role_sql(role: Option<&str>) -> String {
+    match role {
+        Some(_) => "RESET ROLE;\n".to_string(),
+        None => String::new(),
+    }
+}
 
-    let output = command.output().expect("Failed to execute psql command");
-    let stderr = String::from_utf8_lossy(&output.stderr);
+/// Compiles the changes and applies them, or prints the SQL on `dry_run`.
+/// Returns what was applied, or `None` on `dry_run`.
+pub fn apply(options: &ApplyOptions) -> Result<Option<ApplySummary>> {
+    if options.dump_only && !options.dry_run {
+        return Err(dump_only_error());
+    }
+    let (body, profile) = build_body_profiled(options)?;
+    let sql = apply_sql(options, &body)?;
 
-    // Process stderr to remove prefix 'psql:/path/to/temp/file:1234: '
-    stderr.lines().for_each(|line| {
-        println!("{}", line.split_once(": ").map_or(line, |(_, rest)| rest));
-    });
+    // Print the SQL and exit if dry-run
+    if options.dry_run {
+        print!("{}", sql);
+        return Ok(None);
+    }
+    let mut summary = run(&sql, &body, options, options.database_url.as_deref())?;
+    if options.profile {
+        summary.profile = Some(Profile {
+            execute: summary.elapsed,
+            ..profile
+        });
+    }
+    Ok(Some(summary))
+}
 
-    if output.status.success() {
+/// Where the time of an apply went, for [`ApplyOptions::profile`].
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Listing and reading the files of the categories applied, including
+    /// decompressing them
+    pub read: Duration,
+    /// Checking the files and the options before compiling
+    pub check: Duration,
+    /// Compiling the SQL from the files read
+    pub compile: Duration,
+    /// Running the SQL with psql
+    pub execute: Duration,
+    /// The largest files, largest first, as `(category/file, bytes)`
+    pub largest_files: Vec<(String, usize)>,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |elapsed: Duration| elapsed.as_secs_f64() * 1000.0;
+        writeln!(f, "Profile:")?;
+        writeln!(f, "  {:>10.1} ms  reading files", ms(self.read))?;
+        writeln!(f, "  {:>10.1} ms  checking files", ms(self.check))?;
+        writeln!(f, "  {:>10.1} ms  compiling", ms(self.compile))?;
+        writeln!(f, "  {:>10.1} ms  executing", ms(self.execute))?;
+        if !self.largest_files.is_empty() {
+            writeln!(f, "Largest files:")?;
+            for (file, bytes) in &self.largest_files {
+                writeln!(f, "  {:>10} B   {}", bytes, file)?;
+            }
+        }
         Ok(())
+    }
+}
+
+/// Runs `sql`, e.g. a one-off statement to try something, in the `DO` block
+/// apply wraps files in, with the variables, role, lock and messages of
+/// `options` but none of the files of the pgm directory. The pgm tables are
+/// neither created nor updated. As in a file, the SQL runs as PL/pgSQL, so a
+/// query whose result is not needed takes `PERFORM` instead of `SELECT`.
+/// Prints the SQL instead with `dry_run`. Returns how long the SQL took, or
+/// `None` on `dry_run`.
+pub fn run_sql(sql: &str, options: &ApplyOptions) -> Result<Option<Duration>> {
+    if let Some(role) = &options.role {
+        check_role(role)?;
+    }
+    check_message_level(&options.messages)?;
+    let name = "<sql>";
+    let content = strip_transaction_control(Path::new(name), sql)?;
+    let content = format!("{}\n", content.trim_end());
+    let body = format!(
+        "{}DO $pgm$ BEGIN\n{}{}SET LOCAL client_min_messages = {};\n{}{}END $pgm$;\n",
+        variables_sql(&options.variables, true)?,
+        lock_sql(options.lock_timeout),
+        set_role_sql(options.role.as_deref(), true),
+        options.messages,
+        Markers::new(&[&content]).wrap(name, &content),
+        reset_role_sql(options.role.as_deref()),
+    );
+    let sql = format!("{}{}", header(&body), body);
+
+    if options.dry_run {
+        print!("{}", sql);
+        return Ok(None);
+    }
+    let started = Instant::now();
+    execute_sql(
+        &sql,
+        options.database_url.as_deref(),
+        &options.variables,
+        false,
+        options.silent,
+        false,
+        options.keep_temp,
+    )?;
+    Ok(Some(started.elapsed()))
+}
+
+/// The outcome of applying to one database with [`apply_to_databases`].
+pub struct DatabaseApply {
+    /// The connection string of the database
+    pub database: String,
+    pub result: Result<ApplySummary>,
+}
+
+/// Compiles the changes once and applies them to each of `databases`, given
+/// as connection strings, one after the other. Each database is applied in
+/// its own transaction. Stops at the first database that fails unless
+/// `keep_going`, leaving the databases after it out of the result.
+pub fn apply_to_databases(
+    options: &ApplyOptions,
+    databases: &[String],
+    keep_going: bool,
+) -> Result<Vec<DatabaseApply>> {
+    if options.dump_only {
+        return Err(dump_only_error());
+    }
+    let body = build_body(options)?;
+    let sql = apply_sql(options, &body)?;
+
+    let mut applies = Vec::with_capacity(databases.len());
+    for database in databases {
+        log::info!("Applying to {}", redact(database));
+        let result = run(&sql, &body, options, Some(database));
+        let failed = result.is_err();
+        applies.push(DatabaseApply {
+            database: database.clone(),
+            result,
+        });
+        if failed && !keep_going {
+            break;
+        }
+    }
+    Ok(applies)
+}
+
+fn dump_only_error() -> anyhow::Error {
+    anyhow::anyhow!(
+        "A dump-only script does not check what was already applied, so it can only be printed with --dry-run"
+    )
+}
+
+/// Puts the header, and the guard of the plan if there is one, in front of
+/// the compiled `body`.
+fn apply_sql(options: &ApplyOptions, body: &str) -> Result<String> {
+    let sql = match &options.plan {
+        Some(plan) => {
+            if plan.checksum != checksum(body) {
+                return Err(anyhow::anyhow!(
+                    "The plan was made for other SQL than would be applied now; the files or the options changed since. Make a new plan"
+                ));
+            }
+            format!(
+                "{}{}{}",
+                header(body),
+                state_guard_sql(Some(&plan.state), options.lock_timeout),
+                body
+            )
+        }
+        None => format!("{}{}", header(body), body),
+    };
+    Ok(sql)
+}
+
+fn run(
+    sql: &str,
+    body: &str,
+    options: &ApplyOptions,
+    database_url: Option<&str>,
+) -> Result<ApplySummary> {
+    if options.snapshot {
+        let path = Path::new(&options.path);
+        let dir = if path.is_dir() { path } else { Path::new(".") };
+        let snapshot = snapshot_schema(dir, database_url)
+            .context("Failed to snapshot the schema before applying")?;
+        log::info!(
+            "Saved the schema before the apply to {}",
+            snapshot.display()
+        );
+    }
+    let started = Instant::now();
+    let notices = execute_sql(
+        sql,
+        database_url,
+        &options.variables,
+        options.progress,
+        options.silent,
+        false,
+        options.keep_temp,
+    )?;
+    let summary = ApplySummary::from_notices(
+        &notices,
+        started.elapsed(),
+        checksum(body),
+        shows_notices(&options.messages),
+    );
+    if options.fake {
+        return Ok(summary);
+    }
+    if let Some(metrics_file) = &options.metrics_file {
+        if let Err(e) = append_metrics(metrics_file, database_url, &summary) {
+            log::warn!("Failed to write the metrics: {:#}", e);
+        }
+    }
+    match run_post_apply_hooks(options, database_url, &summary) {
+        Err(e) if options.post_apply_fatal => {
+            Err(e.context("The changes were applied, but a post-apply hook failed"))
+        }
+        Err(e) => {
+            log::warn!("Post-apply hook failed: {:#}", e);
+            Ok(summary)
+        }
+        Ok(()) => Ok(summary),
+    }
+}
+
+/// The counts of a category in a [`Metrics`] line.
+#[derive(Serialize)]
+struct CategoryMetrics {
+    applied: usize,
+    skipped: usize,
+}
+
+/// A line of the [`ApplyOptions::metrics_file`]. The counts are left out when
+/// the summary could not count the notices.
+#[derive(Serialize)]
+struct Metrics<'a> {
+    timestamp: String,
+    version: &'static str,
+    database: Option<String>,
+    duration_ms: u128,
+    checksum: &'a str,
+    applied: Option<usize>,
+    skipped: Option<usize>,
+    categories: BTreeMap<&'static str, CategoryMetrics>,
+}
+
+/// Appends the [`Metrics`] of an apply to `path` as a JSON line, creating the
+/// file if needed.
+fn append_metrics(path: &Path, database_url: Option<&str>, summary: &ApplySummary) -> Result<()> {
+    let metrics = Metrics {
+        timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        version: env!("CARGO_PKG_VERSION"),
+        database: database_url.map(redact),
+        duration_ms: summary.elapsed.as_millis(),
+        checksum: summary.checksum(),
+        applied: summary.counted().then(|| summary.applied()),
+        skipped: summary.counted().then(|| summary.skipped()),
+        categories: summary
+            .categories
+            .iter()
+            .map(|(category, applied, skipped)| {
+                (
+                    *category,
+                    CategoryMetrics {
+                        applied: *applied,
+                        skipped: *skipped,
+                    },
+                )
+            })
+            .collect(),
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Failed to open '{}'", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(&metrics)?)
+        .context(format!("Failed to write to '{}'", path.display()))
+}
+
+/// The file in the `hooks` directory of a pgm directory that runs after each
+/// successful apply.
+const POST_APPLY_SQL: &str = "post_apply.sql";
+
+/// Runs the `hooks/post_apply.sql` file, in its own transaction after the
+/// apply committed, then the [`ApplyOptions::post_apply`] command.
+fn run_post_apply_hooks(
+    options: &ApplyOptions,
+    database_url: Option<&str>,
+    summary: &ApplySummary,
+) -> Result<()> {
+    let source = source(options);
+    let hook = source.root().join("hooks").join(POST_APPLY_SQL);
+    if source.has_dir("hooks") && source.files("hooks")?.contains(&hook) {
+        log::debug!("Running {}", hook.display());
+        execute_sql(
+            &source.read(&hook)?,
+            database_url,
+            &options.variables,
+            false,
+            options.silent,
+            false,
+            options.keep_temp,
+        )
+        .context(format!("Failed to run '{}'", hook.display()))?;
+    }
+
+    let Some(command_line) = &options.post_apply else {
+        return Ok(());
+    };
+    let mut command = if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.arg("/C");
+        command
     } else {
-        let exit_code = output.status.code().unwrap_or(-1);
-        Err(anyhow::anyhow!(
-            "psql command failed with exit code: {}",
-            exit_code
-        ))
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command
+        .arg(command_line)
+        .env("PGM_APPLIED", summary.applied().to_string())
+        .env("PGM_SKIPPED", summary.skipped().to_string())
+        .env("PGM_COUNTED", summary.counted().to_string())
+        .env("PGM_CHECKSUM", summary.checksum());
+    for (category, applied, _) in &summary.categories {
+        command.env(
+            format!("PGM_APPLIED_{}", category.to_ascii_uppercase()),
+            applied.to_string(),
+        );
+    }
+    log_command(&command);
+    let status = command
+        .status()
+        .context(format!("Failed to run '{}'", command_line))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("'{}' failed with {}", command_line, status));
+    }
+    Ok(())
+}
+
+/// How many objects of each category an apply changed or skipped, counted
+/// from the notices raised by the compiled SQL.
+pub struct ApplySummary {
+    /// `(category, applied, skipped)` in `CATEGORIES` order
+    categories: Vec<(&'static str, usize, usize)>,
+    elapsed: Duration,
+    checksum: String,
+    timings: Vec<(String, Duration)>,
+    /// Whether the notices of the applied and skipped files were shown, and
+    /// so counted
+    counted: bool,
+    profile: Option<Profile>,
+}
+
+impl ApplySummary {
+    /// Number of objects that were applied, which is 0 when they could not be
+    /// [`counted`](Self::counted).
+    pub fn applied(&self) -> usize {
+        self.categories.iter().map(|(_, applied, _)| applied).sum()
+    }
+
+    /// Number of objects that were skipped because they had not changed.
+    pub fn skipped(&self) -> usize {
+        self.categories.iter().map(|(_, _, skipped)| skipped).sum()
+    }
+
+    /// Whether the applied and skipped objects were counted, which needs
+    /// [`ApplyOptions::messages`] to show notices.
+    pub fn counted(&self) -> bool {
+        self.counted
+    }
+
+    /// The [`checksum`] of the SQL that was applied.
+    pub fn checksum(&self) -> &str {
+        &self.checksum
+    }
+
+    /// How long each applied object took to run, slowest first, as
+    /// `(category/name, elapsed)`. Only recorded with [`ApplyOptions::timing`].
+    /// Functions and triggers are run twice, so their two runs are added up.
+    pub fn timings(&self) -> &[(String, Duration)] {
+        &self.timings
+    }
+
+    /// Where the time of the apply went. Only measured by [`apply`] with
+    /// [`ApplyOptions::profile`].
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    fn from_notices(
+        notices: &[String],
+        elapsed: Duration,
+        checksum: String,
+        counted: bool,
+    ) -> Self {
+        let mut categories = CATEGORIES
+            .iter()
+            .map(|category| (*category, 0, 0))
+            .collect::<Vec<_>>();
+        for (category, applied) in notices.iter().filter_map(|notice| parse_notice(notice)) {
+            if let Some(counts) = categories.iter_mut().find(|(c, _, _)| *c == category) {
+                if applied {
+                    counts.1 += 1;
+                } else {
+                    counts.2 += 1;
+                }
+            }
+        }
+        categories.retain(|(_, applied, skipped)| applied + skipped > 0);
+
+        let mut timings = BTreeMap::<String, Duration>::new();
+        for (object, elapsed) in notices.iter().filter_map(|notice| parse_timing(notice)) {
+            *timings.entry(object.to_string()).or_default() += elapsed;
+        }
+        let mut timings = timings.into_iter().collect::<Vec<_>>();
+        timings.sort_by_key(|(_, elapsed)| std::cmp::Reverse(*elapsed));
+
+        Self {
+            categories,
+            elapsed,
+            checksum,
+            timings,
+            counted,
+            profile: None,
+        }
+    }
+}
+
+impl fmt::Display for ApplySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.counted {
+            write!(f, "Applied")?;
+        } else if self.categories.is_empty() {
+            write!(f, "Nothing to apply")?;
+        } else {
+            let counts = self
+                .categories
+                .iter()
+                .map(|(category, applied, skipped)| {
+                    format!("{} of {} {}", applied, applied + skipped, category)
+                })
+                .collect::<Vec<_>>();
+            write!(f, "Applied {}", counts.join(", "))?;
+        }
+        write!(f, " in {:.1}s", self.elapsed.as_secs_f64())?;
+        if self.counted && self.applied() == 0 && !self.categories.is_empty() {
+            write!(f, "; nothing changed")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses an applied/skipped notice raised by the compiled SQL into the
+/// object's category and whether it was applied.
+fn parse_notice(notice: &str) -> Option<(&str, bool)> {
+    // Object notices name the file as '<pgm dir>/<category>/<name>'
+    let category = |path: &str| {
+        let category = path.rsplit('/').nth(1)?;
+        CATEGORIES.iter().find(|c| **c == category).copied()
+    };
+    if let Some(applied) = notice
+        .strip_prefix(applied_marker())
+        .and_then(|rest| rest.strip_prefix(' '))
+    {
+        if applied.starts_with("Applied migration: ")
+            || applied.starts_with("Fake applied migration: ")
+        {
+            return Some(("migrations", true));
+        }
+        if let Some(table) = applied
+            .strip_prefix("Fake applied: pgm_")
+            .and_then(|rest| rest.split_once(' '))
+            .map(|(table, _)| table)
+        {
+            return CATEGORIES
+                .iter()
+                .find(|category| category.trim_end_matches('s') == table)
+                .map(|category| (*category, true));
+        }
+        return category(applied.strip_prefix("Applied ")?).map(|category| (category, true));
     }
+    if notice.starts_with("- Skipped migration: ") {
+        return Some(("migrations", false));
+    }
+    let path = notice
+        .strip_prefix("- Skipped ")?
+        .strip_suffix(" (no changes)")?;
+    category(path).map(|category| (category, false))
+}
+
+/// Parses a timing notice raised by [`timed`] SQL into the object and the
+/// time it took.
+fn parse_timing(notice: &str) -> Option<(&str, Duration)> {
+    let (object, elapsed) = notice
+        .strip_prefix(timing_marker())?
+        .strip_prefix(' ')?
+        .rsplit_once(": ")?;
+    let milliseconds = elapsed.strip_suffix(" ms")?.parse::<f64>().ok()?;
+    Some((object, Duration::from_secs_f64(milliseconds / 1000.0)))
+}
+
+/// Include and exclude globs matched against paths relative to the pgm
+/// directory, e.g. `functions/billing_*.sql`.
+pub(crate) struct FileFilter {
+    base: PathBuf,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    /// Files left out whatever the globs, e.g. duplicate migrations on
+    /// [`ApplyOptions::skip_duplicates`]
+    skipped: Vec<PathBuf>,
+    /// Objects the `disabled.txt` file of the pgm directory leaves out
+    pub(crate) disabled: Disabled,
+    /// The only file compiled, for [`ApplyOptions::file`], without listing
+    /// the others
+    pub(crate) only: Option<PathBuf>,
 }
 
-fn pgm_tables_create_sql() -> String {
-    String::from(
+impl FileFilter {
+    pub(crate) fn new(pgm_dir_path: &Path, include: &[String], exclude: &[String]) -> Result<Self> {
+        let parse = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).context(format!("Invalid glob pattern '{}'", pattern))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(Self {
+            base: pgm_dir_path.to_path_buf(),
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+            skipped: Vec::new(),
+            disabled: Disabled::default(),
+            only: None,
+        })
+    }
+
+    /// Returns whether the file at `path`, inside the pgm directory, should be
+    /// compiled.
+    pub(crate) fn allows(&self, path: &Path) -> bool {
+        if self.skipped.iter().any(|skipped| skipped == path) {
+            return false;
+        }
+        let relative_path = path.strip_prefix(&self.base).unwrap_or(path);
+        let category = relative_path
+            .components()
+            .next()
+            .and_then(|component| component.as_os_str().to_str());
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| sql_name(name, true));
+        if let (Some(category), Some(name)) = (category, name) {
+            if self.disabled.disables(category, name) {
+                log::debug!("Disabled {}", path.display());
+                return false;
+            }
+        }
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let matches = |patterns: &[Pattern]| {
+            patterns
+                .iter()
+                .any(|pattern| pattern.matches_path_with(relative_path, options))
+        };
+        let allowed =
+            (self.include.is_empty() || matches(&self.include)) && !matches(&self.exclude);
+        if !allowed {
+            log::debug!("Filtered out {}", path.display());
+        }
+        allowed
+    }
+}
+
+/// Takes the advisory lock serializing applies, held until the transaction
+/// ends. With a `lock_timeout`, waiting longer for it fails with a message
+/// saying another apply is running, rather than Postgres' lock timeout error.
+pub(crate) fn lock_sql(lock_timeout: Option<Duration>) -> String {
+    let mut sql = String::new();
+    if let Some(lock_timeout) = lock_timeout {
+        sql.push_str(&format!(
+            "SET LOCAL lock_timeout = '{}ms';\n",
+            lock_timeout.as_millis().max(1)
+        ));
+    }
+    sql.push_str(&format!(
+        "BEGIN
+PERFORM pg_advisory_xact_lock({ADVISORY_LOCK_KEY});
+EXCEPTION WHEN lock_not_available THEN
+RAISE EXCEPTION 'Another apply is in progress on this database; gave up waiting for it after %', current_setting('lock_timeout');
+END;
+"
+    ));
+    sql
+}
+
+/// Creates the pgm tables if they are missing, and then sets
+/// `client_min_messages` to `messages`.
+pub(crate) fn pgm_tables_create_sql(messages: &str) -> String {
+    format!(
         r#"
--- Create tables if they don't exist
+-- Create tables if they don't exist, without a notice for each one that does
+SET LOCAL client_min_messages = warning;
+CREATE TABLE IF NOT EXISTS pgm_extension (
+    name TEXT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
 CREATE TABLE IF NOT EXISTS pgm_migration (
     name TEXT PRIMARY KEY,
+    hash TEXT,
     applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
+ALTER TABLE pgm_migration ADD COLUMN IF NOT EXISTS hash TEXT;
 
 CREATE TABLE IF NOT EXISTS pgm_function (
     name TEXT PRIMARY KEY,
     hash TEXT NOT NULL,
+    signatures TEXT[],
     applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
+ALTER TABLE pgm_function ADD COLUMN IF NOT EXISTS signatures TEXT[];
 
 CREATE TABLE IF NOT EXISTS pgm_trigger (
     name TEXT PRIMARY KEY,
     hash TEXT NOT NULL,
+    signatures TEXT[],
     applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
+ALTER TABLE pgm_trigger ADD COLUMN IF NOT EXISTS signatures TEXT[];
 
 CREATE TABLE IF NOT EXISTS pgm_view (
     name TEXT PRIMARY KEY,
     hash TEXT NOT NULL,
     applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
 );
-"#,
+
+CREATE TABLE IF NOT EXISTS pgm_constraint (
+    name TEXT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS pgm_grant (
+    name TEXT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS pgm_comment (
+    name TEXT PRIMARY KEY,
+    hash TEXT NOT NULL,
+    applied_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+SET LOCAL client_min_messages = {messages};
+"#
     )
 }
 
-fn build(pgm_dir_path: &str, minify: bool) -> Result<String> {
+/// The `-- RUN <tag> <file> --` and `-- DONE <tag> <file> --` lines around
+/// each file in compiled SQL. The tag is derived from the contents of every
+/// file compiled, so that no line of a file can be taken for a marker, while
+/// the same files still compile to the same SQL.
+pub(crate) struct Markers(String);
+
+impl Markers {
+    pub(crate) fn new<S: AsRef<str>>(contents: &[S]) -> Self {
+        let mut context = md5::Context::new();
+        for content in contents {
+            context.consume(content.as_ref());
+        }
+        Self(format!("{:x}", context.compute())[..8].to_string())
+    }
+
+    /// Markers for the files of [`read_files`].
+    fn for_files(files: &[SqlFile]) -> Self {
+        Self::new(&files.iter().map(|file| &file.content).collect::<Vec<_>>())
+    }
+
+    /// Puts the markers of `file` around its compiled `sql`.
+    pub(crate) fn wrap(&self, file: &str, sql: &str) -> String {
+        format!(
+            "-- RUN {tag} {file} --\n{sql}-- DONE {tag} {file} --\n",
+            tag = self.0
+        )
+    }
+
+    fn is_marker(&self, line: &str) -> bool {
+        [
+            format!("-- RUN {} ", self.0),
+            format!("-- DONE {} ", self.0),
+        ]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+    }
+}
+
+/// Which comments [`compile`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comments {
+    Keep,
+    /// Only the `-- RUN`/`-- DONE` lines around each file
+    Markers,
+    Strip,
+}
+
+fn compile(
+    source: &Source,
+    comments: Comments,
+    markers: &Markers,
+    filter: &FileFilter,
+    options: &ApplyOptions,
+) -> Result<String> {
     // Check if the postgres directory exists
-    if !Path::new(pgm_dir_path).is_dir() {
+    if !source.exists() {
         return Err(anyhow::anyhow!(
             "Directory '{}' not found. Have you run 'pgm init'?",
-            pgm_dir_path
+            source.root().display()
         ));
     }
 
+    let categories = &options.categories;
+    let (strict, lock_timeout, timing) = (options.strict, options.lock_timeout, options.timing);
+    let includes = |category: &str| categories.iter().any(|c| c == category);
+    let fake_migrations = named_migration_files(source, &options.fake_migrations)?;
+    let rerun_migrations = named_migration_files(source, &options.rerun_migrations)?;
+    if let Some(path) = rerun_migrations
+        .iter()
+        .find(|path| fake_migrations.contains(path))
+    {
+        return Err(anyhow::anyhow!(
+            "Migration '{}' cannot be both faked and re-run",
+            migration_name(path)
+        ));
+    }
+    // The SQL deleting the record of a migration to re-run it, if any, and
+    // the SQL of the migration
+    let migration_sql = |path: &Path| -> Result<(String, String)> {
+        if fake_migrations.iter().any(|fake| fake == path) {
+            return Ok((String::new(), process_migration_fake(source, path)?));
+        }
+        let sql = process_migration(source, path, strict, timing, markers)?;
+        if sql.is_empty() || !rerun_migrations.iter().any(|rerun| rerun == path) {
+            return Ok((String::new(), sql));
+        }
+        Ok((rerun_migration_sql(path), sql))
+    };
+    let is_oversized = |path: &Path| -> Result<bool> {
+        match options.max_migration_size {
+            Some(max_size) => Ok(source.read(path)?.len() as u64 > max_size),
+            None => Ok(false),
+        }
+    };
+    let block_start = |check_function_bodies: bool| {
+        format!(
+            "DO $pgm$ BEGIN\n{}{}SET LOCAL check_function_bodies = {};\nSET LOCAL client_min_messages = {};\n",
+            lock_sql(lock_timeout),
+            set_role_sql(options.role.as_deref(), true),
+            check_function_bodies,
+            options.messages
+        )
+    };
+    let block_end = format!("{}END $pgm$;\n", reset_role_sql(options.role.as_deref()));
+    // The variables are set for the transaction, so each one sets them again
+    let variables = variables_sql(&options.variables, true)?;
+    let mut check_function_bodies = false;
     let mut compiled_content = String::new();
+    // Runs an oversized migration in a transaction of its own, after
+    // committing what comes before it. A custom-format archive is restored
+    // by pg_restore in a transaction of its own, and only recorded in the
+    // next one, once that succeeded.
+    let push_migration =
+        |compiled_content: &mut String, path: &Path, check_function_bodies: bool| -> Result<()> {
+            let (rerun, sql) = migration_sql(path)?;
+            let new_transaction = format!(
+                "{block_end}{TRANSACTION_BREAK}\n{variables}{}",
+                block_start(check_function_bodies)
+            );
+            if is_archive(path) && !fake_migrations.iter().any(|fake| fake == path) {
+                let restore = restore_line(
+                    &migration_name(path),
+                    options.role.as_deref(),
+                    &source.read_archive(path)?,
+                );
+                compiled_content.push_str(&rerun);
+                compiled_content.push_str(&format!(
+                    "{block_end}{TRANSACTION_BREAK}\n{restore}{TRANSACTION_BREAK}\n{variables}{}",
+                    block_start(check_function_bodies)
+                ));
+                compiled_content.push_str(&sql);
+                compiled_content.push_str(&new_transaction);
+                return Ok(());
+            }
+            if sql.is_empty() || !is_oversized(path)? {
+                compiled_content.push_str(&rerun);
+                compiled_content.push_str(&sql);
+                return Ok(());
+            }
+            log::debug!(
+                "Running {} in a transaction of its own, as it is over the maximum size",
+                path.display()
+            );
+            compiled_content.push_str(&new_transaction);
+            compiled_content.push_str(&rerun);
+            compiled_content.push_str(&sql);
+            compiled_content.push_str(&new_transaction);
+            Ok(())
+        };
 
     // Start the main DO block
-    compiled_content.push_str("DO $pgm$ BEGIN\n");
-    compiled_content.push_str("SET LOCAL check_function_bodies = false;\n");
-    compiled_content.push_str("SET LOCAL client_min_messages = notice;\n");
+    compiled_content.push_str(&block_start(check_function_bodies));
 
     // Add schema creation with existence check
-    compiled_content.push_str(&pgm_tables_create_sql());
+    compiled_content.push_str(&pgm_tables_create_sql(&options.messages));
 
-    let functions_dir = format!("{}/functions", pgm_dir_path);
-    let triggers_dir = format!("{}/triggers", pgm_dir_path);
-    let views_dir = format!("{}/views", pgm_dir_path);
-    let migrations_dir = format!("{}/migrations", pgm_dir_path);
+    let (baseline_files, migration_files) =
+        if includes("migrations") && source.has_dir("migrations") {
+            migration_files(source)?
+                .into_iter()
+                .filter(|path| filter.allows(path))
+                .partition(|path| is_baseline(path))
+        } else {
+            (Vec::new(), Vec::new())
+        };
+    let objects = |category: &str, table: &str, update_table_hash: bool| -> Result<String> {
+        if !includes(category) || !source.has_dir(category) {
+            return Ok(String::new());
+        }
+        process_directory(
+            source,
+            category,
+            table,
+            update_table_hash,
+            filter,
+            timing,
+            markers,
+        )
+        .context(format!("Failed to process {}", category))
+    };
 
-    // Process initial migration if it exists
-    let initial_migration_file = Path::new(&migrations_dir).join(INITIAL_MIGRATION_FILE_NAME);
-    if initial_migration_file.exists() {
-        compiled_content.push_str(&process_migration(&initial_migration_file)?);
+    for phase in &options.order {
+        match phase.as_str() {
+            // Extensions come first by default, as any object may use them
+            "extensions" => {
+                compiled_content.push_str(&objects("extensions", "pgm_extension", true)?)
+            }
+            // Baselines, such as the initial schema dump, come before the
+            // other migrations and objects by default
+            "baselines" => {
+                for path in &baseline_files {
+                    log::debug!("Found baseline migration {}", path.display());
+                    push_migration(&mut compiled_content, path, check_function_bodies)?;
+                }
+            }
+            "functions" => compiled_content.push_str(&objects("functions", "pgm_function", false)?),
+            "triggers" => compiled_content.push_str(&objects("triggers", "pgm_trigger", false)?),
+            "migrations" => {
+                for path in &migration_files {
+                    log::debug!("Found migration {}", path.display());
+                    push_migration(&mut compiled_content, path, check_function_bodies)?;
+                }
+            }
+            // Each constraint file drops and re-adds its constraint, so it is
+            // simply re-run when it changes
+            "constraints" => {
+                compiled_content.push_str(&objects("constraints", "pgm_constraint", true)?)
+            }
+            "views" => compiled_content.push_str(&objects("views", "pgm_view", true)?),
+            "checks" => {
+                check_function_bodies = true;
+                compiled_content.push_str("SET LOCAL check_function_bodies = true;\n");
+                compiled_content.push_str(&objects("functions", "pgm_function", true)?);
+                compiled_content.push_str(&objects("triggers", "pgm_trigger", true)?);
+            }
+            // Grants come last by default, once everything they grant on
+            // exists. They cannot be replaced, so a changed file is simply
+            // re-run. Comments follow them for the same reasons, as COMMENT
+            // ON replaces the previous comment.
+            "grants" => {
+                compiled_content.push_str(&objects("grants", "pgm_grant", true)?);
+                compiled_content.push_str(&objects("comments", "pgm_comment", true)?);
+            }
+            phase => unreachable!("Unknown phase '{}'", phase),
+        }
     }
 
-    // Process functions if directory exists
-    if Path::new(&functions_dir).is_dir() {
-        compiled_content.push_str(&process_directory(&functions_dir, "pgm_function", false)?);
-    }
-    // Process triggers if directory exists
-    if Path::new(&triggers_dir).is_dir() {
-        compiled_content.push_str(&process_directory(&triggers_dir, "pgm_trigger", false)?);
-    }
+    // End the main DO block
+    compiled_content.push_str(&block_end);
 
-    // Process migrations if directory exists
-    if Path::new(&migrations_dir).is_dir() {
-        let mut migration_files: Vec<_> = std::fs::read_dir(&migrations_dir)?
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| {
-                entry.path().is_file() && entry.path().extension().map_or(false, |ext| ext == "sql")
-            })
-            // filter out initial migration file
-            .filter(|entry| {
-                entry.path().file_name().expect("Filename must exist")
-                    != INITIAL_MIGRATION_FILE_NAME
-            })
-            .collect();
-        migration_files.sort_by_key(|entry| entry.file_name());
+    Ok(tidy(&compiled_content, comments, markers))
+}
 
-        for file in migration_files {
-            compiled_content
-                .push_str(&process_migration(&file.path()).expect("Failed to process migration"));
-        }
-    }
+/// Removes the empty lines of compiled SQL, and the comments `comments` does
+/// not keep.
+fn tidy(compiled_content: &str, comments: Comments, markers: &Markers) -> String {
+    compiled_content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter(|line| {
+            comments == Comments::Keep
+                || !line.starts_with("--")
+                || *line == TRANSACTION_BREAK
+                || line.starts_with(RESTORE)
+                || comments == Comments::Markers && markers.is_marker(line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    // Process views if directory exists
-    if Path::new(&views_dir).is_dir() {
-        compiled_content.push_str(
-            &process_directory(&views_dir, "pgm_view", true).expect("Failed to process views"),
-        );
+/// Compiles the files as a plain schema script for
+/// [`ApplyOptions::dump_only`], in the order [`compile`] applies them. Each
+/// file runs once, unconditionally: functions are created without checking
+/// their bodies, as in the first pass of `compile`, and nothing is dropped
+/// first, as the script is meant for a database without the objects.
+fn compile_dump(
+    source: &Source,
+    comments: Comments,
+    markers: &Markers,
+    categories: &[String],
+    order: &[String],
+    filter: &FileFilter,
+    role: Option<&str>,
+) -> Result<String> {
+    if !source.exists() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            source.root().display()
+        ));
     }
 
-    // Check function bodies
-    compiled_content.push_str("SET LOCAL check_function_bodies = true;\n");
-    if Path::new(&functions_dir).is_dir() {
-        compiled_content.push_str(
-            &process_directory(&functions_dir, "pgm_function", true)
-                .expect("Failed to process functions"),
-        );
-    }
-    if Path::new(&triggers_dir).is_dir() {
-        compiled_content.push_str(
-            &process_directory(&triggers_dir, "pgm_trigger", true)
-                .expect("Failed to process triggers"),
-        );
-    }
+    let includes = |category: &str| categories.iter().any(|c| c == category);
+    let files = |category: &str| -> Result<Vec<PathBuf>> {
+        if !includes(category) || !source.has_dir(category) {
+            return Ok(Vec::new());
+        }
+        let mut files = if category == "migrations" {
+            migration_files(source)?
+        } else {
+            object_files(source, category)?
+        };
+        files.retain(|path| filter.allows(path));
+        Ok(files)
+    };
+    let dump_file = |path: &Path, content: &str, sql: &str| {
+        let sql = match Metadata::parse(content).schema {
+            Some(schema) => format!(
+                "SELECT set_config('pgm.search_path', current_setting('search_path'), false);
+SELECT set_config('search_path', quote_ident('{schema}') || ', ' || current_setting('search_path'), false);
+{sql}
+SELECT set_config('search_path', current_setting('pgm.search_path'), false);
+",
+                schema = schema.replace('\'', "''")
+            ),
+            None => format!("{sql}\n"),
+        };
+        markers.wrap(&path.display().to_string(), &sql)
+    };
 
-    // End the main DO block
-    compiled_content.push_str("END $pgm$;\n");
+    let mut compiled_content = String::new();
+    compiled_content.push_str(&set_role_sql(role, false));
+    compiled_content.push_str("SET check_function_bodies = false;\n");
 
-    // Remove empty lines
-    compiled_content = compiled_content
-        .lines()
-        .filter(|line| !line.is_empty())
-        .collect::<Vec<_>>()
-        .join("\n");
+    let (baseline_files, migration_files) = files("migrations")?
+        .into_iter()
+        .partition::<Vec<_>, _>(|path| is_baseline(path));
+    let dump_migrations = |compiled_content: &mut String, paths: &[PathBuf]| -> Result<()> {
+        for path in paths {
+            let mut content = if is_archive(path) {
+                restore_custom_format_dump(path, &source.read_archive(path)?)?
+            } else {
+                source.read(path)?
+            };
+            if Metadata::parse(&content).interpolate {
+                content = interpolate(path, &content)?;
+            }
+            if !is_empty_migration(&content) {
+                compiled_content.push_str(&dump_file(path, &content, &content));
+            }
+        }
+        Ok(())
+    };
 
-    // Remove comments
-    if minify {
-        compiled_content = compiled_content
-            .lines()
-            .filter(|line| !line.starts_with("--"))
-            .collect::<Vec<_>>()
-            .join("\n");
+    let mut bindings = Vec::new();
+    for phase in order {
+        match phase.as_str() {
+            "extensions" => {
+                for path in files("extensions")? {
+                    let content = source.read(&path)?;
+                    let metadata = Metadata::parse(&content);
+                    let sql = format!(
+                        "{}{}",
+                        create_extension_sql(object_name(&path), &metadata),
+                        content
+                    );
+                    compiled_content.push_str(&dump_file(&path, &content, &sql));
+                }
+            }
+            "baselines" => dump_migrations(&mut compiled_content, &baseline_files)?,
+            "migrations" => dump_migrations(&mut compiled_content, &migration_files)?,
+            // Trigger files bind their functions in `checks`, once the
+            // migrations created the tables, as in the second pass of
+            // `compile`
+            "triggers" => {
+                for path in files("triggers")? {
+                    let content = source.read(&path)?;
+                    let (functions, file_bindings) = split_bindings(&content);
+                    compiled_content.push_str(&dump_file(&path, &content, &functions));
+                    bindings.push((path, content, file_bindings));
+                }
+            }
+            "checks" => {
+                for (path, content, file_bindings) in bindings.drain(..) {
+                    if !file_bindings.is_empty() {
+                        let sql = file_bindings
+                            .iter()
+                            .map(|binding| binding.statement.trim_end().to_string())
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        compiled_content.push_str(&dump_file(&path, &content, &sql));
+                    }
+                }
+            }
+            category => {
+                // Comments are applied with the grants, as in `compile`
+                let categories = if category == "grants" {
+                    &["grants", "comments"][..]
+                } else {
+                    &[category][..]
+                };
+                for category in categories {
+                    for path in files(category)? {
+                        let content = source.read(&path)?;
+                        compiled_content.push_str(&dump_file(&path, &content, &content));
+                    }
+                }
+            }
+        }
     }
 
-    Ok(compiled_content)
+    compiled_content.push_str("RESET check_function_bodies;\n");
+    compiled_content.push_str(&reset_role_sql(role));
+    Ok(tidy(&compiled_content, comments, markers))
 }
 
-fn process_directory(full_dir_path: &str, table: &str, update_table_hash: bool) -> Result<String> {
+fn process_directory(
+    source: &Source,
+    category: &str,
+    table: &str,
+    update_table_hash: bool,
+    filter: &FileFilter,
+    timing: bool,
+    markers: &Markers,
+) -> Result<String> {
     let mut compiled_content = String::new();
-    for entry in std::fs::read_dir(full_dir_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "sql") {
-            let content = std::fs::read_to_string(&path)?;
+    let files = match &filter.only {
+        Some(path) => vec![path.clone()],
+        None => object_files(source, category)?,
+    };
+    for path in files {
+        if filter.allows(&path) {
+            let content = source.read(&path)?;
 
             let hash = format!("{:x}", md5::compute(&content));
-            let file_name = path.file_stem().unwrap().to_str().unwrap();
+            let file_name = object_name(&path);
 
-            let file_path = format!("{}/{}", full_dir_path, file_name);
+            let file_path = format!("{}/{}/{}", source.root().display(), category, file_name);
+            log::debug!(
+                "Found {} with hash {}, applied if it differs from {}",
+                path.display(),
+                hash,
+                table
+            );
 
+            // Trigger files bind their function with CREATE TRIGGER, which
+            // waits for the second pass, once the migrations created the
+            // tables
+            let mut object_content = if category == "triggers" {
+                let (mut functions, bindings) = split_bindings(&content);
+                if update_table_hash {
+                    functions.extend(bindings.iter().map(Binding::replace_sql));
+                }
+                functions
+            } else if category == "extensions" {
+                format!("{}{}", extension_sql(file_name, &content), content)
+            } else {
+                content.clone()
+            };
+            // Functions are dropped in the first pass only, as the second
+            // comes after the views that may depend on them
+            let metadata = Metadata::parse(&content);
+            if let Some(mode) = metadata.drop_first {
+                if category == "views" || !update_table_hash {
+                    object_content = format!(
+                        "{}{object_content}",
+                        drop_first_sql(category, table, file_name, mode)
+                    );
+                }
+            }
+            if category == "views" && metadata.recreate && metadata.drop_first.is_none() {
+                object_content = recreate_view_sql(file_name, &object_content);
+            }
+            if update_table_hash && metadata.no_body_check {
+                object_content = format!(
+                    "SET LOCAL check_function_bodies = false;\n{}\nSET LOCAL check_function_bodies = true;\n",
+                    object_content.trim_end()
+                );
+            }
+            let timed_content = timed(&object_content, &format!("{category}/{file_name}"), timing);
             let update_hash_query = if update_table_hash {
+                let record = record_sql(table, file_name, &hash);
                 format!(
                     "
-    INSERT INTO {table} (name, hash) VALUES ('{file_name}', '{hash}') ON CONFLICT (name) DO UPDATE SET hash = EXCLUDED.hash, applied_at = CURRENT_TIMESTAMP;
-    RAISE NOTICE '✅ Applied {file_path}';
+    {record}
+    RAISE NOTICE '{applied} Applied {file_path}';
 ELSE
-    RAISE NOTICE '- Skipped {file_path} (no changes)';"
+    RAISE NOTICE '- Skipped {file_path} (no changes)';",
+                    applied = applied_marker()
                 )
             } else {
                 String::new()
             };
 
-            compiled_content.push_str(&format!(
-                "-- RUN {file_path} --
-IF (SELECT hash FROM {table} WHERE name = '{file_name}') IS DISTINCT FROM '{hash}' THEN
-{content}
+            let sql = format!(
+                "IF (SELECT hash FROM {table} WHERE name = '{file_name}') IS DISTINCT FROM '{hash}' THEN
+{timed_content}
 {update_hash_query}
 END IF;
--- DONE {file_path} --
 "
-            ));
+            );
+            compiled_content
+                .push_str(&markers.wrap(&file_path, &with_metadata(&path, &content, sql)));
         }
     }
     Ok(compiled_content)
 }
 
-fn process_migration(path: &Path) -> Result<String> {
-    let mut compiled_content = String::new();
+/// Fails if two files in a category resolve to the same name, e.g.
+/// `foo.sql` and `foo.SQL` or a migration both plain and compressed, since
+/// they would be tracked as one object. With `skip_migrations`, duplicate
+/// migrations are instead warned about and all but the first, in path order,
+/// returned to be skipped. Warns about functions and triggers sharing a name,
+/// as both end up in `pg_proc`.
+pub(crate) fn check_duplicate_names(
+    source: &Source,
+    categories: &[String],
+    skip_migrations: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut duplicates = Vec::new();
+    let mut skipped = Vec::new();
+    for category in categories {
+        if !source.has_dir(category) {
+            continue;
+        }
+        let mut dirs = vec![(category.to_string(), String::new())];
+        if category == "migrations" && migration_releases() {
+            for release in source.dirs(category)? {
+                dirs.push((format!("{}/{}", category, release), format!("{}/", release)));
+            }
+        }
+        let mut names = BTreeMap::<String, Vec<PathBuf>>::new();
+        for (dir, prefix) in dirs {
+            for path in source.files(&dir)? {
+                let file_name = path.file_name().unwrap().to_string_lossy().to_lowercase();
+                if let Some(name) = sql_name(&file_name, category == "migrations") {
+                    names
+                        .entry(format!("{}{}", prefix, name))
+                        .or_default()
+                        .push(path);
+                }
+            }
+        }
+        for (name, mut paths) in names {
+            if paths.len() > 1 && skip_migrations && category == "migrations" {
+                paths.sort();
+                for path in &paths[1..] {
+                    log::warn!(
+                        "Skipping {}, as migration '{}' is already {}",
+                        path.display(),
+                        name,
+                        paths[0].display()
+                    );
+                }
+                skipped.extend(paths.drain(1..));
+            } else if paths.len() > 1 {
+                paths.sort();
+                let paths = paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                duplicates.push(format!("{}/{}: {}", category, name, paths.join(", ")));
+            }
+        }
+    }
+    if !duplicates.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Several files resolve to the same name, rename or remove all but one of them:\n    {}",
+            duplicates.join("\n    ")
+        ));
+    }
+
+    if ["functions", "triggers"]
+        .iter()
+        .all(|category| categories.iter().any(|c| c == category))
+    {
+        let functions = category_files(source, "functions")?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<HashSet<_>>();
+        for (name, path) in category_files(source, "triggers")? {
+            if functions.contains(&name) {
+                log::warn!(
+                    "Function and trigger '{}' share a name, so one may replace the other ({})",
+                    name,
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(skipped)
+}
 
-    let content = std::fs::read_to_string(path)?;
+/// With `timing`, wraps the SQL of `object` to raise a notice with the time it
+/// took to run, e.g. `⏱ views/v: 12.3 ms`.
+fn timed(content: &str, object: &str, timing: bool) -> String {
+    if !timing {
+        return content.to_string();
+    }
+    format!(
+        "PERFORM set_config('pgm.started', clock_timestamp()::text, true);
+{content}
+RAISE NOTICE '{marker} {object}: % ms', round((extract(epoch FROM clock_timestamp() - current_setting('pgm.started')::timestamptz) * 1000)::numeric, 1);",
+        marker = timing_marker()
+    )
+}
 
-    let file_name = path.file_stem().unwrap().to_str().unwrap();
-    let path_with_extension = path
+/// Warns about `-- pgm:` metadata that is not understood, and fails on files
+/// asking for `no-transaction`, since everything is applied in a single
+/// transaction.
+fn check_metadata(files: &[SqlFile]) -> Result<()> {
+    for SqlFile {
+        category,
+        path,
+        content,
+    } in files
+    {
+        let metadata = Metadata::parse(content);
+        for entry in metadata.unknown {
+            log::warn!(
+                "{}: ignoring unknown pgm metadata '{}'",
+                path.display(),
+                entry
+            );
+        }
+        if metadata.no_body_check && !["functions", "triggers"].contains(&category.as_str()) {
+            log::warn!(
+                "{}: 'no-body-check' only applies to functions and triggers",
+                path.display()
+            );
+        }
+        if metadata.recreate && category != "views" {
+            log::warn!("{}: 'recreate' only applies to views", path.display());
+        }
+        if metadata.recreate && metadata.drop_first.is_some() {
+            log::warn!(
+                "{}: 'drop-first' always drops the view, so 'recreate' is ignored",
+                path.display()
+            );
+        }
+        if metadata.drop_first.is_some()
+            && !["functions", "triggers", "views"].contains(&category.as_str())
+        {
+            log::warn!(
+                "{}: 'drop-first' only applies to functions, triggers and views",
+                path.display()
+            );
+        }
+        if metadata.interpolate && category != "migrations" {
+            log::warn!(
+                "{}: 'interpolate' only applies to migrations",
+                path.display()
+            );
+        }
+        if metadata.version.is_some() && category != "extensions" {
+            log::warn!("{}: 'version' only applies to extensions", path.display());
+        }
+        if metadata.no_transaction {
+            return Err(anyhow::anyhow!(
+                "{}: 'no-transaction' is not supported, since pgm applies everything in a single transaction",
+                path.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the extension named `name` as set in the metadata of `content`: in
+/// its `schema` if any, and at its `version`, updating an installed extension
+/// to it.
+fn extension_sql(name: &str, content: &str) -> String {
+    let metadata = Metadata::parse(content);
+    let sql = create_extension_sql(name, &metadata);
+    let Some(version) = metadata.version else {
+        return sql;
+    };
+    format!(
+        "{sql}IF (SELECT extversion FROM pg_extension WHERE extname = '{}') IS DISTINCT FROM '{version}' THEN
+ALTER EXTENSION \"{}\" UPDATE TO '{version}';
+END IF;
+",
+        name.replace('\'', "''"),
+        name.replace('"', "\"\""),
+        version = version.replace('\'', "''")
+    )
+}
+
+/// Creates the extension named `name` if it is missing, in the `schema` and at
+/// the `version` of `metadata` if they are set.
+fn create_extension_sql(name: &str, metadata: &Metadata) -> String {
+    let mut sql = format!(
+        "CREATE EXTENSION IF NOT EXISTS \"{}\"",
+        name.replace('"', "\"\"")
+    );
+    if let Some(schema) = &metadata.schema {
+        sql.push_str(&format!(" WITH SCHEMA \"{}\"", schema.replace('"', "\"\"")));
+    }
+    if let Some(version) = &metadata.version {
+        sql.push_str(&format!(" VERSION '{}'", version.replace('\'', "''")));
+    }
+    format!("{sql};\n")
+}
+
+/// Records an object as applied with `hash` in `table`, along with the
+/// signatures of the functions it created for functions and triggers, so
+/// they can later be dropped exactly.
+fn record_sql(table: &str, name: &str, hash: &str) -> String {
+    if table == "pgm_function" || table == "pgm_trigger" {
+        format!(
+            "INSERT INTO {table} (name, hash, signatures) VALUES ('{name}', '{hash}', {}) ON CONFLICT (name) DO UPDATE SET hash = EXCLUDED.hash, signatures = EXCLUDED.signatures, applied_at = CURRENT_TIMESTAMP;",
+            signatures_sql(name)
+        )
+    } else {
+        format!(
+            "INSERT INTO {table} (name, hash) VALUES ('{name}', '{hash}') ON CONFLICT (name) DO UPDATE SET hash = EXCLUDED.hash, applied_at = CURRENT_TIMESTAMP;"
+        )
+    }
+}
+
+/// Returns the SQL files of `category`, sorted by name so the same files
+/// always compile to the same SQL, except that files come after those they
+/// declare with `-- pgm: depends=[...]`.
+fn object_files(source: &Source, category: &str) -> Result<Vec<PathBuf>> {
+    let mut files = source.files(category)?;
+    files.retain(|path| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| sql_name(name, false))
+            .is_some()
+    });
+    files.sort();
+    let files = files
+        .into_iter()
+        .map(|path| {
+            let name = object_name(&path).to_string();
+            let metadata = Metadata::parse(&source.read(&path)?);
+            Ok((name, path, metadata))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    order_by_dependencies(files).context(format!("Failed to order the {}", category))
+}
+
+/// Wraps `sql`, compiled for the file at `path`, to run as its metadata asks:
+/// with its `schema` first in the search path, so unqualified objects are
+/// created there while the pgm tables are still found.
+fn with_metadata(path: &Path, content: &str, sql: String) -> String {
+    let Some(schema) = Metadata::parse(content).schema else {
+        return sql;
+    };
+    let schema = schema.replace('\'', "''");
+    format!(
+        "IF NOT EXISTS (SELECT 1 FROM pg_namespace WHERE nspname = '{schema}') THEN
+RAISE EXCEPTION 'Schema {schema} of {path} does not exist';
+END IF;
+PERFORM set_config('pgm.search_path', current_setting('search_path'), true);
+PERFORM set_config('search_path', quote_ident('{schema}') || ', ' || current_setting('search_path'), true);
+{sql}PERFORM set_config('search_path', current_setting('pgm.search_path'), true);
+",
+        path = path.display()
+    )
+}
+
+/// Returns the migration files, in the order they are applied: baselines
+/// first, then the others by file name, then, with [`migration_releases`],
+/// those of each release directory, by the name of the directory and then
+/// by file name.
+fn migration_files(source: &Source) -> Result<Vec<PathBuf>> {
+    let mut files = source.files("migrations")?;
+    if migration_releases() {
+        for release in source.dirs("migrations")? {
+            files.extend(source.files(&format!("migrations/{}", release))?);
+        }
+    }
+    files.retain(|path| is_migration_file(path));
+    files.sort_by(|a, b| {
+        (!is_baseline(a), migration_release(a), a.file_name()).cmp(&(
+            !is_baseline(b),
+            migration_release(b),
+            b.file_name(),
+        ))
+    });
+    Ok(files)
+}
+
+/// Whether migrations are also read from the directories in `migrations/`,
+/// one per release, e.g. `migrations/v2/00001.sql`, as set with
+/// `PGM_MIGRATION_RELEASES=1`. Otherwise such directories are left alone.
+pub(crate) fn migration_releases() -> bool {
+    std::env::var("PGM_MIGRATION_RELEASES")
+        .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+}
+
+/// Returns the release directory the migration at `path` is in, e.g. `v2`
+/// for `migrations/v2/00001.sql`, or `None` for one directly in `migrations/`.
+fn migration_release(path: &Path) -> Option<&str> {
+    let parent = path.parent()?.file_name()?.to_str()?;
+    (parent != "migrations").then_some(parent)
+}
+
+/// Returns the number a migration file name starts with, e.g. 12 for
+/// `00012_add_users.sql` or `v2/00012_add_users`.
+pub(crate) fn migration_number(file_name: &str) -> Option<u32> {
+    let file_name = file_name.rsplit('/').next().unwrap_or(file_name);
+    let digits = file_name
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(file_name.len());
+    file_name[..digits].parse().ok()
+}
+
+/// Returns the highest number of the migrations, including those replaced by
+/// a baseline written by `squash`, so new migrations are numbered after them.
+/// Each release directory is numbered on its own, so with a `release` only
+/// its migrations count.
+pub(crate) fn last_migration_number(source: &Source, release: Option<&str>) -> Result<Option<u32>> {
+    let mut numbers = Vec::new();
+    for (name, path) in category_files(source, "migrations")? {
+        if migration_release(&path) != release {
+            continue;
+        }
+        numbers.extend(migration_number(&name));
+        if is_baseline(&path) {
+            let squashes = Metadata::parse(&source.read(&path)?).squashes;
+            numbers.extend(squashes.iter().filter_map(|name| migration_number(name)));
+        }
+    }
+    Ok(numbers.into_iter().max())
+}
+
+/// Fails, with [`ApplyOptions::strict_order`], listing the gaps and
+/// duplicates in the numbers of the migrations, which usually come from a
+/// merge mistake. The numbers must run from 1 up, counting those replaced by
+/// a baseline written by `squash`; baselines themselves are numbered 0. The
+/// numbers of each release directory run from 1 up on their own.
+fn check_migration_order(source: &Source) -> Result<()> {
+    let mut releases = BTreeMap::<Option<String>, BTreeMap<u32, Vec<String>>>::new();
+    let mut unnumbered = Vec::new();
+    for (name, path) in category_files(source, "migrations")? {
+        let numbers = releases
+            .entry(migration_release(&path).map(str::to_string))
+            .or_default();
+        match migration_number(&name) {
+            Some(0) => {
+                let squashes = Metadata::parse(&source.read(&path)?).squashes;
+                for squashed in squashes {
+                    if let Some(number) = migration_number(&squashed) {
+                        numbers.entry(number).or_default().push(squashed);
+                    }
+                }
+            }
+            Some(number) => numbers.entry(number).or_default().push(name),
+            None => unnumbered.push(name),
+        }
+    }
+
+    let mut problems = Vec::new();
+    if !unnumbered.is_empty() {
+        problems.push(format!("not numbered: {}", unnumbered.join(", ")));
+    }
+    for (release, numbers) in &releases {
+        let prefix = release
+            .as_ref()
+            .map_or(String::new(), |release| format!("{}/", release));
+        let last = numbers.keys().next_back().copied().unwrap_or(0);
+        let missing = (1..last)
+            .filter(|number| !numbers.contains_key(number))
+            .map(|number| format!("{}{:05}", prefix, number))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            problems.push(format!("missing: {}", missing.join(", ")));
+        }
+        for (number, names) in numbers {
+            if names.len() > 1 {
+                problems.push(format!(
+                    "{}{:05} used by: {}",
+                    prefix,
+                    number,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The migration numbers in '{}' are not contiguous:\n    {}",
+            source.root().join("migrations").display(),
+            problems.join("\n    ")
+        ));
+    }
+    Ok(())
+}
+
+/// Returns whether a migration is a baseline, i.e. numbered 0 like the
+/// `00000.sql` schema dump written by `pgm init`, and not in a release
+/// directory. Baselines are applied before functions, triggers and the other
+/// migrations.
+fn is_baseline(path: &Path) -> bool {
+    migration_release(path).is_none() && migration_number(&migration_name(path)) == Some(0)
+}
+
+/// Returns the files of `category` in the pgm directory, in the order `build`
+/// compiles them, with the names they are tracked under. A missing directory
+/// has no files.
+pub(crate) fn category_files(source: &Source, category: &str) -> Result<Vec<(String, PathBuf)>> {
+    if !source.has_dir(category) {
+        return Ok(Vec::new());
+    }
+    if category == "migrations" {
+        Ok(migration_files(source)?
+            .into_iter()
+            .map(|path| (migration_name(&path), path))
+            .collect())
+    } else {
+        Ok(object_files(source, category)?
+            .into_iter()
+            .map(|path| (object_name(&path).to_string(), path))
+            .collect())
+    }
+}
+
+/// Returns whether `path` is a migration file, either plain `.sql`,
+/// gzip-compressed `.sql.gz` or a custom-format `.dump`, or with another of
+/// the [`sql_extensions`].
+fn is_migration_file(path: &Path) -> bool {
+    let file_name = path.file_name().and_then(|name| name.to_str());
+    file_name.is_some_and(|name| sql_name(name, true).is_some())
+}
+
+/// Returns the name a migration is tracked under in `pgm_migration`, which is
+/// its file name without the `.sql`, `.sql.gz` or `.dump` extension, after
+/// its release directory if it is in one, e.g. `v2/00001`.
+fn migration_name(path: &Path) -> String {
+    let file_name = migration_file_name(path);
+    let name = sql_name(&file_name, true).expect("Should be a migration file");
+    name.to_string()
+}
+
+/// Returns the file name of the migration at `path`, after its release
+/// directory if it is in one, e.g. `v2/00001.sql`.
+fn migration_file_name(path: &Path) -> String {
+    let file_name = path
         .file_name()
         .expect("File name should exist")
         .to_str()
         .expect("Should be a string");
+    match migration_release(path) {
+        Some(release) => format!("{}/{}", release, file_name),
+        None => file_name.to_string(),
+    }
+}
 
-    compiled_content.push_str(&format!(
-        "-- RUN {path_with_extension} --
-IF NOT EXISTS (SELECT 1 FROM pgm_migration WHERE name = '{file_name}') THEN
-{content}
-INSERT INTO pgm_migration (name) VALUES ('{file_name}');
-RAISE NOTICE '✅ Applied migration: {file_name}';
+/// Returns the name the object file at `path` is tracked under, which is its
+/// file name without the `.sql` extension.
+fn object_name(path: &Path) -> &str {
+    let file_name = path
+        .file_name()
+        .expect("File name should exist")
+        .to_str()
+        .expect("Should be a string");
+    sql_name(file_name, false).expect("Should be a SQL file")
+}
+
+/// Removes top-level `BEGIN`/`START TRANSACTION`/`COMMIT` statements from a
+/// migration with a warning, since every migration already runs inside the
+/// transaction of the compiled DO block. `ROLLBACK` cannot be honored that
+/// way and is an error. Lines starting inside a dollar-quoted body, a string
+/// or a block comment are left alone.
+fn strip_transaction_control(path: &Path, content: &str) -> Result<String> {
+    let mut stripped = String::with_capacity(content.len());
+    let mut open: Option<Quote> = None;
+
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let top_level = open.is_none();
+        open = track_quotes(line, open);
+
+        let statement = line.trim();
+        let words = statement
+            .strip_suffix(';')
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|word| word.to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+        let is_transaction_control = match words.as_slice() {
+            ["begin"] | ["commit", ..] | ["start", "transaction", ..] => true,
+            ["begin", next, ..] => [
+                "transaction",
+                "work",
+                "isolation",
+                "read",
+                "deferrable",
+                "not",
+            ]
+            .contains(next),
+            ["rollback", ..] | ["abort", ..] if top_level => {
+                return Err(anyhow::anyhow!(
+                    "{}:{}: '{}' is not supported in migrations, since pgm applies everything in a single transaction",
+                    path.display(),
+                    index + 1,
+                    statement
+                ));
+            }
+            _ => false,
+        };
+
+        if top_level && is_transaction_control {
+            log::warn!(
+                "{}:{}: removed '{}', since pgm already applies migrations in a transaction",
+                path.display(),
+                index + 1,
+                statement
+            );
+            if line.ends_with('\n') {
+                stripped.push('\n');
+            }
+        } else {
+            stripped.push_str(line);
+        }
+    }
+    Ok(stripped)
+}
+
+/// Returns the table DDL at the top level of `content`, outside dollar-quoted
+/// bodies, that must only run once, such as a `CREATE TABLE` without
+/// `IF NOT EXISTS`, as `path:line: 'STATEMENT'`.
+pub(crate) fn once_only_ddl(path: &Path, content: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut dollar_quote: Option<String> = None;
+    for (index, line) in content.lines().enumerate() {
+        let top_level = dollar_quote.is_none();
+        dollar_quote = track_dollar_quote(line, dollar_quote);
+        if !top_level {
+            continue;
+        }
+        let words = line
+            .split_whitespace()
+            .take(5)
+            .map(|word| word.trim_end_matches(';').to_ascii_uppercase())
+            .collect::<Vec<_>>();
+        let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+        let statement = match words.as_slice() {
+            ["CREATE", "TABLE", "IF", "NOT", "EXISTS"] => continue,
+            ["CREATE", "TABLE", ..] => "CREATE TABLE",
+            ["ALTER", "TABLE", ..] => "ALTER TABLE",
+            ["DROP", "TABLE", ..] => "DROP TABLE",
+            _ => continue,
+        };
+        found.push(format!("{}:{}: '{}'", path.display(), index + 1, statement));
+    }
+    found
+}
+
+/// Warns about the [`once_only_ddl`] `found` in files that run again `when`,
+/// or with `strict` fails listing them.
+pub(crate) fn report_once_only_ddl(found: &[String], when: &str, strict: bool) -> Result<()> {
+    if found.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        return Err(anyhow::anyhow!(
+            "Table DDL that must only run once would run again {}; move it to a migration:\n    {}",
+            when,
+            found.join("\n    ")
+        ));
+    }
+    for statement in found {
+        log::warn!("{} runs again {}; move it to a migration", statement, when);
+    }
+    Ok(())
+}
+
+/// Checks the functions, views and triggers among `files` for
+/// [`once_only_ddl`], which would run again whenever their file changes.
+fn check_once_only_ddl(files: &[SqlFile], strict: bool) -> Result<()> {
+    let mut found = Vec::new();
+    for file in files {
+        if ["functions", "views", "triggers"].contains(&file.category.as_str()) {
+            found.extend(once_only_ddl(&file.path, &file.content));
+        }
+    }
+    report_once_only_ddl(&found, "whenever the file changes", strict)
+}
+
+/// Fails on the first object file among `files` that `filter` allows and
+/// that is [`unbalanced`], as it was likely cut short. Migrations are left
+/// alone, as the applied ones cannot be fixed anyway.
+fn check_balance(files: &[SqlFile], filter: &FileFilter) -> Result<()> {
+    let files = files
+        .iter()
+        .filter(|file| file.category != "migrations" && filter.allows(&file.path));
+    for file in files {
+        if let Some(problem) = unbalanced(&file.content) {
+            return Err(anyhow::anyhow!(
+                "In '{}', {}; the file may be truncated or malformed. Pass --no-balance-check if it is not",
+                file.path.display(),
+                problem
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// What a line of SQL can leave open for the next, for [`track_quotes`].
+#[derive(Debug, Clone, PartialEq)]
+enum Quote {
+    /// A dollar-quoted string, with its tag, e.g. `$body$`
+    Dollar(String),
+    /// A string in single quotes; an `E'...'` string also takes backslash
+    /// escapes
+    String { escapes: bool },
+    /// An identifier in double quotes
+    Identifier,
+    /// A `/* ... */` comment, nested this deep
+    Comment(usize),
+}
+
+/// Returns what is still open at the end of `line`, given what was open at
+/// its start: a dollar quote as [`track_dollar_quote`] tracks it, a string,
+/// with `''` and, in `E'...'`, `\'` escaping a quote, a quoted identifier or
+/// a block comment. Text after `--` outside of these is ignored.
+fn track_quotes(line: &str, mut open: Option<Quote>) -> Option<Quote> {
+    let bytes = line.as_bytes();
+    let is_word = |byte: u8| byte.is_ascii_alphanumeric() || byte == b'_';
+    let mut i = 0;
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        match &open {
+            None => match bytes[i] {
+                b'-' if next == Some(b'-') => return None,
+                b'/' if next == Some(b'*') => {
+                    open = Some(Quote::Comment(1));
+                    i += 1;
+                }
+                b'\'' => {
+                    let escapes = i > 0
+                        && bytes[i - 1].eq_ignore_ascii_case(&b'e')
+                        && (i < 2 || !is_word(bytes[i - 2]));
+                    open = Some(Quote::String { escapes });
+                }
+                b'"' => open = Some(Quote::Identifier),
+                b'$' if i == 0 || !is_word(bytes[i - 1]) => {
+                    let after = &line[i + 1..];
+                    let len = after
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(after.len());
+                    if after[len..].starts_with('$')
+                        && !after.starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        open = Some(Quote::Dollar(line[i..i + len + 2].to_string()));
+                        i += len + 1;
+                    }
+                }
+                _ => {}
+            },
+            Some(Quote::Dollar(tag)) => {
+                if bytes[i..].starts_with(tag.as_bytes()) {
+                    i += tag.len() - 1;
+                    open = None;
+                }
+            }
+            Some(Quote::String { escapes }) => match bytes[i] {
+                b'\\' if *escapes => i += 1,
+                b'\'' if next == Some(b'\'') => i += 1,
+                b'\'' => open = None,
+                _ => {}
+            },
+            Some(Quote::Identifier) => match bytes[i] {
+                b'"' if next == Some(b'"') => i += 1,
+                b'"' => open = None,
+                _ => {}
+            },
+            Some(Quote::Comment(depth)) => match (bytes[i], next) {
+                (b'*', Some(b'/')) => {
+                    open = depth
+                        .checked_sub(1)
+                        .filter(|depth| *depth > 0)
+                        .map(Quote::Comment);
+                    i += 1;
+                }
+                (b'/', Some(b'*')) => {
+                    open = Some(Quote::Comment(depth + 1));
+                    i += 1;
+                }
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+    open
+}
+
+/// Returns the dollar quote (`$$`, `$body$`, ...) still open at the end of
+/// `line`, given the one open at its start. Text after `--` outside a dollar
+/// quote is ignored.
+pub(crate) fn track_dollar_quote(line: &str, mut open: Option<String>) -> Option<String> {
+    let mut rest = line;
+    loop {
+        if open.is_none() {
+            let comment = rest.find("--").unwrap_or(rest.len());
+            rest = &rest[..comment];
+        }
+        let Some(start) = rest.find('$') else {
+            return open;
+        };
+        let after = &rest[start + 1..];
+        let Some(len) = after.find(|c: char| !(c.is_alphanumeric() || c == '_')) else {
+            return open;
+        };
+        if !after[len..].starts_with('$') || after.starts_with(|c: char| c.is_ascii_digit()) {
+            // A positional parameter such as $1, or a lone '$'
+            rest = after;
+            continue;
+        }
+        let tag = &rest[start..start + len + 2];
+        match &open {
+            Some(open_tag) if open_tag == tag => open = None,
+            Some(_) => {}
+            None => open = Some(tag.to_string()),
+        }
+        rest = &rest[start + tag.len()..];
+    }
+}
+
+/// Whether a migration has nothing but whitespace and comments. Recording such
+/// a migration as applied would skip it for good once it is filled in.
+pub(crate) fn is_empty_migration(content: &str) -> bool {
+    content
+        .lines()
+        .map(str::trim)
+        .all(|line| line.is_empty() || line.starts_with("--"))
+}
+
+fn process_migration(
+    source: &Source,
+    path: &Path,
+    strict: bool,
+    timing: bool,
+    markers: &Markers,
+) -> Result<String> {
+    let mut compiled_content = String::new();
+
+    // A custom-format archive reads as empty, as the part before this one
+    // restores it
+    let archive = is_archive(path);
+    let content = source.read(path)?;
+    let hash = source.hash(path)?;
+    // Migrations applied before hashes were recorded, or recorded with CRLF
+    // line endings or a byte order mark before those were normalized, adopt
+    // the current hash
+    let adopted_hashes = if archive {
+        "hash IS NULL".to_string()
+    } else {
+        let crlf = content.replace('\n', "\r\n");
+        let legacy_hashes = [
+            crlf.clone(),
+            format!("\u{feff}{content}"),
+            format!("\u{feff}{crlf}"),
+        ]
+        .map(|legacy| format!("'{:x}'", md5::compute(legacy)))
+        .join(", ");
+        format!("hash IS NULL OR hash IN ({legacy_hashes})")
+    };
+    let content = strip_transaction_control(path, &content)?;
+    let content = if Metadata::parse(&content).interpolate {
+        interpolate(path, &content)?
+    } else {
+        content
+    };
+
+    if !archive && is_empty_migration(&content) {
+        log::warn!(
+            "Skipping empty migration {}; it will be applied once it has content",
+            path.display()
+        );
+        return Ok(compiled_content);
+    }
+
+    let file_name = migration_name(path);
+    let path_with_extension = migration_file_name(path);
+
+    let timed_content = timed(
+        &content,
+        &format!("migrations/{file_name}"),
+        timing && !archive,
+    );
+
+    let edited_level = if strict { "EXCEPTION" } else { "WARNING" };
+
+    let apply_sql = format!(
+        "{timed_content}
+INSERT INTO pgm_migration (name, hash) VALUES ('{file_name}', '{hash}');
+RAISE NOTICE '{applied} Applied migration: {file_name}';",
+        applied = applied_marker()
+    );
+    let squashes = Metadata::parse(&content).squashes;
+    let apply_sql = if squashes.is_empty() {
+        apply_sql
+    } else {
+        squashed_sql(&file_name, &hash, &squashes, apply_sql)
+    };
+
+    let sql = format!(
+        "IF NOT EXISTS (SELECT 1 FROM pgm_migration WHERE name = '{file_name}') THEN
+{apply_sql}
 ELSE
 RAISE NOTICE '- Skipped migration: {file_name} (already applied)';
+UPDATE pgm_migration SET hash = '{hash}' WHERE name = '{file_name}' AND ({adopted_hashes});
+IF (SELECT hash FROM pgm_migration WHERE name = '{file_name}') <> '{hash}' THEN
+RAISE {edited_level} 'Migration {file_name} was edited after it was applied; the changes will not be applied';
+END IF;
 END IF;
--- DONE {path_with_extension} --
 "
-    ));
+    );
+    compiled_content
+        .push_str(&markers.wrap(&path_with_extension, &with_metadata(path, &content, sql)));
 
     Ok(compiled_content)
 }
 
-fn build_fake(pgm_dir_path: &str) -> Result<String> {
+/// Wraps `apply_sql` applying a baseline written by `squash`, which replaces
+/// the migrations named `squashes`. A database that applied all of them has
+/// the baseline recorded without running it, while a new one runs it and has
+/// them recorded as applied, so both end up with the same `pgm_migration`.
+fn squashed_sql(file_name: &str, hash: &str, squashes: &[String], apply_sql: String) -> String {
+    let names = squashes
+        .iter()
+        .map(|name| format!("'{}'", name.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "IF (SELECT count(*) FROM pgm_migration WHERE name IN ({names})) = {count} THEN
+INSERT INTO pgm_migration (name, hash) VALUES ('{file_name}', '{hash}');
+RAISE NOTICE '- Skipped migration: {file_name} (the migrations it squashes are applied)';
+ELSIF EXISTS (SELECT 1 FROM pgm_migration WHERE name IN ({names})) THEN
+RAISE EXCEPTION 'Only some of the migrations squashed into {file_name} are applied; apply the others from the directory as it was before the squash first';
+ELSE
+{apply_sql}
+INSERT INTO pgm_migration (name) SELECT unnest(ARRAY[{names}]) ON CONFLICT (name) DO NOTHING;
+END IF;",
+        count = squashes.len()
+    )
+}
+
+fn build_fake(
+    source: &Source,
+    categories: &[String],
+    filter: &FileFilter,
+    lock_timeout: Option<Duration>,
+    role: Option<&str>,
+    messages: &str,
+) -> Result<String> {
     // Check if the postgres directory exists
-    if !Path::new(pgm_dir_path).is_dir() {
+    if !source.exists() {
         return Err(anyhow::anyhow!(
             "Directory '{}' not found. Have you run 'pgm init'?",
-            pgm_dir_path
+            source.root().display()
         ));
     }
 
+    let includes = |category: &str| categories.iter().any(|c| c == category);
     let mut compiled_content = String::new();
 
     // Start the main DO block
     compiled_content.push_str("DO $pgm$ BEGIN\n");
+    compiled_content.push_str(&lock_sql(lock_timeout));
+    compiled_content.push_str(&set_role_sql(role, true));
+
+    compiled_content.push_str(&pgm_tables_create_sql(messages));
 
-    compiled_content.push_str(&pgm_tables_create_sql());
+    // Process extensions if selected and directory exists
+    if includes("extensions") && source.has_dir("extensions") {
+        let extensions_content =
+            process_directory_fake(source, "extensions", "pgm_extension", filter)
+                .expect("Failed to process extensions");
+        compiled_content.push_str(&extensions_content);
+    }
 
-    // Process functions if directory exists
-    if Path::new(&format!("{}/functions", pgm_dir_path)).is_dir() {
-        let functions_content =
-            process_directory_fake(&format!("{}/functions", pgm_dir_path), "pgm_function")
-                .expect("Failed to process functions");
+    // Process functions if selected and directory exists
+    if includes("functions") && source.has_dir("functions") {
+        let functions_content = process_directory_fake(source, "functions", "pgm_function", filter)
+            .expect("Failed to process functions");
         compiled_content.push_str(&functions_content);
     }
 
-    // Process triggers if directory exists
-    if Path::new(&format!("{}/triggers", pgm_dir_path)).is_dir() {
-        let triggers_content =
-            process_directory_fake(&format!("{}/triggers", pgm_dir_path), "pgm_trigger")
-                .expect("Failed to process triggers");
+    // Process triggers if selected and directory exists
+    if includes("triggers") && source.has_dir("triggers") {
+        let triggers_content = process_directory_fake(source, "triggers", "pgm_trigger", filter)
+            .expect("Failed to process triggers");
         compiled_content.push_str(&triggers_content);
     }
 
-    // Process views if directory exists
-    if Path::new(&format!("{}/views", pgm_dir_path)).is_dir() {
-        let views_content = process_directory_fake(&format!("{}/views", pgm_dir_path), "pgm_view")
+    // Process constraints if selected and directory exists
+    if includes("constraints") && source.has_dir("constraints") {
+        let constraints_content =
+            process_directory_fake(source, "constraints", "pgm_constraint", filter)
+                .expect("Failed to process constraints");
+        compiled_content.push_str(&constraints_content);
+    }
+
+    // Process views if selected and directory exists
+    if includes("views") && source.has_dir("views") {
+        let views_content = process_directory_fake(source, "views", "pgm_view", filter)
             .expect("Failed to process views");
         compiled_content.push_str(&views_content);
     }
 
-    // Process migrations if directory exists
-    if Path::new(&format!("{}/migrations", pgm_dir_path)).is_dir() {
+    // Process grants if selected and directory exists
+    if includes("grants") && source.has_dir("grants") {
+        let grants_content = process_directory_fake(source, "grants", "pgm_grant", filter)
+            .expect("Failed to process grants");
+        compiled_content.push_str(&grants_content);
+    }
+
+    // Process comments if selected and directory exists
+    if includes("comments") && source.has_dir("comments") {
+        let comments_content = process_directory_fake(source, "comments", "pgm_comment", filter)
+            .expect("Failed to process comments");
+        compiled_content.push_str(&comments_content);
+    }
+
+    // Process migrations if selected and directory exists
+    if includes("migrations") && source.has_dir("migrations") {
         let migrations_content =
-            process_migrations_fake(pgm_dir_path).expect("Failed to process migrations");
+            process_migrations_fake(source, filter).expect("Failed to process migrations");
         compiled_content.push_str(&migrations_content);
     }
 
     // End the main DO block
+    compiled_content.push_str(&reset_role_sql(role));
     compiled_content.push_str("END $pgm$;\n");
 
     Ok(compiled_content)
 }
 
-fn process_directory_fake(full_dir_path: &str, table: &str) -> Result<String> {
+fn process_directory_fake(
+    source: &Source,
+    category: &str,
+    table: &str,
+    filter: &FileFilter,
+) -> Result<String> {
     let mut compiled_content = String::new();
-    for entry in std::fs::read_dir(full_dir_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "sql") {
-            let content = std::fs::read_to_string(&path)?;
+    for path in object_files(source, category)? {
+        if filter.allows(&path) {
+            let content = source.read(&path)?;
             let hash = format!("{:x}", md5::compute(&content));
-            let file_name = path.file_stem().unwrap().to_str().unwrap();
+            let file_name = object_name(&path);
 
+            let record = record_sql(table, file_name, &hash);
             compiled_content.push_str(&format!(
                 "-- Fake apply {table} '{file_name}'
-INSERT INTO {table} (name, hash) VALUES ('{file_name}', '{hash}') 
-                ON CONFLICT (name) DO UPDATE SET hash = EXCLUDED.hash, applied_at = CURRENT_TIMESTAMP;
-                RAISE NOTICE '✅ Fake applied: {table} - {file_name}';\n"
+{}                RAISE NOTICE '{} Fake applied: {table} - {file_name}';\n",
+                with_metadata(&path, &content, format!("{record}\n")),
+                applied_marker()
             ));
         }
     }
     Ok(compiled_content)
 }
 
-fn process_migrations_fake(pgm_dir_path: &str) -> Result<String> {
-    let migrations_dir = format!("{}/migrations", pgm_dir_path);
-    let migrations_dir = migrations_dir.as_str();
-    let mut migration_files: Vec<_> = std::fs::read_dir(migrations_dir)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| {
-            entry.path().is_file() && entry.path().extension().map_or(false, |ext| ext == "sql")
-        })
-        .collect();
-
-    // Sort the migration files
-    migration_files.sort_by_key(|entry| entry.file_name());
-
+fn process_migrations_fake(source: &Source, filter: &FileFilter) -> Result<String> {
     let mut compiled_content = String::new();
-    for entry in migration_files {
-        let path = entry.path();
-        let file_name = path.file_stem().unwrap().to_str().unwrap();
-        compiled_content.push_str(&format!(
-            "-- Fake apply migration '{file_name}'
-INSERT INTO pgm_migration (name) VALUES ('{file_name}') ON CONFLICT (name) DO NOTHING;
-            RAISE NOTICE '✅ Fake applied migration: {file_name}';\n"
-        ));
+    for path in migration_files(source)? {
+        if filter.allows(&path) {
+            compiled_content.push_str(&process_migration_fake(source, &path)?);
+        }
     }
     Ok(compiled_content)
 }
+
+/// Records the migration at `path` as applied without running it.
+fn process_migration_fake(source: &Source, path: &Path) -> Result<String> {
+    let file_name = migration_name(path);
+    if !is_archive(path) && is_empty_migration(&source.read(path)?) {
+        log::warn!(
+            "Skipping empty migration {}; it will be applied once it has content",
+            path.display()
+        );
+        return Ok(String::new());
+    }
+    let hash = source.hash(path)?;
+    Ok(format!(
+        "-- Fake apply migration '{file_name}'
+INSERT INTO pgm_migration (name, hash) VALUES ('{file_name}', '{hash}') ON CONFLICT (name) DO NOTHING;
+            RAISE NOTICE '{} Fake applied migration: {file_name}';\n",
+        applied_marker()
+    ))
+}
+
+/// Deletes the record of the migration at `path`, for
+/// [`ApplyOptions::rerun_migrations`], so it runs again and is recorded anew.
+fn rerun_migration_sql(path: &Path) -> String {
+    let name = migration_name(path);
+    format!(
+        "DELETE FROM pgm_migration WHERE name = '{name}';
+RAISE NOTICE 'Re-running migration: {name}';
+"
+    )
+}
+
+/// Returns the files of the migrations named in `names`, each given by its
+/// name, file name or number, e.g. `00012_add_users`, `00012_add_users.sql`
+/// or `00012`, or `v2/00012` in a release directory. Fails on a name matching
+/// no migration or several.
+fn named_migration_files(source: &Source, names: &[String]) -> Result<Vec<PathBuf>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    let migrations = category_files(source, "migrations")?;
+    names
+        .iter()
+        .map(|name| {
+            let number = name
+                .chars()
+                .all(|c| c.is_ascii_digit())
+                .then(|| name.parse::<u32>().ok())
+                .flatten();
+            let matches = migrations
+                .iter()
+                .filter(|(migration, path)| {
+                    migration == name
+                        || migration_file_name(path) == *name
+                        || path
+                            .file_name()
+                            .is_some_and(|file_name| file_name == name.as_str())
+                        || number.is_some() && migration_number(migration) == number
+                })
+                .map(|(_, path)| path.clone())
+                .collect::<Vec<_>>();
+            match matches.as_slice() {
+                [path] => Ok(path.clone()),
+                [] => Err(anyhow::anyhow!(
+                    "No migration '{}' in '{}'",
+                    name,
+                    source.root().join("migrations").display()
+                )),
+                _ => Err(anyhow::anyhow!(
+                    "'{}' matches several migrations, give the full name of one: {}",
+                    name,
+                    matches
+                        .iter()
+                        .map(|path| migration_name(path))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,131 @@
+/// Returns what is left open at the end of `sql`, or closed without being
+/// opened, with the line it is on: a dollar quote, a quoted string or
+/// identifier, a block comment or a parenthesis. It catches files cut short,
+/// e.g. by an editor crash, before psql fails on them deep in the apply. It
+/// is a cheap scan rather than a parser, so parentheses inside dollar-quoted
+/// bodies are not checked.
+pub(crate) fn unbalanced(sql: &str) -> Option<String> {
+    let bytes = sql.as_bytes();
+    let line_at = |i: usize| sql[..i].matches('\n').count() + 1;
+    let mut parentheses = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &sql[i..];
+        match bytes[i] {
+            b'-' if rest.starts_with("--") => {
+                i += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            }
+            b'/' if rest.starts_with("/*") => {
+                // Block comments nest in Postgres
+                let mut depth = 0;
+                let mut j = i;
+                while j < bytes.len() {
+                    if bytes[j..].starts_with(b"/*") {
+                        depth += 1;
+                        j += 2;
+                    } else if bytes[j..].starts_with(b"*/") {
+                        depth -= 1;
+                        j += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        j += 1;
+                    }
+                }
+                if depth > 0 {
+                    return Some(format!(
+                        "the block comment opened on line {} is never closed",
+                        line_at(i)
+                    ));
+                }
+                i = j;
+                continue;
+            }
+            quote @ (b'\'' | b'"') => {
+                let escapes = quote == b'\''
+                    && i > 0
+                    && bytes[i - 1].eq_ignore_ascii_case(&b'e')
+                    && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+                let mut j = i + 1;
+                let mut closed = false;
+                while j < bytes.len() {
+                    if escapes && bytes[j] == b'\\' {
+                        j += 2;
+                    } else if bytes[j] == quote {
+                        // A doubled quote stands for the quote itself
+                        if bytes.get(j + 1) == Some(&quote) {
+                            j += 2;
+                        } else {
+                            closed = true;
+                            break;
+                        }
+                    } else {
+                        j += 1;
+                    }
+                }
+                if !closed {
+                    let kind = if quote == b'\'' {
+                        "string"
+                    } else {
+                        "quoted identifier"
+                    };
+                    return Some(format!(
+                        "the {} opened on line {} is never closed",
+                        kind,
+                        line_at(i)
+                    ));
+                }
+                i = j + 1;
+                continue;
+            }
+            b'$' if i == 0 || !is_identifier_byte(bytes[i - 1]) => {
+                if let Some(tag) = dollar_quote_tag(rest) {
+                    let body = &rest[tag.len()..];
+                    match body.find(tag) {
+                        Some(end) => i += tag.len() + end + tag.len(),
+                        None => {
+                            return Some(format!(
+                                "the dollar quote {} opened on line {} is never closed",
+                                tag,
+                                line_at(i)
+                            ))
+                        }
+                    }
+                    continue;
+                }
+            }
+            b'(' => parentheses.push(i),
+            b')' if parentheses.pop().is_none() => {
+                return Some(format!(
+                    "the ')' on line {} closes no parenthesis",
+                    line_at(i)
+                ));
+            }
+            _ => {}
+        }
+        i += rest.chars().next().map_or(1, char::len_utf8);
+    }
+    parentheses.last().map(|&i| {
+        format!(
+            "the parenthesis opened on line {} is never closed",
+            line_at(i)
+        )
+    })
+}
+
+/// Returns the dollar quote tag, such as `$$` or `$body$`, `sql` starts with,
+/// if any. A positional parameter such as `$1` is not one.
+fn dollar_quote_tag(sql: &str) -> Option<&str> {
+    let after = &sql[1..];
+    let len = after.find(|c: char| !(c.is_alphanumeric() || c == '_'))?;
+    if !after[len..].starts_with('$') || after.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(&sql[..len + 2])
+}
+
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'$' || !byte.is_ascii()
+}
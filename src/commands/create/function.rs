@@ -1,40 +1,68 @@
 use anyhow::{Context, Result};
-use std::io::{self, Write};
 use std::path::Path;
 
-pub fn create_function(pgm_dir_path: &str, name: &str) -> Result<()> {
-    if !Path::new(pgm_dir_path).exists() {
-        return Err(anyhow::anyhow!(
-            "Directory '{}' not found. Have you run 'pgm init'?",
-            pgm_dir_path
-        ));
-    }
+use super::create_object;
 
-    let functions_dir = Path::new(pgm_dir_path).join("functions");
-    std::fs::create_dir_all(&functions_dir).context("Failed to create functions directory")?;
+/// The built-in templates for `create function`, by name.
+pub const FUNCTION_TEMPLATES: [(&str, &str); 3] = [
+    ("plain", include_str!("templates/function.sql")),
+    ("trigger", include_str!("templates/function_trigger.sql")),
+    (
+        "security-definer",
+        include_str!("templates/function_security_definer.sql"),
+    ),
+];
 
-    let file_path = functions_dir.join(format!("{}.sql", name));
-    if file_path.exists() {
-        print!(
-            "Function '{}' already exists. Do you want to reset it? (y/N): ",
-            name
-        );
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+/// Creates a function from the template named `template`, which is looked up
+/// in `<pgm_dir_path>/templates/functions/<template>.sql` first, so a project
+/// can override the built-in templates or add its own. Returns whether the
+/// file was written.
+pub fn create_function(
+    pgm_dir_path: &str,
+    name: &str,
+    template: &str,
+    force: bool,
+) -> Result<bool> {
+    create_object(
+        pgm_dir_path,
+        "Function",
+        "functions",
+        &function_template(pgm_dir_path, template)?,
+        name,
+        force,
+    )
+}
 
-        if !input.trim().eq_ignore_ascii_case("y") {
-            println!("Function creation aborted.");
-            return Ok(());
-        }
+fn function_template(pgm_dir_path: &str, template: &str) -> Result<String> {
+    let templates_dir = Path::new(pgm_dir_path).join("templates/functions");
+    let override_path = templates_dir.join(format!("{}.sql", template));
+    if override_path.is_file() {
+        return std::fs::read_to_string(&override_path).context(format!(
+            "Failed to read template '{}'",
+            override_path.display()
+        ));
+    }
+    if let Some((_, content)) = FUNCTION_TEMPLATES
+        .iter()
+        .find(|(name, _)| *name == template)
+    {
+        return Ok(content.to_string());
     }
 
-    let template = include_str!("templates/function.sql");
-    let content = template.replace("<name_placeholder>", name);
-    std::fs::File::create(&file_path).context("Failed to create function file")?;
-    std::fs::write(file_path, content).context("Failed to write to function file")?;
-
-    println!("Function '{}' created successfully", name);
-    Ok(())
+    let mut available = FUNCTION_TEMPLATES
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    if let Ok(entries) = std::fs::read_dir(&templates_dir) {
+        available.extend(entries.filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?.strip_suffix(".sql")?;
+            Some(name.to_string())
+        }));
+    }
+    Err(anyhow::anyhow!(
+        "Unknown function template '{}', use one of: {}",
+        template,
+        available.join(", ")
+    ))
 }
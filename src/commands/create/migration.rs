@@ -1,30 +1,53 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use anyhow::{Result, Context};
 
-pub fn create_migration(pgm_dir_path: &str) -> Result<()> {
+use super::super::apply::{last_migration_number, migration_releases};
+use super::super::source::Source;
+
+/// Creates the next empty migration, in the release directory `release` of
+/// the migrations if given, e.g. `migrations/v2/`.
+pub fn create_migration(pgm_dir_path: &str, release: Option<&str>) -> Result<()> {
     if !Path::new(pgm_dir_path).exists() {
         return Err(anyhow::anyhow!(
             "Directory '{}' not found. Have you run 'pgm init'?",
             pgm_dir_path
         ));
     }
+    if let Some(release) = release {
+        if release.is_empty()
+            || release.starts_with('.')
+            || release.contains(['/', '\\'])
+            || release == "migrations"
+        {
+            return Err(anyhow::anyhow!(
+                "Invalid release '{}'; use a plain directory name, e.g. 'v2'",
+                release
+            ));
+        }
+        if !migration_releases() {
+            log::warn!(
+                "Migrations in release directories are only applied with PGM_MIGRATION_RELEASES=1"
+            );
+        }
+    }
 
-    let migrations_dir = format!("{}/migrations", pgm_dir_path);
+    let migrations_dir = match release {
+        Some(release) => format!("{}/migrations/{}", pgm_dir_path, release),
+        None => format!("{}/migrations", pgm_dir_path),
+    };
     let migrations_dir = migrations_dir.as_str();
-    let last_migration_file = std::fs::read_dir(migrations_dir)?
-        .filter_map(|entry| entry.ok())
-        .max_by_key(|entry| entry.file_name());
-    let last_migration_number = last_migration_file.map_or(0, |entry| {
-        entry
-            .file_name()
-            .to_str()
-            .and_then(|s| s.split('.').next())
-            .and_then(|s| s.parse::<i32>().ok())
-            .unwrap_or(0)
-    });
+
+    // Create migrations directory if it doesn't exist
+    std::fs::create_dir_all(migrations_dir).context("Failed to create migrations directory")?;
+
+    // Number after the highest numbered migration, so baselines numbered 0,
+    // names like '00012_add_users.sql' and squashed migrations are taken into
+    // account
+    let last_migration_number =
+        last_migration_number(&Source::directory(PathBuf::from(pgm_dir_path)), release)?
+            .unwrap_or(0);
     let next_migration_number = format!("{:05}", last_migration_number + 1);
     let next_migration_file = format!("{}/{}.sql", migrations_dir, next_migration_number);
-    std::fs::create_dir_all(migrations_dir).context("Failed to create migrations directory")?;
     std::fs::write(next_migration_file, "").context("Failed to create migration file")?;
     Ok(())
-}
\ No newline at end of file
+}
@@ -8,4 +8,107 @@ pub use migration::*;
 pub use trigger::*;
 pub use function::*;
 pub use view::*;
-pub use seed::*;
\ No newline at end of file
+pub use seed::*;
+
+use anyhow::{Context, Result};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+/// The keywords Postgres reserves, from its SQL Key Words appendix, which
+/// cannot name an object unless quoted everywhere it is used. Separated by
+/// whitespace.
+const RESERVED_KEYWORDS: &str = "\
+    all analyse analyze and any array as asc asymmetric both case cast check collate \
+    column constraint create current_catalog current_date current_role current_time \
+    current_timestamp current_user default deferrable desc distinct do else end \
+    except false fetch for foreign from grant group having in initially intersect \
+    into lateral leading limit localtime localtimestamp not null offset on only or \
+    order placing primary references returning select session_user some symmetric \
+    system_user table then to trailing true union unique user using variadic when \
+    where window with";
+
+/// Refuses a `name` that is, or has a schema-qualified part that is, a
+/// reserved keyword, as the compiled SQL uses names unquoted. With `force`
+/// it is only a warning.
+fn check_reserved_keyword(kind: &str, name: &str, force: bool) -> Result<()> {
+    let Some(keyword) = name.split('.').find(|part| {
+        RESERVED_KEYWORDS
+            .split_whitespace()
+            .any(|keyword| part.eq_ignore_ascii_case(keyword))
+    }) else {
+        return Ok(());
+    };
+    let message = format!(
+        "'{}' is a reserved keyword in Postgres, so the {} would have to be quoted everywhere it is used; consider renaming it",
+        keyword,
+        kind.to_lowercase()
+    );
+    if force {
+        log::warn!("{}", message);
+        return Ok(());
+    }
+    Err(anyhow::anyhow!(
+        "{}, or pass --force to create it anyway",
+        message
+    ))
+}
+
+/// Creates `<pgm_dir_path>/<dir_name>/<name>.sql` from `template`, with
+/// `<name_placeholder>` replaced by `name`. An existing file is only
+/// overwritten with `force` or after confirmation, and a name that is a
+/// reserved keyword is only accepted with `force`. Returns whether the file
+/// was written, which it is not if resetting it was declined.
+fn create_object(
+    pgm_dir_path: &str,
+    kind: &str,
+    dir_name: &str,
+    template: &str,
+    name: &str,
+    force: bool,
+) -> Result<bool> {
+    if !Path::new(pgm_dir_path).exists() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            pgm_dir_path
+        ));
+    }
+
+    check_reserved_keyword(kind, name, force)?;
+
+    let objects_dir = Path::new(pgm_dir_path).join(dir_name);
+    std::fs::create_dir_all(&objects_dir)
+        .context(format!("Failed to create {} directory", dir_name))?;
+
+    let file_path = objects_dir.join(format!("{}.sql", name));
+    if file_path.exists() && !force && !confirm_reset(kind, name) {
+        return Ok(false);
+    }
+
+    let content = template.replace("<name_placeholder>", name);
+    std::fs::write(file_path, content)
+        .context(format!("Failed to write to {} file", kind.to_lowercase()))?;
+    Ok(true)
+}
+
+/// Asks whether the existing `kind` named `name` should be reset. Without an
+/// interactive stdin there is nobody to ask, so the answer is no.
+fn confirm_reset(kind: &str, name: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        println!(
+            "{} '{}' already exists. Use --force to reset it.",
+            kind, name
+        );
+        return false;
+    }
+
+    print!(
+        "{} '{}' already exists. Do you want to reset it? (y/N): ",
+        kind, name
+    );
+    io::stdout().flush().unwrap();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+
+    input.trim().eq_ignore_ascii_case("y")
+}
@@ -0,0 +1,11 @@
+CREATE OR REPLACE FUNCTION <name_placeholder> (
+)
+RETURNS void
+LANGUAGE plpgsql VOLATILE PARALLEL UNSAFE
+SECURITY DEFINER
+-- Keeps callers from hijacking the function with objects in their own schemas
+SET search_path = pg_catalog, public AS $$
+BEGIN
+
+END;
+$$;
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE OR REPLACE FUNCTION <name_placeholder> ()
+RETURNS TRIGGER
+LANGUAGE plpgsql VOLATILE PARALLEL UNSAFE AS $$
+BEGIN
+    -- Runs AFTER INSERT, once bound with CREATE TRIGGER. NEW is the inserted row.
+
+    RETURN NULL;
+END;
+$$;
\ No newline at end of file
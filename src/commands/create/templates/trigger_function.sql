@@ -6,4 +6,8 @@ LANGUAGE plpgsql VOLATILE PARALLEL UNSAFE AS $$
 BEGIN
     RETURN NEW;
 END;
-$$;
\ No newline at end of file
+$$;
+
+-- CREATE TRIGGER <name_placeholder>
+--     BEFORE INSERT OR UPDATE ON my_table
+--     FOR EACH ROW EXECUTE FUNCTION <name_placeholder>();
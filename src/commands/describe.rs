@@ -0,0 +1,329 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::apply::{category_files, source, ApplyOptions, FileFilter, CATEGORIES};
+use super::disabled::Disabled;
+use super::psql::{log_command, psql_command, redact};
+use super::source::Source;
+
+/// The version of the shape of [`Description`], raised whenever a field is
+/// removed or changes its meaning. Fields may be added without raising it.
+pub const DESCRIPTION_FORMAT: u32 = 1;
+
+/// A machine-readable description of a pgm directory and, if the database
+/// can be reached, of what its pgm tables record, made by [`describe`].
+#[derive(Debug, Clone, Serialize)]
+pub struct Description {
+    /// See [`DESCRIPTION_FORMAT`]
+    pub format: u32,
+    /// The version of pgm that made the description
+    pub version: String,
+    pub path: PathBuf,
+    /// Whether the database could be reached to read the pgm tables, which
+    /// `recorded`, `status` and `orphans` depend on
+    pub database: bool,
+    /// Every file, in the order `build` compiles them
+    pub objects: Vec<DescribedObject>,
+    /// Objects recorded in the pgm tables without a file, e.g. removed files
+    pub orphans: Vec<Orphan>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DescribedObject {
+    pub category: String,
+    /// The name the object is tracked under
+    pub name: String,
+    pub path: PathBuf,
+    /// The md5 hash of the file, as recorded when it is applied
+    pub hash: String,
+    /// What the pgm tables record for the object, if anything
+    pub recorded: Option<Record>,
+    /// How the file compares to the record, if the database could be reached
+    pub status: Option<Status>,
+    /// Whether `disabled.txt` leaves the object out of `build`
+    pub disabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    /// Missing for migrations applied before pgm recorded hashes
+    pub hash: Option<String>,
+    /// When the object was last applied, in RFC 3339
+    pub applied_at: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Orphan {
+    pub category: String,
+    pub name: String,
+    pub recorded: Record,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    /// Applied as it is on disk
+    Applied,
+    /// Changed since it was applied. An apply re-runs it, except for
+    /// migrations, which never run again.
+    Changed,
+    /// Not applied yet
+    Pending,
+}
+
+/// Describes every file in the pgm directory at `pgm_dir_path` with its hash,
+/// and what the pgm tables record for it. The database part is left out if it
+/// cannot be reached.
+pub fn describe(pgm_dir_path: &str) -> Result<Description> {
+    if !Path::new(pgm_dir_path).is_dir() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            pgm_dir_path
+        ));
+    }
+
+    let mut records = get_records();
+    let source = Source::directory(PathBuf::from(pgm_dir_path));
+    let disabled = Disabled::read(&source)?;
+    let mut objects = Vec::new();
+    for category in CATEGORIES {
+        for (name, path) in category_files(&source, category)? {
+            let hash = source.hash(&path)?;
+            let recorded = records
+                .as_mut()
+                .and_then(|records| records.remove(&(category.to_string(), name.clone())));
+            let status = records.as_ref().map(|_| match &recorded {
+                // Migrations without a hash adopt the current one
+                Some(record) if record.hash.as_ref().is_none_or(|h| *h == hash) => Status::Applied,
+                Some(_) => Status::Changed,
+                None => Status::Pending,
+            });
+            objects.push(DescribedObject {
+                category: category.to_string(),
+                disabled: disabled.disables(category, &name),
+                name,
+                path,
+                hash,
+                recorded,
+                status,
+            });
+        }
+    }
+
+    Ok(Description {
+        format: DESCRIPTION_FORMAT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        path: PathBuf::from(pgm_dir_path),
+        database: records.is_some(),
+        orphans: records
+            .unwrap_or_default()
+            .into_iter()
+            .map(|((category, name), recorded)| Orphan {
+                category,
+                name,
+                recorded,
+            })
+            .collect(),
+        objects,
+    })
+}
+
+/// The pgm tables [`get_records`] reads, by category.
+const TABLES: [(&str, &str); 8] = [
+    ("extensions", "pgm_extension"),
+    ("migrations", "pgm_migration"),
+    ("functions", "pgm_function"),
+    ("triggers", "pgm_trigger"),
+    ("constraints", "pgm_constraint"),
+    ("views", "pgm_view"),
+    ("grants", "pgm_grant"),
+    ("comments", "pgm_comment"),
+];
+
+/// Returns the records of the pgm tables by category and name, or `None` if
+/// the database cannot be reached. Only the tables that exist are read, as a
+/// database last applied by an older pgm lacks the newer ones, and one never
+/// applied has none.
+pub(crate) fn get_records() -> Option<BTreeMap<(String, String), Record>> {
+    let existing = query_rows(&format!(
+        "SELECT t FROM unnest(ARRAY[{}]) AS t WHERE to_regclass(t) IS NOT NULL",
+        TABLES.map(|(_, table)| format!("'{table}'")).join(", ")
+    ))?;
+    let query = TABLES
+        .iter()
+        .filter(|(_, table)| existing.iter().any(|name| name == table))
+        .map(|(category, table)| {
+            format!(
+                "SELECT '{category}', name, hash, to_char(applied_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') FROM {table}"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ");
+    if query.is_empty() {
+        return Some(BTreeMap::new());
+    }
+
+    let fields = query_rows(&query)?;
+    let optional = |field: &str| (field != "\u{1}").then(|| field.to_string());
+    Some(
+        fields
+            .chunks(4)
+            .filter_map(|row| match row {
+                [category, name, hash, applied_at] => Some((
+                    (category.to_string(), name.to_string()),
+                    Record {
+                        hash: optional(hash),
+                        applied_at: optional(applied_at),
+                    },
+                )),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// Runs `query` and returns the fields of every row, in order, or `None` if
+/// it fails.
+fn query_rows(query: &str) -> Option<Vec<String>> {
+    // Fields and rows are separated by NUL bytes, and NULLs shown as a
+    // sequence no name contains
+    let mut command = psql_command();
+    command.args(["-t", "-A", "-z", "-0", "-P", "null=\u{1}", "-c", query]);
+    // Don't wait long for a database that may not be running
+    if std::env::var_os("PGCONNECT_TIMEOUT").is_none() {
+        command.env("PGCONNECT_TIMEOUT", "3");
+    }
+    log_command(&command);
+
+    let output = match command.output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            log::debug!(
+                "Not describing the database: {}",
+                redact(String::from_utf8_lossy(&output.stderr).trim())
+            );
+            return None;
+        }
+        Err(e) => {
+            log::debug!("Not describing the database: {}", e);
+            return None;
+        }
+    };
+
+    let output = String::from_utf8_lossy(&output.stdout);
+    Some(
+        output
+            .strip_suffix('\0')
+            .map(|rows| rows.split('\0').map(str::to_string).collect())
+            .unwrap_or_default(),
+    )
+}
+
+/// What an apply would do with an object, as previewed by [`preview`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Not applied yet
+    New,
+    /// Changed since it was applied, so it is re-run
+    Reapply,
+    /// A migration changed since it was applied, which is never re-run
+    Edited,
+    /// Applied as it is on disk, so it is skipped
+    Unchanged,
+    /// Recorded in the pgm tables without a file. apply leaves it in place.
+    NoFile,
+    /// Listed in `disabled.txt`, so it is neither applied nor dropped
+    Disabled,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::New => "new",
+            Action::Reapply => "would apply",
+            Action::Edited => "edited (not re-run)",
+            Action::Unchanged => "unchanged",
+            Action::NoFile => "no file (kept)",
+            Action::Disabled => "disabled",
+        })
+    }
+}
+
+/// An object of a [`preview`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviewedObject {
+    pub category: String,
+    /// The name the object is tracked under
+    pub name: String,
+    pub action: Action,
+}
+
+/// Compares the hashes of the files `apply` with `options` would compile to
+/// those the pgm tables of the database record, to tell what the apply would
+/// do with each object without compiling or running anything.
+pub fn preview(options: &ApplyOptions) -> Result<Vec<PreviewedObject>> {
+    let source = source(options);
+    if !source.exists() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            source.root().display()
+        ));
+    }
+    let filter = FileFilter::new(source.root(), &options.include, &options.exclude)?;
+    let disabled = Disabled::read(&source)?;
+    let mut records = get_records().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Could not read the pgm tables; check the PG* environment variables, or run with --log-level debug to see why"
+        )
+    })?;
+
+    let mut objects = Vec::new();
+    for category in CATEGORIES {
+        if !options.categories.iter().any(|c| c == category) {
+            continue;
+        }
+        for (name, path) in category_files(&source, category)? {
+            if disabled.disables(category, &name) {
+                records.remove(&(category.to_string(), name.clone()));
+                objects.push(PreviewedObject {
+                    category: category.to_string(),
+                    name,
+                    action: Action::Disabled,
+                });
+                continue;
+            }
+            if !filter.allows(&path) {
+                continue;
+            }
+            let hash = source.hash(&path)?;
+            let action = match records.remove(&(category.to_string(), name.clone())) {
+                None => Action::New,
+                // Migrations without a hash adopt the current one
+                Some(record) if record.hash.as_ref().is_none_or(|h| *h == hash) => {
+                    Action::Unchanged
+                }
+                Some(_) if category == "migrations" => Action::Edited,
+                Some(_) => Action::Reapply,
+            };
+            objects.push(PreviewedObject {
+                category: category.to_string(),
+                name,
+                action,
+            });
+        }
+    }
+    objects.extend(
+        records
+            .into_keys()
+            .filter(|(category, _)| options.categories.contains(category))
+            .map(|(category, name)| PreviewedObject {
+                category,
+                name,
+                action: Action::NoFile,
+            }),
+    );
+    Ok(objects)
+}
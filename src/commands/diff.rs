@@ -0,0 +1,187 @@
+use anyhow::Result;
+use similar::TextDiff;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::apply::{category_files, CATEGORIES};
+use super::describe::get_records;
+use super::source::Source;
+use crate::DEFAULT_PGM_PATH;
+
+/// Options for [`diff`].
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// The pgm directory with the new state
+    pub path: String,
+    /// The pgm directory with the old state, or `None` to compare `path` with
+    /// the hashes the pgm tables of the database record
+    pub base: Option<String>,
+    /// The categories to compare, out of [`CATEGORIES`]
+    pub categories: Vec<String>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_PGM_PATH.to_string(),
+            base: None,
+            categories: CATEGORIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Only in the new state, e.g. a file not applied yet
+    Added,
+    /// Only in the old state, e.g. a recorded object whose file was removed
+    Removed,
+    /// In both, with a different hash
+    Changed,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Changed => "changed",
+        })
+    }
+}
+
+/// An object that differs between the two states [`diff`] compares.
+#[derive(Debug, Clone)]
+pub struct ObjectDiff {
+    pub category: String,
+    /// The name the object is tracked under
+    pub name: String,
+    pub change: Change,
+    /// The file of the object in the new state, unless it was removed
+    pub path: Option<PathBuf>,
+    /// The unified diff of the files, if both states are directories
+    pub text_diff: Option<String>,
+}
+
+/// Compares the objects of the pgm directory at [`DiffOptions::path`] with
+/// those of [`DiffOptions::base`] by name and hash, or, without a base, with
+/// what the pgm tables record, in which case pending files are added and
+/// recorded objects without a file removed. Migrations recorded before pgm
+/// hashed them count as unchanged. Nothing is written anywhere.
+pub fn diff(options: &DiffOptions) -> Result<Vec<ObjectDiff>> {
+    let new = read_directory(&options.path, &options.categories)?;
+    let old = match &options.base {
+        Some(base) => read_directory(base, &options.categories)?,
+        None => read_records(&options.categories)?,
+    };
+
+    let mut diffs = Vec::new();
+    for category in CATEGORIES {
+        let mut names = new
+            .keys()
+            .chain(old.keys())
+            .filter(|(c, _)| c == category)
+            .map(|(_, name)| name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let key = (category.to_string(), name.clone());
+            let change = match (old.get(&key), new.get(&key)) {
+                (None, Some(_)) => Change::Added,
+                (Some(_), None) => Change::Removed,
+                (Some(old), Some(new)) if old.changed_to(new) => Change::Changed,
+                _ => continue,
+            };
+            let text_diff = options.base.as_ref().map(|_| {
+                let (old, new) = (old.get(&key), new.get(&key));
+                let content = |object: Option<&Object>| {
+                    object
+                        .and_then(|object| object.content.clone())
+                        .unwrap_or_default()
+                };
+                // Like git, a missing file is shown as /dev/null
+                let label = |object: Option<&Object>| match object {
+                    Some(object) => object.path.display().to_string(),
+                    None => "/dev/null".to_string(),
+                };
+                TextDiff::from_lines(&content(old), &content(new))
+                    .unified_diff()
+                    .header(&label(old), &label(new))
+                    .to_string()
+            });
+            diffs.push(ObjectDiff {
+                category: category.to_string(),
+                name,
+                change,
+                path: new.get(&key).map(|object| object.path.clone()),
+                text_diff,
+            });
+        }
+    }
+    Ok(diffs)
+}
+
+/// An object on one side of a [`diff`].
+struct Object {
+    /// Missing for migrations recorded before pgm hashed them
+    hash: Option<String>,
+    path: PathBuf,
+    /// The file, if the object comes from a directory
+    content: Option<String>,
+}
+
+impl Object {
+    fn changed_to(&self, new: &Object) -> bool {
+        self.hash.is_some() && new.hash.is_some() && self.hash != new.hash
+    }
+}
+
+fn read_directory(
+    pgm_dir_path: &str,
+    categories: &[String],
+) -> Result<BTreeMap<(String, String), Object>> {
+    if !Path::new(pgm_dir_path).is_dir() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            pgm_dir_path
+        ));
+    }
+    let source = Source::directory(PathBuf::from(pgm_dir_path));
+    let mut objects = BTreeMap::new();
+    for category in categories {
+        for (name, path) in category_files(&source, category)? {
+            let content = source.read(&path)?;
+            objects.insert(
+                (category.clone(), name),
+                Object {
+                    hash: Some(source.hash(&path)?),
+                    path,
+                    content: Some(content),
+                },
+            );
+        }
+    }
+    Ok(objects)
+}
+
+fn read_records(categories: &[String]) -> Result<BTreeMap<(String, String), Object>> {
+    let records = get_records().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Could not read the pgm tables; check the PG* environment variables, or run with --log-level debug to see why"
+        )
+    })?;
+    Ok(records
+        .into_iter()
+        .filter(|((category, _), _)| categories.contains(category))
+        .map(|(key, record)| {
+            let object = Object {
+                hash: record.hash,
+                path: PathBuf::new(),
+                content: None,
+            };
+            (key, object)
+        })
+        .collect())
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+
+use super::source::{Origin, Source};
+
+/// The file at the root of a pgm directory listing the objects pgm leaves
+/// out of `build` while keeping their files, e.g. experimental functions.
+/// Unlike `.pgmignore`, `list` and `describe` still show them, as disabled.
+pub(crate) const DISABLED: &str = "disabled.txt";
+
+/// The patterns of the [`DISABLED`] file of a pgm directory, each matching
+/// `<category>/<name>`, e.g. `functions/experimental_*`.
+#[derive(Default)]
+pub(crate) struct Disabled(Vec<Pattern>);
+
+impl Disabled {
+    /// Reads the [`DISABLED`] file of `source`, which disables nothing if
+    /// there is none. Blank lines and lines starting with `#` are skipped.
+    pub(crate) fn read(source: &Source) -> Result<Self> {
+        let path = source.root().join(DISABLED);
+        let exists = match &source.origin {
+            Origin::Directory(_) => path.is_file(),
+            Origin::Bundle { bundle, .. } => bundle.files.contains_key(DISABLED),
+        };
+        if !exists {
+            return Ok(Self::default());
+        }
+        let patterns = source
+            .read(&path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|pattern| {
+                Pattern::new(pattern).context(format!(
+                    "Invalid pattern '{}' in '{}'",
+                    pattern,
+                    path.display()
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self(patterns))
+    }
+
+    /// Returns whether the object `name` of `category` is disabled. Migrations
+    /// never are, as the ones after them may depend on them.
+    pub(crate) fn disables(&self, category: &str, name: &str) -> bool {
+        if category == "migrations" {
+            return false;
+        }
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let object = format!("{}/{}", category, name);
+        self.0
+            .iter()
+            .any(|pattern| pattern.matches_with(&object, options))
+    }
+}
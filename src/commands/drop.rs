@@ -0,0 +1,109 @@
+use anyhow::Result;
+
+use super::metadata::DropFirst;
+
+/// Builds the statements dropping the object a file of `category` named
+/// `name` manages, always with `IF EXISTS`. Functions and trigger functions
+/// can be overloaded, so they are dropped by the `signatures` recorded when
+/// they were applied (see [`signatures_sql`]) rather than by bare name, which
+/// fails with "function name is not unique" once an overload exists.
+pub(crate) fn drop_statements(category: &str, name: &str, signatures: &[String]) -> Result<String> {
+    match category {
+        "functions" | "triggers" => {
+            if signatures.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No signature is recorded for function '{}'; apply it once so pgm knows which overloads it created",
+                    name
+                ));
+            }
+            Ok(signatures
+                .iter()
+                .map(|signature| format!("DROP FUNCTION IF EXISTS {};\n", signature))
+                .collect())
+        }
+        "views" => Ok(format!("DROP VIEW IF EXISTS {};\n", name)),
+        "extensions" => Ok(format!(
+            "DROP EXTENSION IF EXISTS \"{}\";\n",
+            name.replace('"', "\"\"")
+        )),
+        "constraints" => {
+            // Constraint files are named '<table>.<constraint>', both quoted
+            // where needed, so the last dot outside of quotes separates them
+            let mut quoted = false;
+            let dot = name.char_indices().rev().find_map(|(i, c)| {
+                match c {
+                    '"' => quoted = !quoted,
+                    '.' if !quoted => return Some(i),
+                    _ => {}
+                }
+                None
+            });
+            let (table, constraint) =
+                dot.map(|i| (&name[..i], &name[i + 1..])).ok_or_else(|| {
+                    anyhow::anyhow!("Constraint '{}' is not named '<table>.<constraint>'", name)
+                })?;
+            Ok(format!(
+                "ALTER TABLE IF EXISTS {} DROP CONSTRAINT IF EXISTS {};\n",
+                table, constraint
+            ))
+        }
+        _ => Err(anyhow::anyhow!("{} cannot be dropped", category)),
+    }
+}
+
+/// Wraps the SQL of the view file `name`, with `-- pgm: recreate`, so that
+/// when Postgres refuses to replace the view (`invalid_table_definition`, e.g.
+/// "cannot drop columns from view"), the view is dropped with `CASCADE` and
+/// created again. The views dropped along with it are forgotten in
+/// `pgm_view`, so that they are created again by their own file, later in
+/// the same apply or in the next one.
+pub(crate) fn recreate_view_sql(name: &str, content: &str) -> String {
+    format!(
+        "BEGIN
+{content}
+EXCEPTION WHEN invalid_table_definition THEN
+RAISE WARNING 'views/{name}: %; dropping it with CASCADE to create it again, which also drops the objects depending on it', SQLERRM;
+DROP VIEW IF EXISTS {name} CASCADE;
+{content}
+DELETE FROM pgm_view WHERE name <> '{name}' AND to_regclass(quote_ident(name)) IS NULL;
+END;
+"
+    )
+}
+
+/// SQL evaluating to the identities (`schema.name(argument types)`) of the
+/// functions named `name` in the search path, as recorded in the `signatures`
+/// column of `pgm_function` and `pgm_trigger`.
+pub(crate) fn signatures_sql(name: &str) -> String {
+    format!(
+        "ARRAY(SELECT format('%I.%I(%s)', n.nspname, p.proname, pg_get_function_identity_arguments(p.oid)) \
+         FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace \
+         WHERE p.proname = '{}' AND n.nspname = ANY (current_schemas(false)) ORDER BY 1)",
+        name
+    )
+}
+
+/// Builds the statements a file with `-- pgm: drop-first` runs before its own
+/// SQL. Functions are dropped by the signatures recorded in `table`, or by
+/// those in the database if none are, so a changed argument list still drops
+/// the old function.
+pub(crate) fn drop_first_sql(category: &str, table: &str, name: &str, mode: DropFirst) -> String {
+    let cascade = match mode {
+        DropFirst::Restrict => "",
+        DropFirst::Cascade => " CASCADE",
+    };
+    if category == "views" {
+        return format!("DROP VIEW IF EXISTS {name}{cascade};\n");
+    }
+    format!(
+        "DECLARE
+pgm_signature text;
+BEGIN
+FOR pgm_signature IN SELECT unnest(coalesce((SELECT signatures FROM {table} WHERE name = '{name}'), {})) LOOP
+EXECUTE 'DROP FUNCTION IF EXISTS ' || pgm_signature || '{cascade}';
+END LOOP;
+END;
+",
+        signatures_sql(name)
+    )
+}
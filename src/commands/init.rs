@@ -1,69 +1,303 @@
 use anyhow::{Context, Result};
-use std::path::Path;
-use std::process::Command as ProcessCommand;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command as ProcessCommand, Output};
 use tempfile::NamedTempFile;
 
-use crate::INITIAL_MIGRATION_FILE_NAME;
+use super::apply::track_dollar_quote;
+use super::drop::drop_statements;
+use super::psql::{
+    check_program, log_command, pg_dump_program, pg_restore_program, psql_command, psql_program,
+    redact, temp_file,
+};
+use crate::{DEFAULT_PGM_PATH, INITIAL_MIGRATION_FILE_NAME};
 
-fn create_directory_structure(pgm_dir_path: &str) -> Result<()> {
+/// The directories `init` creates in every pgm directory. The other
+/// categories only get a directory when they are used.
+pub(crate) const STANDARD_DIRS: [&str; 5] =
+    ["migrations", "triggers", "views", "functions", "seeds"];
+
+/// The initial migration `init --format custom` writes.
+const CUSTOM_FORMAT_MIGRATION_FILE_NAME: &str = "00000.dump";
+
+pub(crate) fn create_directory_structure(pgm_dir_path: &str) -> Result<()> {
     std::fs::create_dir_all(pgm_dir_path).context("Failed to create directory")?;
-    std::fs::create_dir_all(format!("{}/migrations", pgm_dir_path))
-        .context("Failed to create migrations directory")?;
-    std::fs::create_dir_all(format!("{}/triggers", pgm_dir_path))
-        .context("Failed to create triggers directory")?;
-    std::fs::create_dir_all(format!("{}/views", pgm_dir_path))
-        .context("Failed to create views directory")?;
-    std::fs::create_dir_all(format!("{}/functions", pgm_dir_path))
-        .context("Failed to create functions directory")?;
-    std::fs::create_dir_all(format!("{}/seeds", pgm_dir_path))
-        .context("Failed to create seeds directory")?;
+    for dir in STANDARD_DIRS {
+        std::fs::create_dir_all(format!("{}/{}", pgm_dir_path, dir))
+            .context(format!("Failed to create {} directory", dir))?;
+    }
     Ok(())
 }
 
-fn get_initial_migration_from_db() -> Result<NamedTempFile> {
+/// Makes a `pg_dump` script runnable inside the `DO` block `apply` wraps
+/// migrations in: drops psql meta-commands (`\restrict`) and the
+/// `search_path` reset, which would otherwise last for the rest of the apply,
+/// turns the other top-level `SELECT`s, such as the large object `lo_create`
+/// calls, into `PERFORM`s and lowers `client_min_messages` so our notices stay
+/// visible. Warns if there is no `search_path` reset, as a `pg_dump` whose
+/// output changed may set it some other way.
+fn normalize_dump(content: &str) -> String {
+    let mut normalized = String::with_capacity(content.len());
+    let mut dollar_quote = None;
+    let mut starts_statement = true;
+    let mut resets_search_path = false;
+    for line in content.lines() {
+        let top_level = dollar_quote.is_none() && starts_statement;
+        dollar_quote = track_dollar_quote(line, dollar_quote);
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with("--") {
+            starts_statement =
+                dollar_quote.is_none() && (trimmed.ends_with(';') || trimmed.starts_with('\\'));
+        }
+        if !top_level {
+            normalized.push_str(line);
+            normalized.push('\n');
+            continue;
+        }
+
+        let statement = trimmed.to_ascii_lowercase();
+        if is_search_path_reset(&statement) {
+            resets_search_path = true;
+            continue;
+        }
+        if statement.starts_with('\\') {
+            continue;
+        }
+        if statement.starts_with("set client_min_messages") {
+            normalized.push_str("SET client_min_messages = notice;");
+        } else if statement
+            .strip_prefix("select")
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        {
+            normalized.push_str("PERFORM");
+            normalized.push_str(&trimmed["select".len()..]);
+        } else {
+            normalized.push_str(line);
+        }
+        normalized.push('\n');
+    }
+    if !resets_search_path {
+        log::warn!(
+            "The dump does not reset search_path the way pg_dump does; check that it does not change search_path some other way, as that would last for the rest of the apply"
+        );
+    }
+    normalized
+}
+
+/// Whether the lowercased `statement` empties `search_path`, as `pg_dump`
+/// does at the start of a dump, whatever the spacing.
+fn is_search_path_reset(statement: &str) -> bool {
+    let statement = statement
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let statement = statement
+        .strip_prefix("select")
+        .map(|call| call.strip_prefix("pg_catalog.").unwrap_or(call));
+    statement.is_some_and(|call| call.starts_with("set_config('search_path','',"))
+}
+
+pub(crate) fn get_initial_migration_from_db(
+    database_url: Option<&str>,
+    pg_dump_args: &[String],
+) -> Result<NamedTempFile> {
+    let schema_dump_file = dump_schema(database_url, &[], pg_dump_args)?;
+
+    let schema_dump_file_content = std::fs::read_to_string(schema_dump_file.path())?;
+    std::fs::write(
+        schema_dump_file.path(),
+        normalize_dump(&schema_dump_file_content),
+    )?;
+
+    Ok(schema_dump_file)
+}
+
+/// Dumps the schema of the database into a temporary file with `pg_dump`,
+/// passing it `format_args` before the user's `pg_dump_args`.
+fn dump_schema(
+    database_url: Option<&str>,
+    format_args: &[&str],
+    pg_dump_args: &[String],
+) -> Result<NamedTempFile> {
     // Create temporary file for schema dump
     let schema_dump_file =
-        NamedTempFile::new().context("Failed to create temporary file for schema dump")?;
-
-    let mut child = match ProcessCommand::new("pg_dump")
-        .args(&[
-            "-f",
-            schema_dump_file.path().to_str().unwrap(),
-            "--no-owner",
-            "--schema-only",
-        ])
+        temp_file().context("Failed to create temporary file for schema dump")?;
+
+    let mut command = ProcessCommand::new(pg_dump_program());
+    command.args([
+        "-f",
+        schema_dump_file.path().to_str().unwrap(),
+        "--no-owner",
+        "--schema-only",
+    ]);
+    command.args(format_args);
+    if let Some(database_url) = database_url {
+        command.arg(format!("--dbname={}", database_url));
+    }
+    command.args(pg_dump_args);
+    log_command(&command);
+
+    let child = command
+        .stderr(std::process::Stdio::piped())
         .spawn()
-    {
-        Ok(child) => child,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+        .context("Failed to spawn pg_dump")?;
+
+    let output = child
+        .wait_with_output()
+        .context("Failed to wait for pg_dump")?;
+    if !output.status.success() {
+        let stderr = redact(&String::from_utf8_lossy(&output.stderr));
+        if stderr.contains("server version mismatch") {
             return Err(anyhow::anyhow!(
-                "pg_dump not found. Please ensure it is installed and in your PATH."
+                "pg_dump is older than the database server. Please use a pg_dump matching the server's major version.\n{}",
+                stderr.trim()
             ));
         }
-        Err(e) => return Err(anyhow::anyhow!("Failed to spawn pg_dump: {}", e)),
-    };
+        return Err(anyhow::anyhow!("pg_dump failed: {}", stderr.trim()));
+    }
 
-    let exit_status = child.wait().context("Failed to wait for pg_dump")?;
-    if !exit_status.success() {
-        return Err(anyhow::anyhow!("pg_dump failed"));
+    Ok(schema_dump_file)
+}
+
+/// The directory [`snapshot_schema`] writes to, inside the pgm directory.
+pub(crate) const SNAPSHOTS_DIR: &str = ".pgm-snapshots";
+
+/// Dumps the schema of the database at `database_url`, or of the PG*
+/// environment variables, as it is to `<dir>/.pgm-snapshots/<time>_<database>.sql`,
+/// a plain `pg_dump` script to restore from by hand, and returns its path.
+pub(crate) fn snapshot_schema(dir: &Path, database_url: Option<&str>) -> Result<PathBuf> {
+    check_program(&pg_dump_program(), "PGM_PG_DUMP")?;
+    let output = psql(database_url, &["-t", "-A"], "SELECT current_database()")
+        .context("Failed to execute psql command to get the database name")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to connect to the database to snapshot: {}",
+            redact(String::from_utf8_lossy(&output.stderr).trim())
+        ));
     }
+    let database = psql_value(&output.stdout)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let time = humantime::format_rfc3339_seconds(std::time::SystemTime::now())
+        .to_string()
+        .replace(['-', ':'], "");
+
+    let dump = dump_schema(database_url, &[], &[])?;
+    let snapshots = dir.join(SNAPSHOTS_DIR);
+    std::fs::create_dir_all(&snapshots)
+        .context(format!("Failed to create '{}'", snapshots.display()))?;
+    let path = snapshots.join(format!("{}_{}.sql", time, database));
+    std::fs::copy(dump.path(), &path).context(format!(
+        "Failed to write the snapshot to '{}'",
+        path.display()
+    ))?;
+    Ok(path)
+}
 
-    // HACK: replace SELECT pg_catalog.set_config('search_path', '', false);
-    let schema_dump_file_content = std::fs::read_to_string(schema_dump_file.path())?;
-    let modified_content = schema_dump_file_content
-        .replace("SELECT pg_catalog.set_config('search_path', '', false);", "")
-        .replace("SET client_min_messages = warning;", "SET client_min_messages = notice;");
-    std::fs::write(schema_dump_file.path(), modified_content)?;
+/// Converts `archive`, the custom-format dump at `path` as written by `init
+/// --format custom`, to a SQL script with `pg_restore`, prepared like the
+/// dumps [`get_initial_migration_from_db`] makes, for a dump-only apply,
+/// which prints SQL. Other applies restore it without converting it.
+pub(crate) fn restore_custom_format_dump(path: &Path, archive: &[u8]) -> Result<String> {
+    let mut file = temp_file()?;
+    file.write_all(archive).context(format!(
+        "Failed to copy '{}' to a temporary file",
+        path.display()
+    ))?;
+    let mut command = ProcessCommand::new(pg_restore_program());
+    command.args(["-f", "-", "--no-owner"]).arg(file.path());
+    log_command(&command);
+    let output = command.output().context(format!(
+        "Failed to run pg_restore to read '{}'. Please ensure it is installed and in your PATH, or set PGM_PG_RESTORE to its path",
+        path.display()
+    ))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "pg_restore failed to read '{}': {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let script = String::from_utf8(output.stdout).context(format!(
+        "pg_restore output for '{}' is not UTF-8",
+        path.display()
+    ))?;
+    Ok(normalize_dump(&script))
+}
+
+/// Makes the initial migration from the plain-format `pg_dump` output at
+/// `dump_path`, as [`get_initial_migration_from_db`] does from a dump it makes
+/// itself.
+fn get_initial_migration_from_dump(dump_path: &str) -> Result<NamedTempFile> {
+    let dump = std::fs::read(dump_path).context(format!("Failed to read dump '{}'", dump_path))?;
+    if dump.starts_with(b"PGDMP") {
+        return Err(anyhow::anyhow!(
+            "'{}' is a custom-format dump; convert it to SQL with 'pg_restore -f dump.sql {}' first",
+            dump_path,
+            dump_path
+        ));
+    }
+    let dump = String::from_utf8(dump).context(format!("Dump '{}' is not UTF-8", dump_path))?;
 
+    let schema_dump_file =
+        temp_file().context("Failed to create temporary file for schema dump")?;
+    std::fs::write(schema_dump_file.path(), normalize_dump(&dump))?;
     Ok(schema_dump_file)
 }
 
-fn get_triggers_from_db() -> Result<Vec<(String, String)>> {
-    let function_names = ProcessCommand::new("psql")
-        .args(&[
-            "-t",
-            "-c",
-            "SELECT proname AS function_name
+/// Runs `sql` with `psql` and `args`, e.g. `-t -A`, connected to
+/// `database_url`, or to the database described by the PG* environment
+/// variables when no URL is given. The SQL is read from a temporary file
+/// rather than passed with `-c`, as Windows limits the length of a command
+/// line and its quoting mangles the SQL. psql stops at the first error, so
+/// the exit status still tells whether the query failed.
+pub(crate) fn psql(database_url: Option<&str>, args: &[&str], sql: &str) -> Result<Output> {
+    let mut file = temp_file().context("Failed to create temporary file for the query")?;
+    file.write_all(sql.as_bytes())
+        .context("Failed to write the query to a temporary file")?;
+    let mut command = psql_command();
+    if let Some(database_url) = database_url {
+        command.args(["-d", database_url]);
+    }
+    command.args(args);
+    command.args(["-v", "ON_ERROR_STOP=1", "-f"]);
+    command.arg(file.path());
+    log_command(&command);
+    log::debug!("Query: {}", sql);
+    command.output().context("Failed to run psql")
+}
+
+/// Returns what `psql -t -A` printed for a query, exactly as the database
+/// returned it but for the newline ending the last row. Lines are not
+/// trimmed, as trailing whitespace inside a dollar-quoted body or a string
+/// literal is part of the definition.
+fn psql_value(stdout: &[u8]) -> String {
+    let output = String::from_utf8_lossy(stdout);
+    output.strip_suffix('\n').unwrap_or(&output).to_string()
+}
+
+/// Returns what a [`psql`] run printed, or an error with what psql reported
+/// if the query failed, e.g. for lack of a permission, naming the `objects`
+/// the query was getting.
+fn query_stdout(output: Output, objects: &str) -> Result<Vec<u8>> {
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to get {}: {}",
+            objects,
+            redact(String::from_utf8_lossy(&output.stderr).trim())
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn get_triggers_from_db(database_url: Option<&str>) -> Result<Vec<(String, String)>> {
+    let function_names = psql(
+        database_url,
+        &["-t"],
+        "SELECT proname AS function_name
              FROM pg_proc p
              JOIN pg_namespace n ON p.pronamespace = n.oid
              LEFT JOIN pg_depend d ON d.objid = p.oid AND d.deptype = 'e'
@@ -77,56 +311,40 @@ fn get_triggers_from_db() -> Result<Vec<(String, String)>> {
                     WHERE t.tgfoid = p.oid
                 )
              ORDER BY function_name;",
-        ])
-        .output()
-        .context("Failed to execute psql command to get function names")?;
-    let function_names = String::from_utf8(function_names.stdout)
+    )
+    .context("Failed to execute psql command to get function names")?;
+    let function_names = String::from_utf8(query_stdout(function_names, "function names")?)
         .context("Failed to convert function names output to UTF-8")?
         .lines()
         .map(|line| line.trim().to_string())
         .filter(|line| !line.is_empty())
         .collect::<Vec<String>>();
 
-    let processes = function_names.iter().map(|name| {
-        ProcessCommand::new("psql")
-            .args(&[
-                "-t",
-                "-A",
-                "-c",
-                &format!(
-                    "SELECT pg_get_functiondef(p.oid) AS function_definition
+    let outputs = function_names.iter().map(|name| {
+        psql(
+            database_url,
+            &["-t", "-A"],
+            // The function, followed by the triggers binding it to tables
+            &format!(
+                    "SELECT rtrim(pg_get_functiondef(p.oid), E'\\n') || ';' || coalesce(E'\\n\\n' || (
+                         SELECT string_agg(pg_get_triggerdef(t.oid) || ';', E'\\n' ORDER BY t.tgname)
+                         FROM pg_trigger t
+                         WHERE t.tgfoid = p.oid AND NOT t.tgisinternal
+                     ), '') AS function_definition
                      FROM pg_proc p
                      JOIN pg_namespace n ON p.pronamespace = n.oid
                      WHERE n.nspname = 'public' AND p.proname = '{}';",
-                    name
-                ),
-            ])
-            .stdout(std::process::Stdio::piped())
-            .spawn()
-            .context(format!(
-                "Failed to spawn psql command for function '{}'",
-                name
-            ))
+                name.replace('\'', "''")
+            ),
+        )
+        .context(format!("Failed to run psql command for function '{}'", name))
     });
 
-    let function_contents = processes
-        .map(|process| {
-            process.and_then(|child| {
-                child
-                    .wait_with_output()
-                    .context("Failed to wait for psql command output")
-            })
-        })
+    let function_contents = outputs
         .map(|output| {
-            output.map(|o| {
-                let content = String::from_utf8_lossy(&o.stdout)
-                    .lines()
-                    .map(|line| line.trim_end())
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                let content = content.trim_end();
-                format!("{content};")
-            })
+            output
+                .and_then(|o| query_stdout(o, "a trigger function"))
+                .map(|stdout| psql_value(&stdout).trim_end().to_string())
         })
         .collect::<Result<Vec<_>, _>>()
         .context("Failed to collect function contents")?;
@@ -140,12 +358,11 @@ fn get_triggers_from_db() -> Result<Vec<(String, String)>> {
     Ok(functions)
 }
 
-fn get_functions_from_db() -> Result<Vec<(String, String)>> {
-    let function_names = ProcessCommand::new("psql")
-        .args(&[
-            "-t",
-            "-c",
-            "SELECT DISTINCT proname AS function_name
+fn get_functions_from_db(database_url: Option<&str>) -> Result<Vec<(String, String)>> {
+    let function_names = psql(
+        database_url,
+        &["-t"],
+        "SELECT DISTINCT proname AS function_name
              FROM pg_proc p
              JOIN pg_namespace n ON p.pronamespace = n.oid
              LEFT JOIN pg_depend d ON d.objid = p.oid AND d.deptype = 'e'
@@ -159,54 +376,38 @@ fn get_functions_from_db() -> Result<Vec<(String, String)>> {
                     WHERE t.tgfoid = p.oid
                 )
              ORDER BY function_name;",
-        ])
-        .output()
-        .context("Failed to execute psql command to get function names")?;
-    let function_names = String::from_utf8(function_names.stdout)
+    )
+    .context("Failed to execute psql command to get function names")?;
+    let function_names = String::from_utf8(query_stdout(function_names, "function names")?)
         .context("Failed to convert function names output to UTF-8")?
         .lines()
         .map(|line| line.trim().to_string())
         .filter(|line| !line.is_empty())
         .collect::<Vec<String>>();
 
-    let processes = function_names.iter().map(|name| {
-        ProcessCommand::new("psql")
-            .args(&[
-                "-t",
-                "-A",
-                "-c",
-                &format!(
-                    "SELECT RTRIM(pg_get_functiondef(p.oid), E'\n') || ';\n' AS function_definition
+    let outputs = function_names.iter().map(|name| {
+        psql(
+            database_url,
+            &["-t", "-A"],
+            &format!(
+                "SELECT RTRIM(pg_get_functiondef(p.oid), E'\n') || ';\n' AS function_definition
                      FROM pg_proc p
                      JOIN pg_namespace n ON p.pronamespace = n.oid
                      WHERE n.nspname = 'public' AND p.proname = '{}';",
-                    name
-                ),
-            ])
-            .stdout(std::process::Stdio::piped())
-            .spawn()
-            .context(format!(
-                "Failed to spawn psql command for function '{}'",
-                name
-            ))
+                name.replace('\'', "''")
+            ),
+        )
+        .context(format!(
+            "Failed to run psql command for function '{}'",
+            name
+        ))
     });
 
-    let function_contents = processes
-        .map(|process| {
-            process.and_then(|child| {
-                child
-                    .wait_with_output()
-                    .context("Failed to wait for psql command output")
-            })
-        })
+    let function_contents = outputs
         .map(|output| {
-            output.map(|o| {
-                String::from_utf8_lossy(&o.stdout)
-                    .lines()
-                    .map(|line| line.trim_end())
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            })
+            output
+                .and_then(|o| query_stdout(o, "a function"))
+                .map(|stdout| psql_value(&stdout))
         })
         .collect::<Result<Vec<_>, _>>()
         .context("Failed to collect function contents")?;
@@ -220,12 +421,11 @@ fn get_functions_from_db() -> Result<Vec<(String, String)>> {
     Ok(functions)
 }
 
-fn get_views_from_db() -> Result<Vec<(String, String)>> {
-    let view_names = ProcessCommand::new("psql")
-        .args(&[
-            "-t",
-            "-c",
-            "SELECT c.relname AS view_name
+fn get_views_from_db(database_url: Option<&str>) -> Result<Vec<(String, String)>> {
+    let view_names = psql(
+        database_url,
+        &["-t"],
+        "SELECT c.relname AS view_name
             FROM pg_class c
             JOIN pg_namespace n ON n.oid = c.relnamespace
             LEFT JOIN pg_depend d ON d.objid = c.oid AND d.deptype = 'e'
@@ -234,45 +434,35 @@ fn get_views_from_db() -> Result<Vec<(String, String)>> {
               AND d.objid IS NULL 
               AND c.relname NOT LIKE 'pg_%'
             ORDER BY c.relname;",
-        ])
-        .output()
-        .context("Failed to execute psql command to get view names")?;
-    let view_names = String::from_utf8(view_names.stdout)
+    )
+    .context("Failed to execute psql command to get view names")?;
+    let view_names = String::from_utf8(query_stdout(view_names, "view names")?)
         .context("Failed to convert view names output to UTF-8")?
         .lines()
         .map(|line| line.trim().to_string())
         .filter(|line| !line.is_empty())
         .collect::<Vec<String>>();
 
-    let processes = view_names.iter().map(|name| {
-        ProcessCommand::new("psql")
-            .args(&[
-                "-t",
-                "-A",
-                "-c",
-                &format!("SELECT pg_get_viewdef('{}') AS view_definition;", name),
-            ])
-            .stdout(std::process::Stdio::piped())
-            .spawn()
-            .context(format!("Failed to spawn psql command for view '{}'", name))
+    let outputs = view_names.iter().map(|name| {
+        psql(
+            database_url,
+            &["-t", "-A"],
+            // Pretty-printed, as it is read and edited in the file. The
+            // output is stable, so re-extracting an applied view gives the
+            // same file.
+            &format!(
+                "SELECT pg_get_viewdef(to_regclass(quote_ident('{}')), true) AS view_definition;",
+                name.replace('\'', "''")
+            ),
+        )
+        .context(format!("Failed to run psql command for view '{}'", name))
     });
 
-    let view_contents = processes
-        .map(|process| {
-            process.and_then(|child| {
-                child
-                    .wait_with_output()
-                    .context("Failed to wait for psql command output")
-            })
-        })
+    let view_contents = outputs
         .map(|output| {
-            output.map(|o| {
-                String::from_utf8_lossy(&o.stdout)
-                    .lines()
-                    .map(|line| line.trim_end())
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            })
+            output
+                .and_then(|o| query_stdout(o, "a view"))
+                .map(|stdout| psql_value(&stdout))
         })
         .collect::<Result<Vec<_>, _>>()
         .context("Failed to collect view contents")?;
@@ -282,14 +472,212 @@ fn get_views_from_db() -> Result<Vec<(String, String)>> {
         .into_iter()
         .zip(view_contents)
         .map(|(name, content)| {
-            let view_definition = format!("CREATE OR REPLACE VIEW {name} AS\n{content}");
+            let view_definition = format!("CREATE OR REPLACE VIEW {name} AS\n{content}\n");
             (name, view_definition)
         })
         .collect::<Vec<_>>();
     Ok(views)
 }
 
-pub fn init(pgm_dir_path: &str, existing_db: bool) -> Result<()> {
+/// Extracts the named CHECK and foreign key constraints as
+/// `(table.constraint, statements)` pairs, where the statements drop and
+/// re-add the constraint so they can be re-run when the file changes. The
+/// table and constraint names are quoted where needed, e.g. `users."PosId"`,
+/// so the statements match the constraint the baseline created.
+fn get_constraints_from_db(database_url: Option<&str>) -> Result<Vec<(String, String)>> {
+    let output = psql(
+        database_url,
+        &["-t", "-A", "-F", "\t"],
+        "SELECT c.conrelid::regclass, quote_ident(c.conname), pg_get_constraintdef(c.oid)
+             FROM pg_constraint c
+             JOIN pg_namespace n ON n.oid = c.connamespace
+             LEFT JOIN pg_depend d ON d.objid = c.conrelid AND d.deptype = 'e'
+             WHERE c.contype IN ('c', 'f')
+               AND c.conrelid <> 0
+               AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+               AND d.objid IS NULL
+             ORDER BY 1, 2;",
+    )
+    .context("Failed to execute psql command to get constraints")?;
+
+    let constraints = String::from_utf8(query_stdout(output, "constraints")?)
+        .context("Failed to convert constraints output to UTF-8")?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some((fields.next()?, fields.next()?, fields.next()?))
+        })
+        .map(|(table, name, definition)| {
+            let file_name = format!("{table}.{name}");
+            let statements = format!(
+                "{}ALTER TABLE {table} ADD CONSTRAINT {name} {definition};\n",
+                drop_statements("constraints", &file_name, &[])?
+            );
+            Ok((file_name, statements))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(constraints)
+}
+
+/// Extracts the comments on tables, views and their columns from
+/// `pg_description`, as `(table, statements)` pairs with one `COMMENT ON`
+/// statement per comment, the table's first.
+fn get_comments_from_db(database_url: Option<&str>) -> Result<Vec<(String, String)>> {
+    // Rows end with a NUL byte rather than a newline, as comments may span
+    // lines
+    let output = psql(
+        database_url,
+        &["-t", "-A", "-0", "-F", "\t"],
+        "SELECT c.oid::regclass, string_agg(
+                 CASE WHEN d.objsubid = 0
+                 THEN format('COMMENT ON %s %s IS %L;',
+                      CASE c.relkind WHEN 'v' THEN 'VIEW' WHEN 'm' THEN 'MATERIALIZED VIEW'
+                      WHEN 'f' THEN 'FOREIGN TABLE' ELSE 'TABLE' END,
+                      c.oid::regclass, d.description)
+                 ELSE format('COMMENT ON COLUMN %s.%I IS %L;', c.oid::regclass, a.attname, d.description)
+                 END, E'\\n' ORDER BY d.objsubid)
+             FROM pg_description d
+             JOIN pg_class c ON c.oid = d.objoid AND d.classoid = 'pg_class'::regclass
+             JOIN pg_namespace n ON n.oid = c.relnamespace
+             LEFT JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum = d.objsubid
+             LEFT JOIN pg_depend e ON e.objid = c.oid AND e.deptype = 'e'
+             WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+               AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+               AND e.objid IS NULL
+             GROUP BY c.oid
+             ORDER BY 1;",
+    )
+    .context("Failed to execute psql command to get comments")?;
+
+    let comments = String::from_utf8(query_stdout(output, "comments")?)
+        .context("Failed to convert comments output to UTF-8")?
+        .split('\0')
+        .filter_map(|row| {
+            let (table, statements) = row.trim_start_matches('\n').split_once('\t')?;
+            Some((table.to_string(), format!("{}\n", statements)))
+        })
+        .collect();
+
+    Ok(comments)
+}
+
+/// Extracts the installed extensions but plpgsql, which every database has,
+/// as `(name, settings)` pairs, where the settings pin the extension to its
+/// schema and version.
+fn get_extensions_from_db(database_url: Option<&str>) -> Result<Vec<(String, String)>> {
+    let output = psql(
+        database_url,
+        &["-t", "-A", "-F", "\t"],
+        "SELECT e.extname, n.nspname, e.extversion
+             FROM pg_extension e
+             JOIN pg_namespace n ON n.oid = e.extnamespace
+             WHERE e.extname <> 'plpgsql'
+             ORDER BY 1;",
+    )
+    .context("Failed to execute psql command to get extensions")?;
+
+    let extensions = String::from_utf8(query_stdout(output, "extensions")?)
+        .context("Failed to convert extensions output to UTF-8")?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            Some((fields.next()?, fields.next()?, fields.next()?))
+        })
+        .map(|(name, schema, version)| {
+            (
+                name.to_string(),
+                format!("-- pgm: schema={schema}; version={version}\n"),
+            )
+        })
+        .collect();
+
+    Ok(extensions)
+}
+
+/// Copies the schema dump to the migrations directory, gzip-compressed if
+/// requested.
+fn write_initial_migration(
+    pgm_dir_path: &str,
+    initial_migration_file: &NamedTempFile,
+    compress: bool,
+) -> Result<()> {
+    let migrations_dir = Path::new(pgm_dir_path).join("migrations");
+    if compress {
+        let compressed_file = std::fs::File::create(
+            migrations_dir.join(format!("{}.gz", INITIAL_MIGRATION_FILE_NAME)),
+        )
+        .context("Failed to create compressed schema dump in migrations directory")?;
+        let mut encoder = GzEncoder::new(compressed_file, Compression::default());
+        std::io::copy(
+            &mut std::fs::File::open(initial_migration_file.path())?,
+            &mut encoder,
+        )
+        .context("Failed to compress schema dump")?;
+        encoder.finish().context("Failed to compress schema dump")?;
+    } else {
+        std::fs::copy(
+            initial_migration_file.path(),
+            migrations_dir.join(INITIAL_MIGRATION_FILE_NAME),
+        )
+        .context("Failed to copy schema dump to migrations directory")?;
+    }
+    Ok(())
+}
+
+/// Options for [`init`].
+#[derive(Debug, Clone)]
+pub struct InitOptions {
+    /// The pgm directory to create
+    pub path: String,
+    /// Extracts the schema, extensions, functions, triggers and views of an
+    /// existing database instead of creating an empty directory
+    pub existing_db: bool,
+    /// Writes the initial migration gzip-compressed
+    pub compress: bool,
+    /// Writes the initial migration as a `pg_dump` custom-format archive,
+    /// `00000.dump`, which apply restores with `pg_restore`
+    pub custom_format: bool,
+    /// Also extracts CHECK and foreign key constraints
+    pub constraints: bool,
+    /// Also extracts the comments on tables, views and their columns into
+    /// `comments/`, one file per table
+    pub comments: bool,
+    /// Connects to this database instead of using the PG* environment variables
+    pub database_url: Option<String>,
+    /// Extra arguments passed through to pg_dump
+    pub pg_dump_args: Vec<String>,
+    /// Makes the initial migration from this plain-format `pg_dump` output
+    /// instead of connecting to a database. Functions, triggers and views are
+    /// not extracted into their own files.
+    pub from_dump: Option<String>,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_PGM_PATH.to_string(),
+            existing_db: false,
+            compress: false,
+            custom_format: false,
+            constraints: false,
+            comments: false,
+            database_url: None,
+            pg_dump_args: Vec::new(),
+            from_dump: None,
+        }
+    }
+}
+
+/// Creates the pgm directory, extracting an existing database into it if
+/// `existing_db` is set, or only its schema dump if `from_dump` is set.
+pub fn init(options: &InitOptions) -> Result<()> {
+    let pgm_dir_path = options.path.as_str();
+    let database_url = options.database_url.as_deref();
+    let pg_dump_args = options.pg_dump_args.as_slice();
+    let existing_db = options.existing_db;
+    let compress = options.compress;
+    let constraints = options.constraints;
     if Path::new(pgm_dir_path).exists() {
         return Err(anyhow::anyhow!(
             "Directory '{}' already exists",
@@ -297,29 +685,68 @@ pub fn init(pgm_dir_path: &str, existing_db: bool) -> Result<()> {
         ));
     }
 
-    if existing_db {
+    if let Some(dump_path) = &options.from_dump {
+        let initial_migration_file = get_initial_migration_from_dump(dump_path)?;
+        create_directory_structure(pgm_dir_path)?;
+        write_initial_migration(pgm_dir_path, &initial_migration_file, compress)?;
+    } else if existing_db {
+        check_program(&pg_dump_program(), "PGM_PG_DUMP")?;
+        check_program(&psql_program(), "PGM_PSQL")?;
+
         // Call get_initial_migration_from_db to get schema-only dump
-        let initial_migration_file = get_initial_migration_from_db()?;
+        let initial_migration_file = if options.custom_format {
+            dump_schema(database_url, &["--format=custom"], pg_dump_args)?
+        } else {
+            get_initial_migration_from_db(database_url, pg_dump_args)?
+        };
 
         // Get functions from the database
-        let functions = get_functions_from_db()?;
+        let functions = get_functions_from_db(database_url)?;
 
         // Get triggers from the database
-        let triggers = get_triggers_from_db()?;
+        let triggers = get_triggers_from_db(database_url)?;
 
         // Get views from the database
-        let views = get_views_from_db()?;
+        let views = get_views_from_db(database_url)?;
+
+        // Get extensions from the database
+        let extensions = get_extensions_from_db(database_url)?;
+
+        // Get constraints from the database if requested
+        let constraints = if constraints {
+            get_constraints_from_db(database_url)?
+        } else {
+            Vec::new()
+        };
+
+        // Get comments from the database if requested
+        let comments = if options.comments {
+            get_comments_from_db(database_url)?
+        } else {
+            Vec::new()
+        };
+
+        log::debug!(
+            "Found {} functions, {} triggers and {} views",
+            functions.len(),
+            triggers.len(),
+            views.len()
+        );
 
         // Create directory structure
         create_directory_structure(pgm_dir_path)?;
 
-        // Copy schema dump to migrations directory
-        let migrations_dir = Path::new(pgm_dir_path).join("migrations");
-        std::fs::copy(
-            initial_migration_file,
-            migrations_dir.join(INITIAL_MIGRATION_FILE_NAME),
-        )
-        .context("Failed to copy schema dump to migrations directory")?;
+        if options.custom_format {
+            std::fs::copy(
+                initial_migration_file.path(),
+                Path::new(pgm_dir_path)
+                    .join("migrations")
+                    .join(CUSTOM_FORMAT_MIGRATION_FILE_NAME),
+            )
+            .context("Failed to copy schema dump to migrations directory")?;
+        } else {
+            write_initial_migration(pgm_dir_path, &initial_migration_file, compress)?;
+        }
 
         // Write all function to functions directory
         let functions_dir = Path::new(pgm_dir_path).join("functions");
@@ -344,6 +771,44 @@ pub fn init(pgm_dir_path: &str, existing_db: bool) -> Result<()> {
             std::fs::write(view_file, content)
                 .context(format!("Failed to write view '{}' to file", name))?;
         }
+
+        // Write all extensions to extensions directory
+        if !extensions.is_empty() {
+            let extensions_dir = Path::new(pgm_dir_path).join("extensions");
+            std::fs::create_dir_all(&extensions_dir)
+                .context("Failed to create extensions directory")?;
+            for (name, content) in extensions {
+                let extension_file = extensions_dir.join(format!("{}.sql", name));
+                std::fs::write(extension_file, content)
+                    .context(format!("Failed to write extension '{}' to file", name))?;
+            }
+        }
+
+        // Write all constraints to constraints directory
+        if !constraints.is_empty() {
+            let constraints_dir = Path::new(pgm_dir_path).join("constraints");
+            std::fs::create_dir_all(&constraints_dir)
+                .context("Failed to create constraints directory")?;
+            for (name, content) in constraints {
+                let constraint_file = constraints_dir.join(format!("{}.sql", name));
+                std::fs::write(constraint_file, content)
+                    .context(format!("Failed to write constraint '{}' to file", name))?;
+            }
+        }
+
+        // Write the comments of each table to comments directory
+        if !comments.is_empty() {
+            let comments_dir = Path::new(pgm_dir_path).join("comments");
+            std::fs::create_dir_all(&comments_dir)
+                .context("Failed to create comments directory")?;
+            for (table, content) in comments {
+                let comments_file = comments_dir.join(format!("{}.sql", table));
+                std::fs::write(comments_file, content).context(format!(
+                    "Failed to write the comments on '{}' to file",
+                    table
+                ))?;
+            }
+        }
     } else {
         // Create directory structure without using pg_dump
         create_directory_structure(pgm_dir_path)?;
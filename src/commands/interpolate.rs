@@ -0,0 +1,57 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Expands the `${NAME}` and `${NAME:-default}` references to environment
+/// variables in `content`, the file at `path`. `\${` stands for a literal
+/// `${`, and a `${...}` not holding a variable name, such as the `${"a": 1}`
+/// of a JSON value, is left as it is. Fails naming every variable that is
+/// not set and has no default.
+pub(crate) fn interpolate(path: &Path, content: &str) -> Result<String> {
+    let mut interpolated = String::with_capacity(content.len());
+    let mut missing = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('\\') {
+            interpolated.push_str(&rest[..start - 1]);
+            interpolated.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        interpolated.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find('}') else {
+            interpolated.push_str("${");
+            continue;
+        };
+        let (name, default) = match rest[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&rest[..end], None),
+        };
+        if !is_variable_name(name) {
+            interpolated.push_str("${");
+            continue;
+        }
+        match (std::env::var(name), default) {
+            (Ok(value), _) => interpolated.push_str(&value),
+            (Err(_), Some(default)) => interpolated.push_str(default),
+            (Err(_), None) if !missing.contains(&name) => missing.push(name),
+            (Err(_), None) => {}
+        }
+        rest = &rest[end + 1..];
+    }
+    interpolated.push_str(rest);
+
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "{}: not set in the environment: {}; give a default with ${{NAME:-default}} to make a variable optional",
+            path.display(),
+            missing.join(", ")
+        ));
+    }
+    Ok(interpolated)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
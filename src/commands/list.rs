@@ -0,0 +1,87 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::apply::{category_files, CATEGORIES};
+use super::describe::get_records;
+use super::disabled::Disabled;
+use super::source::Source;
+use crate::DEFAULT_PGM_PATH;
+
+/// Options for [`list`].
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// The pgm directory
+    pub path: String,
+    /// The categories to list, out of [`CATEGORIES`]
+    pub categories: Vec<String>,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_PGM_PATH.to_string(),
+            categories: CATEGORIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+/// A file pgm manages, as found on disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManagedObject {
+    pub category: String,
+    /// The name the object is tracked under
+    pub name: String,
+    pub path: PathBuf,
+    /// Whether a migration has been applied, if the database could be reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applied: Option<bool>,
+    /// When the object was last applied, in RFC 3339, if the database records
+    /// it. For functions, triggers and views that is the last apply that
+    /// changed them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub applied_at: Option<String>,
+    /// Whether `disabled.txt` leaves the object out of `build`
+    pub disabled: bool,
+}
+
+/// Lists the objects `build` would pick up, in the order it compiles them.
+/// Nothing but what the pgm tables record about them needs the database, and
+/// that is left out if it cannot be reached.
+pub fn list(options: &ListOptions) -> Result<Vec<ManagedObject>> {
+    if !Path::new(&options.path).is_dir() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            options.path
+        ));
+    }
+
+    let records = get_records();
+
+    let source = Source::directory(PathBuf::from(&options.path));
+    let disabled = Disabled::read(&source)?;
+    let mut objects = Vec::new();
+    for category in CATEGORIES {
+        if !options.categories.iter().any(|c| c == category) {
+            continue;
+        }
+        for (name, path) in category_files(&source, category)? {
+            let recorded = records
+                .as_ref()
+                .map(|records| records.get(&(category.to_string(), name.clone())));
+            objects.push(ManagedObject {
+                category: category.to_string(),
+                applied: recorded
+                    .filter(|_| category == "migrations")
+                    .map(|record| record.is_some()),
+                applied_at: recorded
+                    .flatten()
+                    .and_then(|record| record.applied_at.clone()),
+                disabled: disabled.disables(category, &name),
+                name,
+                path,
+            });
+        }
+    }
+    Ok(objects)
+}
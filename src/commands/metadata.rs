@@ -0,0 +1,162 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Settings for a single file, read from `-- pgm:` comment lines at its top,
+/// e.g. `-- pgm: depends=[other_view]; schema=app`. Postgres ignores them
+/// like any other comment.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Metadata {
+    /// Files of the same category to apply before this one, by name
+    pub(crate) depends: Vec<String>,
+    /// The schema the file creates its objects in, put first in the search
+    /// path while it runs
+    pub(crate) schema: Option<String>,
+    /// Asks for the file to run outside of a transaction, which pgm cannot do
+    pub(crate) no_transaction: bool,
+    /// Creates the functions of the file without checking their bodies, also
+    /// in the pass that checks all others, e.g. for a function referring to a
+    /// table another tool creates later
+    pub(crate) no_body_check: bool,
+    /// The version to install or update an extension to
+    pub(crate) version: Option<String>,
+    /// The migrations a baseline written by `squash` replaces, by name
+    pub(crate) squashes: Vec<String>,
+    /// Drops the object before creating it again, e.g. to change the return
+    /// type of a function, which `CREATE OR REPLACE` refuses
+    pub(crate) drop_first: Option<DropFirst>,
+    /// Drops a view with `CASCADE` and creates it again, only when `CREATE OR
+    /// REPLACE` refuses the change, e.g. a removed or renamed column
+    pub(crate) recreate: bool,
+    /// Expands the `${NAME}` references to environment variables in a
+    /// migration, as in every seed
+    pub(crate) interpolate: bool,
+    /// Entries that were not understood
+    pub(crate) unknown: Vec<String>,
+}
+
+/// How a file with `drop-first` drops its object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DropFirst {
+    /// Fails if other objects depend on it
+    Restrict,
+    /// Also drops the objects depending on it
+    Cascade,
+}
+
+impl Metadata {
+    /// Parses the `-- pgm:` lines of the leading comments of `content`.
+    /// Unknown or malformed entries are collected in `unknown`.
+    pub(crate) fn parse(content: &str) -> Self {
+        let mut metadata = Self::default();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() {
+                continue;
+            }
+            let Some(comment) = line.strip_prefix("--") else {
+                break;
+            };
+            let Some(entries) = comment.trim().strip_prefix("pgm:") else {
+                continue;
+            };
+            for entry in entries.split(';').map(str::trim) {
+                let (key, value) = match entry.split_once('=') {
+                    Some((key, value)) => (key.trim(), Some(value.trim())),
+                    None => (entry, None),
+                };
+                match (key, value) {
+                    ("", None) => {}
+                    ("depends", Some(value)) => metadata.depends.extend(names(value)),
+                    ("squashes", Some(value)) => metadata.squashes.extend(names(value)),
+                    ("schema", Some(value)) if !value.is_empty() => {
+                        metadata.schema = Some(value.to_string())
+                    }
+                    ("no-transaction", None) => metadata.no_transaction = true,
+                    ("no-body-check", None) => metadata.no_body_check = true,
+                    ("interpolate", None) => metadata.interpolate = true,
+                    ("recreate", None) => metadata.recreate = true,
+                    ("drop-first", None) => metadata.drop_first = Some(DropFirst::Restrict),
+                    ("drop-first cascade", None) => metadata.drop_first = Some(DropFirst::Cascade),
+                    ("version", Some(value)) if !value.is_empty() => {
+                        metadata.version = Some(value.to_string())
+                    }
+                    _ => metadata.unknown.push(entry.to_string()),
+                }
+            }
+        }
+        metadata
+    }
+}
+
+/// Parses a list of names such as `[a, b]`.
+fn names(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Orders `files`, given as `(name, path, metadata)` in file name order, so
+/// that every file comes after the files it depends on, and otherwise keeps
+/// their order. Dependencies on names not in `files` are left to the order of
+/// the categories.
+pub(crate) fn order_by_dependencies(
+    files: Vec<(String, PathBuf, Metadata)>,
+) -> Result<Vec<PathBuf>> {
+    let index = files
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _, _))| (name.to_lowercase(), i))
+        .collect::<HashMap<_, _>>();
+    let mut ordered = Vec::with_capacity(files.len());
+    let mut visited = vec![false; files.len()];
+    let mut visiting = Vec::new();
+
+    fn visit(
+        i: usize,
+        files: &[(String, PathBuf, Metadata)],
+        index: &HashMap<String, usize>,
+        visited: &mut [bool],
+        visiting: &mut Vec<usize>,
+        ordered: &mut Vec<PathBuf>,
+    ) -> Result<()> {
+        if visited[i] {
+            return Ok(());
+        }
+        if let Some(start) = visiting.iter().position(|&v| v == i) {
+            let cycle = visiting[start..]
+                .iter()
+                .chain([&i])
+                .map(|&v| files[v].0.as_str())
+                .collect::<Vec<_>>();
+            return Err(anyhow::anyhow!(
+                "Files depend on each other in a cycle: {}",
+                cycle.join(" -> ")
+            ));
+        }
+        visiting.push(i);
+        let (name, path, metadata) = &files[i];
+        for dependency in &metadata.depends {
+            match index.get(&dependency.to_lowercase()) {
+                Some(&d) => visit(d, files, index, visited, visiting, ordered)?,
+                None => log::debug!(
+                    "{} depends on '{}', which is not in the same directory",
+                    name,
+                    dependency
+                ),
+            }
+        }
+        visiting.pop();
+        visited[i] = true;
+        ordered.push(path.clone());
+        Ok(())
+    }
+
+    for i in 0..files.len() {
+        visit(i, &files, &index, &mut visited, &mut visiting, &mut ordered)?;
+    }
+    Ok(ordered)
+}
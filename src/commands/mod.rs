@@ -1,9 +1,33 @@
 mod apply;
+mod balance;
 mod create;
+mod describe;
+mod diff;
+mod disabled;
+mod drop;
 mod init;
+mod interpolate;
+mod list;
+mod metadata;
+mod pgmignore;
+mod plan;
+mod psql;
 mod seed;
+mod source;
+mod squash;
+mod trigger;
+mod verify;
 
 pub use init::*;
 pub use apply::*;
 pub use create::*;
-pub use seed::*;
\ No newline at end of file
+pub use describe::*;
+pub use diff::*;
+pub use list::*;
+pub use plan::*;
+pub use seed::*;
+pub use source::{bundle, Bundle};
+pub use squash::*;
+pub use verify::*;
+
+pub use psql::redact;
\ No newline at end of file
@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// The file at the root of a pgm directory listing, in gitignore syntax, the
+/// files pgm leaves out, e.g. scratch files or `*.bak` copies.
+pub(crate) const PGMIGNORE: &str = ".pgmignore";
+
+/// The patterns of the [`PGMIGNORE`] file of a pgm directory.
+pub(crate) struct PgmIgnore(Gitignore);
+
+impl PgmIgnore {
+    /// Reads the [`PGMIGNORE`] file of the pgm directory at `root`, which
+    /// ignores nothing if there is none.
+    pub(crate) fn read(root: &Path) -> Result<Self> {
+        let path = root.join(PGMIGNORE);
+        if !path.is_file() {
+            return Ok(Self(Gitignore::empty()));
+        }
+        let mut builder = GitignoreBuilder::new(root);
+        if let Some(e) = builder.add(&path) {
+            return Err(e).context(format!("Failed to read '{}'", path.display()));
+        }
+        let gitignore = builder
+            .build()
+            .context(format!("Failed to read '{}'", path.display()))?;
+        Ok(Self(gitignore))
+    }
+
+    /// Returns whether the file at `path`, inside the pgm directory, is
+    /// ignored, either itself or through one of its directories.
+    pub(crate) fn ignores(&self, path: &Path) -> bool {
+        let ignored = self.0.matched_path_or_any_parents(path, false).is_ignore();
+        if ignored {
+            log::debug!("Ignored {}", path.display());
+        }
+        ignored
+    }
+}
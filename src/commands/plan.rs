@@ -0,0 +1,120 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+use super::apply::{build_body, checksum, header, lock_sql, pgm_tables_create_sql, ApplyOptions};
+use super::psql::execute_sql;
+
+/// Prefix of the notice raised with the state of the pgm tables.
+const STATE_NOTICE: &str = "pgm state: ";
+
+/// What an apply would do, made by [`plan`] to be reviewed before it is
+/// applied with [`ApplyOptions::plan`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    /// The version of pgm that made the plan
+    pub version: String,
+    pub created_at: String,
+    /// The [`checksum`] of the SQL the plan was made for
+    pub checksum: String,
+    /// A fingerprint of the names and hashes in the pgm tables at the time,
+    /// which decide what the SQL applies and skips
+    pub state: String,
+    /// The notices raised while trying the SQL, i.e. what would be applied
+    /// and skipped
+    pub notices: Vec<String>,
+}
+
+impl Plan {
+    /// Parses a plan written by [`Plan::to_json`].
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).context("Failed to parse plan")
+    }
+
+    /// Reads a plan from the file at `path`.
+    pub fn read(path: &str) -> Result<Self> {
+        let bytes = std::fs::read(path).context(format!("Failed to read plan '{}'", path))?;
+        Self::from_slice(&bytes).context(format!("Failed to read plan '{}'", path))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Tries the SQL [`apply`](super::apply()) would run for `options` in a
+/// transaction that is rolled back, and returns what it would do. Applying
+/// the plan with [`ApplyOptions::plan`] fails if the SQL or the pgm tables
+/// have changed in the meantime.
+pub fn plan(options: &ApplyOptions) -> Result<Plan> {
+    let options = ApplyOptions {
+        dry_run: false,
+        plan: None,
+        ..options.clone()
+    };
+    let body = build_body(&options)?;
+    let sql = format!(
+        "{}{}{}",
+        header(&body),
+        state_guard_sql(None, options.lock_timeout),
+        body
+    );
+    let notices = execute_sql(
+        &sql,
+        options.database_url.as_deref(),
+        &options.variables,
+        options.progress,
+        options.silent,
+        true,
+        options.keep_temp,
+    )?;
+
+    let state = notices
+        .iter()
+        .find_map(|notice| notice.strip_prefix(STATE_NOTICE))
+        .ok_or_else(|| anyhow::anyhow!("The state of the pgm tables was not reported"))?
+        .to_string();
+    Ok(Plan {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        checksum: checksum(&body),
+        state,
+        notices: notices
+            .into_iter()
+            .filter(|notice| !notice.starts_with(STATE_NOTICE))
+            .collect(),
+    })
+}
+
+/// A DO block run ahead of the compiled SQL, in the same transaction, that
+/// takes the apply lock and then reports the state of the pgm tables, or
+/// fails unless it is `expected_state`.
+pub(crate) fn state_guard_sql(
+    expected_state: Option<&str>,
+    lock_timeout: Option<Duration>,
+) -> String {
+    let state = "(SELECT md5(coalesce(string_agg(format('%s:%s:%s', t, name, hash), ',' ORDER BY t, name), '')) FROM (
+SELECT 'extension' AS t, name, hash FROM pgm_extension
+UNION ALL SELECT 'migration', name, hash FROM pgm_migration
+UNION ALL SELECT 'function', name, hash FROM pgm_function
+UNION ALL SELECT 'trigger', name, hash FROM pgm_trigger
+UNION ALL SELECT 'view', name, hash FROM pgm_view
+UNION ALL SELECT 'constraint', name, hash FROM pgm_constraint
+UNION ALL SELECT 'grant', name, hash FROM pgm_grant
+UNION ALL SELECT 'comment', name, hash FROM pgm_comment) AS objects)";
+    let check = match expected_state {
+        Some(expected_state) => format!(
+            "IF {state} IS DISTINCT FROM '{}' THEN
+RAISE EXCEPTION 'The database changed since the plan was made, as the pgm tables differ. Make a new plan';
+END IF;",
+            expected_state.replace('\'', "''")
+        ),
+        None => format!("RAISE NOTICE '{STATE_NOTICE}%', {state};"),
+    };
+    format!(
+        "DO $pgm$ BEGIN\n{}{}{}\nEND $pgm$;\n",
+        lock_sql(lock_timeout),
+        pgm_tables_create_sql("notice"),
+        check
+    )
+}
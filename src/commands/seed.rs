@@ -1,94 +1,133 @@
-use std::{io::Write, path::Path, process::Command};
+use std::path::Path;
 
 use anyhow::{Context, Result};
-use tempfile::NamedTempFile;
 
-fn process_seed_directory(full_dir_path: &str) -> Result<String> {
+use super::apply::{check_message_level, once_only_ddl, report_once_only_ddl, Markers};
+use super::interpolate::interpolate;
+use super::pgmignore::PgmIgnore;
+use super::psql::{applied_marker, execute_sql};
+use super::source::sql_name;
+use crate::DEFAULT_PGM_PATH;
+
+fn process_seed_directory(full_dir_path: &str, ignore: &PgmIgnore, strict: bool) -> Result<String> {
     let mut entries: Vec<_> = std::fs::read_dir(full_dir_path)?
         .filter_map(|entry| entry.ok())
         .collect();
     
     entries.sort_by_key(|entry| entry.path());
 
-    let mut compiled_content = String::new();
+    let mut seeds = Vec::new();
+    let mut once_only = Vec::new();
     for entry in entries {
         let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "sql") {
-            let content = std::fs::read_to_string(&path)?;
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| sql_name(name, false))
+            .map(str::to_string);
+        if let Some(file_name) = file_name.filter(|_| path.is_file() && !ignore.ignores(&path)) {
+            let content = interpolate(&path, &std::fs::read_to_string(&path)?)?;
 
-            let file_name = path.file_stem().unwrap().to_str().unwrap();
+            log::debug!("Found seed {}", path.display());
+            once_only.extend(once_only_ddl(&path, &content));
 
-            let file_path = format!("{}/{}", full_dir_path, file_name);
-            compiled_content.push_str(&format!(
-                "-- RUN {file_path} --
-{content}
-RAISE NOTICE '✅ Applied seed: {file_name}';
--- DONE {file_path} --
-"
-            ));
+            seeds.push((file_name, content));
         }
     }
-    Ok(compiled_content)
-}
 
-fn execute_sql(sql: &str) -> Result<()> {
-    // Check if psql exists
-    if !Command::new("psql").arg("--version").output().is_ok() {
-        return Err(anyhow::anyhow!(
-            "psql not found. Please ensure it is installed and in your PATH."
+    report_once_only_ddl(&once_only, "on every seed", strict)?;
+
+    let markers = Markers::new(&seeds.iter().map(|(_, content)| content).collect::<Vec<_>>());
+    let mut compiled_content = String::new();
+    for (file_name, content) in seeds {
+        let file_path = format!("{}/{}", full_dir_path, file_name);
+        compiled_content.push_str(&markers.wrap(
+            &file_path,
+            &format!(
+                "{content}\nRAISE NOTICE '{} Applied seed: {file_name}';\n",
+                applied_marker()
+            ),
         ));
     }
+    Ok(compiled_content)
+}
 
-    // Create a temporary file
-    let mut temp_file = NamedTempFile::new().context("Failed to create temporary file")?;
-    temp_file
-        .write_all(sql.as_bytes())
-        .context("Failed to write SQL to temporary file")?;
-
-    // Construct the psql command
-    let mut command = Command::new("psql");
-    command.args(&[
-        "-f",
-        temp_file.path().to_str().unwrap(),
-        "-v",
-        "ON_ERROR_STOP=1",
-    ]);
-
-    let output = command.output().context("Failed to execute psql command")?;
-    let stderr = String::from_utf8_lossy(&output.stderr);
-
-    // Process stderr to remove prefix 'psql:/path/to/temp/file:1234: '
-    stderr.lines().for_each(|line| {
-        println!("{}", line.split_once(": ").map_or(line, |(_, rest)| rest));
-    });
+/// Options for [`seed`].
+#[derive(Debug, Clone)]
+pub struct SeedOptions {
+    /// The pgm directory
+    pub path: String,
+    /// Prints the SQL instead of running it
+    pub dry_run: bool,
+    /// Shows a progress bar instead of the applied lines when stdout is a
+    /// terminal
+    pub progress: bool,
+    /// Leaves out the NOTICEs psql prints for each file
+    pub silent: bool,
+    /// Fails instead of warning when a seed creates, alters or drops a table
+    pub strict: bool,
+    /// Keeps the temporary file with the SQL psql runs, as in
+    /// [`ApplyOptions::keep_temp`](super::ApplyOptions::keep_temp)
+    pub keep_temp: bool,
+    /// The `client_min_messages` to run with, as in
+    /// [`ApplyOptions::messages`](super::ApplyOptions::messages)
+    pub messages: String,
+}
 
-    if output.status.success() {
-        Ok(())
-    } else {
-        let exit_code = output.status.code().unwrap_or(-1);
-        Err(anyhow::anyhow!(
-            "psql command failed with exit code: {}",
-            exit_code
-        ))
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_PGM_PATH.to_string(),
+            dry_run: false,
+            progress: false,
+            silent: false,
+            strict: false,
+            keep_temp: false,
+            messages: "notice".to_string(),
+        }
     }
 }
 
-pub fn seed(pgm_dir_path: &str) -> Result<()> {
+/// Runs every file in the seeds directory, in order, in a single transaction.
+pub fn seed(options: &SeedOptions) -> Result<()> {
+    let pgm_dir_path = options.path.as_str();
     if !Path::new(pgm_dir_path).is_dir() {
         return Err(anyhow::anyhow!(
             "Directory '{}' not found. Have you run 'pgm init'?",
             pgm_dir_path
         ));
     }
+    check_message_level(&options.messages)?;
     let seeds_dir = format!("{}/seeds", pgm_dir_path);
     let seeds_dir = seeds_dir.as_str();
     let mut compiled_content = String::new();
     compiled_content.push_str("DO $pgm_seed$ BEGIN ");
-    compiled_content.push_str("SET LOCAL client_min_messages=NOTICE;");
-    compiled_content
-        .push_str(&process_seed_directory(seeds_dir).context("Failed to process seed directory")?);
+    compiled_content.push_str(&format!(
+        "SET LOCAL client_min_messages={};",
+        options.messages
+    ));
+    let ignore = PgmIgnore::read(Path::new(pgm_dir_path))?;
+    compiled_content.push_str(
+        &process_seed_directory(seeds_dir, &ignore, options.strict)
+            .context("Failed to process seed directory")?,
+    );
     compiled_content.push_str("END $pgm_seed$;");
 
-    execute_sql(&compiled_content).context("Failed to execute seed SQL")?;
+    // Print the SQL and exit if dry-run
+    if options.dry_run {
+        println!("{}", compiled_content);
+        return Ok(());
+    }
+
+    execute_sql(
+        &compiled_content,
+        None,
+        &[],
+        options.progress,
+        options.silent,
+        false,
+        options.keep_temp,
+    )
+    .context("Failed to execute seed SQL")?;
     Ok(())
 }
@@ -0,0 +1,444 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use super::apply::{check_duplicate_names, CATEGORIES};
+use super::pgmignore::PgmIgnore;
+
+/// The files of a pgm directory serialized into one file by [`bundle`], so a
+/// schema can be shipped inside an application, e.g. with `include_bytes!`,
+/// and applied without a directory on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    /// The version of pgm that wrote the bundle
+    pub version: String,
+    /// The content of every file by its path relative to the pgm directory,
+    /// e.g. `functions/f.sql`. Compressed migrations are stored decompressed
+    /// under their `.sql.gz` path, and custom-format archives base64-encoded
+    /// under their `.dump` path.
+    pub files: BTreeMap<String, String>,
+}
+
+impl Bundle {
+    /// Parses a bundle written by [`Bundle::to_json`].
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes).context("Failed to parse bundle")
+    }
+
+    /// Reads a bundle from the file at `path`.
+    pub fn read(path: &str) -> Result<Self> {
+        let bytes = std::fs::read(path).context(format!("Failed to read bundle '{}'", path))?;
+        Self::from_slice(&bytes).context(format!("Failed to read bundle '{}'", path))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Serializes every file in the pgm directory at `pgm_dir_path` into a
+/// [`Bundle`], except those its `.pgmignore` file lists.
+pub fn bundle(pgm_dir_path: &str) -> Result<Bundle> {
+    let source = Source::directory(PathBuf::from(pgm_dir_path));
+    if !source.exists() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            pgm_dir_path
+        ));
+    }
+    let categories = CATEGORIES.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    check_duplicate_names(&source, &categories, false)?;
+
+    let ignore = PgmIgnore::read(Path::new(pgm_dir_path))?;
+    let mut files = BTreeMap::new();
+    add_files(
+        &source,
+        &ignore,
+        Path::new(pgm_dir_path),
+        Path::new(""),
+        &mut files,
+    )?;
+    Ok(Bundle {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        files,
+    })
+}
+
+/// Adds the files in `dir`, at `relative_dir` in the pgm directory, and in
+/// its subdirectories to `files`.
+fn add_files(
+    source: &Source,
+    ignore: &PgmIgnore,
+    dir: &Path,
+    relative_dir: &Path,
+    files: &mut BTreeMap<String, String>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative_path = relative_dir.join(path.file_name().unwrap());
+        if ignore.ignores(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            add_files(source, ignore, &path, &relative_path, files)?;
+        } else {
+            let content = if is_archive(&path) {
+                BASE64.encode(source.read_archive(&path)?)
+            } else {
+                source.read(&path)?
+            };
+            files.insert(relative_path.to_string_lossy().replace('\\', "/"), content);
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether `path` is a `pg_dump` custom-format archive ending in
+/// `.dump`, as written by `init --format custom`. Apply restores it with
+/// `pg_restore` rather than running it as SQL.
+pub(crate) fn is_archive(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "dump")
+}
+
+/// Returns the extensions of the files pgm reads SQL from: `sql`, or those
+/// listed in `PGM_SQL_EXTENSIONS`, e.g. `sql,pgsql,psql`.
+pub(crate) fn sql_extensions() -> Vec<String> {
+    let extensions = std::env::var("PGM_SQL_EXTENSIONS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|extension| extension.trim().trim_start_matches('.').to_string())
+        .filter(|extension| !extension.is_empty())
+        .collect::<Vec<_>>();
+    if extensions.is_empty() {
+        vec!["sql".to_string()]
+    } else {
+        extensions
+    }
+}
+
+/// Returns the name of an object or migration file, which is `file_name`
+/// without its SQL extension and, if `compressed` files are allowed, a `.gz`
+/// suffix, or `None` if it has none of the [`sql_extensions`]. Compressed
+/// files also include `pg_dump` custom-format archives, see [`is_archive`].
+pub(crate) fn sql_name(file_name: &str, compressed: bool) -> Option<&str> {
+    if let Some(name) = file_name.strip_suffix(".dump").filter(|_| compressed) {
+        return Some(name);
+    }
+    let file_name = match file_name.strip_suffix(".gz") {
+        Some(uncompressed) if compressed => uncompressed,
+        _ => file_name,
+    };
+    let (name, extension) = file_name.rsplit_once('.')?;
+    sql_extensions()
+        .iter()
+        .any(|sql_extension| sql_extension == extension)
+        .then_some(name)
+}
+
+/// Returns the path of an include line, `-- pgm:include <path>`, with the
+/// path relative to the including file, e.g. `../shared/common.sql`.
+fn include_path(line: &str) -> Option<&str> {
+    let directive = line.trim().strip_prefix("--")?.trim_start();
+    let path = directive
+        .strip_prefix("pgm:")?
+        .trim_start()
+        .strip_prefix("include")?;
+    if !path.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(path.trim()).filter(|path| !path.is_empty())
+}
+
+/// Resolves the `..` components of `path` without touching the file system,
+/// as files in a bundle only exist under their path.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Where `build` reads the files of a pgm directory from. Paths are always
+/// under [`Source::root`], so messages look the same for both origins.
+pub(crate) struct Source<'a> {
+    pub(crate) origin: Origin<'a>,
+    /// The content of the files read so far by path, so each file is only
+    /// read once
+    contents: RefCell<HashMap<PathBuf, String>>,
+}
+
+/// The files a [`Source`] reads.
+pub(crate) enum Origin<'a> {
+    Directory(PathBuf),
+    /// A bundle, with its files shown under `root`
+    Bundle {
+        root: PathBuf,
+        bundle: &'a Bundle,
+    },
+}
+
+impl<'a> Source<'a> {
+    /// Reads the pgm directory at `root`.
+    pub(crate) fn directory(root: PathBuf) -> Self {
+        Self::new(Origin::Directory(root))
+    }
+
+    /// Reads the files of `bundle`, shown under `root`.
+    pub(crate) fn bundle(root: PathBuf, bundle: &'a Bundle) -> Self {
+        Self::new(Origin::Bundle { root, bundle })
+    }
+
+    fn new(origin: Origin<'a>) -> Self {
+        Self {
+            origin,
+            contents: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        match &self.origin {
+            Origin::Directory(root) | Origin::Bundle { root, .. } => root,
+        }
+    }
+
+    pub(crate) fn exists(&self) -> bool {
+        match &self.origin {
+            Origin::Directory(root) => root.is_dir(),
+            Origin::Bundle { .. } => true,
+        }
+    }
+
+    /// Returns whether the directory `dir`, relative to the root, exists.
+    pub(crate) fn has_dir(&self, dir: &str) -> bool {
+        match &self.origin {
+            Origin::Directory(root) => root.join(dir).is_dir(),
+            Origin::Bundle { bundle, .. } => bundle.files.keys().any(|path| {
+                path.strip_prefix(dir)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            }),
+        }
+    }
+
+    /// Returns whether there is a file at `path`, as returned by
+    /// [`Source::files`], without listing its directory.
+    pub(crate) fn has_file(&self, path: &Path) -> Result<bool> {
+        match &self.origin {
+            Origin::Directory(root) => Ok(path.is_file() && !PgmIgnore::read(root)?.ignores(path)),
+            Origin::Bundle { root, bundle } => {
+                Ok(path.strip_prefix(root).is_ok_and(|relative_path| {
+                    bundle
+                        .files
+                        .contains_key(&relative_path.to_string_lossy().replace('\\', "/"))
+                }))
+            }
+        }
+    }
+
+    /// Returns the paths of the files directly in the directory `dir`,
+    /// relative to the root, in no particular order. Files the `.pgmignore`
+    /// file of a directory lists are left out.
+    pub(crate) fn files(&self, dir: &str) -> Result<Vec<PathBuf>> {
+        match &self.origin {
+            Origin::Directory(root) => {
+                let ignore = PgmIgnore::read(root)?;
+                let mut files = std::fs::read_dir(root.join(dir))?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                files.retain(|path| path.is_file() && !ignore.ignores(path));
+                Ok(files)
+            }
+            Origin::Bundle { root, bundle } => Ok(bundle
+                .files
+                .keys()
+                .filter(|path| {
+                    path.rsplit_once('/')
+                        .is_some_and(|(parent, _)| parent == dir)
+                })
+                .map(|path| root.join(path))
+                .collect()),
+        }
+    }
+
+    /// Returns the names of the directories directly in the directory `dir`,
+    /// relative to the root, sorted. Hidden directories, such as `.git`, are
+    /// left out.
+    pub(crate) fn dirs(&self, dir: &str) -> Result<Vec<String>> {
+        let mut dirs = match &self.origin {
+            Origin::Directory(root) => {
+                let mut dirs = Vec::new();
+                for entry in std::fs::read_dir(root.join(dir))? {
+                    let path = entry?.path();
+                    if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                        if path.is_dir() {
+                            dirs.push(name.to_string());
+                        }
+                    }
+                }
+                dirs
+            }
+            Origin::Bundle { bundle, .. } => bundle
+                .files
+                .keys()
+                .filter_map(|path| path.strip_prefix(dir)?.strip_prefix('/')?.split_once('/'))
+                .map(|(dir, _)| dir.to_string())
+                .collect(),
+        };
+        dirs.retain(|dir| !dir.starts_with('.'));
+        dirs.sort();
+        dirs.dedup();
+        Ok(dirs)
+    }
+
+    /// Reads the file at `path`, as returned by [`Source::files`],
+    /// transparently decompressing `.gz` files. A `.dump` archive has no SQL
+    /// to read and reads as empty; see [`Source::read_archive`]. Line endings
+    /// are normalized to LF and a leading byte order mark is dropped, so a file hashes and
+    /// compiles the same whatever editor or checkout settings wrote it.
+    /// `-- pgm:include <path>` lines are replaced by the file they name, see
+    /// [`include_path`], so a change to an included file changes the hash of
+    /// every file including it. Each file is only read once; later reads
+    /// return the same content.
+    pub(crate) fn read(&self, path: &Path) -> Result<String> {
+        if let Some(content) = self.contents.borrow().get(path) {
+            return Ok(content.clone());
+        }
+        let content = self.read_including(path, &mut Vec::new())?;
+        self.contents
+            .borrow_mut()
+            .insert(path.to_path_buf(), content.clone());
+        Ok(content)
+    }
+
+    /// Reads the bytes of the `.dump` archive at `path`, see [`is_archive`].
+    pub(crate) fn read_archive(&self, path: &Path) -> Result<Vec<u8>> {
+        match &self.origin {
+            Origin::Directory(_) => {
+                std::fs::read(path).context(format!("Failed to read '{}'", path.display()))
+            }
+            Origin::Bundle { root, bundle } => {
+                let content = path
+                    .strip_prefix(root)
+                    .ok()
+                    .and_then(|relative_path| {
+                        bundle
+                            .files
+                            .get(&relative_path.to_string_lossy().replace('\\', "/"))
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("'{}' is not in the bundle", path.display()))?;
+                BASE64
+                    .decode(content)
+                    .context(format!("'{}' in the bundle is not base64", path.display()))
+            }
+        }
+    }
+
+    /// Returns the md5 hash the file at `path` is recorded under: of its
+    /// content as [`Source::read`] returns it, or of the bytes of a `.dump`
+    /// archive, so it does not depend on the `pg_restore` version.
+    pub(crate) fn hash(&self, path: &Path) -> Result<String> {
+        if is_archive(path) {
+            return Ok(format!("{:x}", md5::compute(self.read_archive(path)?)));
+        }
+        Ok(format!("{:x}", md5::compute(self.read(path)?)))
+    }
+
+    /// Reads the file at `path` as [`Source::read`] does, while the files in
+    /// `including` are being expanded.
+    fn read_including(&self, path: &Path, including: &mut Vec<PathBuf>) -> Result<String> {
+        let content = self.read_raw(path)?;
+        let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
+        let content = content.replace("\r\n", "\n");
+        if !content.lines().any(|line| include_path(line).is_some()) {
+            return Ok(content);
+        }
+
+        including.push(path.to_path_buf());
+        let mut expanded = String::with_capacity(content.len());
+        for line in content.split_inclusive('\n') {
+            let Some(relative_path) = include_path(line) else {
+                expanded.push_str(line);
+                continue;
+            };
+            let included = normalize_path(&path.parent().unwrap_or(path).join(relative_path));
+            if including.contains(&included) {
+                let cycle = including
+                    .iter()
+                    .skip_while(|path| **path != included)
+                    .chain([&included])
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>();
+                return Err(anyhow::anyhow!(
+                    "Files include each other in a cycle: {}",
+                    cycle.join(" -> ")
+                ));
+            }
+            let included_content = self.read_including(&included, including).context(format!(
+                "Failed to include '{}' in '{}'",
+                relative_path,
+                path.display()
+            ))?;
+            expanded.push_str(&included_content);
+            if !included_content.ends_with('\n') {
+                expanded.push('\n');
+            }
+        }
+        including.pop();
+        Ok(expanded)
+    }
+
+    fn read_raw(&self, path: &Path) -> Result<String> {
+        let utf8 = |bytes: Vec<u8>| {
+            String::from_utf8(bytes).map_err(|e| {
+                anyhow::anyhow!(
+                    "'{}' is not valid UTF-8 (at byte {}); save it with UTF-8 encoding",
+                    path.display(),
+                    e.utf8_error().valid_up_to()
+                )
+            })
+        };
+        if is_archive(path) {
+            return Ok(String::new());
+        }
+        match &self.origin {
+            Origin::Directory(_) if path.extension().is_some_and(|ext| ext == "gz") => {
+                let file = std::fs::File::open(path)
+                    .context(format!("Failed to open '{}'", path.display()))?;
+                let mut content = Vec::new();
+                GzDecoder::new(file)
+                    .read_to_end(&mut content)
+                    .context(format!("Failed to decompress '{}'", path.display()))?;
+                utf8(content)
+            }
+            Origin::Directory(_) => {
+                utf8(std::fs::read(path).context(format!("Failed to read '{}'", path.display()))?)
+            }
+            Origin::Bundle { root, bundle } => path
+                .strip_prefix(root)
+                .ok()
+                .and_then(|relative_path| {
+                    bundle
+                        .files
+                        .get(&relative_path.to_string_lossy().replace('\\', "/"))
+                })
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("'{}' is not in the bundle", path.display())),
+        }
+    }
+}
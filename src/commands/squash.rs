@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::apply::{category_files, is_empty_migration, migration_number};
+use super::init::{get_initial_migration_from_db, psql};
+use super::metadata::Metadata;
+use super::psql::redact;
+use super::source::{is_archive, Source};
+use crate::DEFAULT_PGM_PATH;
+
+/// The pgm tables, left out of the dump as every apply creates them.
+const PGM_TABLES: [&str; 8] = [
+    "pgm_extension",
+    "pgm_migration",
+    "pgm_function",
+    "pgm_trigger",
+    "pgm_view",
+    "pgm_constraint",
+    "pgm_grant",
+    "pgm_comment",
+];
+
+/// Options for [`squash`].
+#[derive(Debug, Clone)]
+pub struct SquashOptions {
+    /// The pgm directory
+    pub path: String,
+    /// The database to dump the schema from, which must have every migration
+    /// applied. Uses the PG* environment variables if `None`.
+    pub database_url: Option<String>,
+    /// Extra arguments passed to `pg_dump`
+    pub pg_dump_args: Vec<String>,
+}
+
+impl Default for SquashOptions {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_PGM_PATH.to_string(),
+            database_url: None,
+            pg_dump_args: Vec::new(),
+        }
+    }
+}
+
+/// What [`squash`] did.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Squash {
+    /// The names of the migrations replaced by the baseline
+    pub migrations: Vec<String>,
+    /// The new baseline
+    pub baseline: PathBuf,
+    /// The directory the replaced migration files were moved to
+    pub archive: PathBuf,
+}
+
+/// Replaces every migration but empty ones with a baseline holding the schema of a database
+/// they are all applied to, and moves the migration files to
+/// `archive/squashed_<number>` in the pgm directory. The baseline lists the
+/// migrations it replaces, so databases that applied them only record it,
+/// while new databases run just the baseline.
+pub fn squash(options: &SquashOptions) -> Result<Squash> {
+    let pgm_dir_path = Path::new(&options.path);
+    if !pgm_dir_path.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Directory '{}' not found. Have you run 'pgm init'?",
+            options.path
+        ));
+    }
+
+    // Empty migrations are never applied, so they are left in place to be
+    // written. The numbers of the migrations a previous squash replaced count
+    // towards the last number.
+    let source = Source::directory(pgm_dir_path.to_path_buf());
+    let mut files = Vec::new();
+    let mut numbers = Vec::new();
+    for (name, path) in category_files(&source, "migrations")? {
+        if name.contains('/') {
+            return Err(anyhow::anyhow!(
+                "Migration '{}' is in a release directory; squashing release directories is not supported",
+                name
+            ));
+        }
+        // A custom-format baseline reads as empty, but is not
+        let content = source.read(&path)?;
+        if !is_archive(&path) && is_empty_migration(&content) {
+            continue;
+        }
+        numbers.extend(migration_number(&name));
+        numbers.extend(
+            Metadata::parse(&content)
+                .squashes
+                .iter()
+                .filter_map(|name| migration_number(name)),
+        );
+        files.push((name, path));
+    }
+    let Some(last_number) = numbers.into_iter().max() else {
+        return Err(anyhow::anyhow!("There are no migrations to squash"));
+    };
+    let migrations = files
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+
+    let applied = applied_migrations(options.database_url.as_deref())?;
+    let missing = migrations
+        .iter()
+        .filter(|name| !applied.contains(*name))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The schema is dumped from the database, so apply every migration before squashing; not applied: {}",
+            missing.join(", ")
+        ));
+    }
+
+    let mut pg_dump_args = PGM_TABLES
+        .iter()
+        .map(|table| format!("--exclude-table={table}"))
+        .collect::<Vec<_>>();
+    pg_dump_args.extend(options.pg_dump_args.iter().cloned());
+    let dump = get_initial_migration_from_db(options.database_url.as_deref(), &pg_dump_args)?;
+    let dump = std::fs::read_to_string(dump.path()).context("Failed to read the schema dump")?;
+
+    let archive = pgm_dir_path
+        .join("archive")
+        .join(format!("squashed_{:05}", last_number));
+    if archive.exists() {
+        return Err(anyhow::anyhow!(
+            "'{}' already exists; move it away to squash again",
+            archive.display()
+        ));
+    }
+    std::fs::create_dir_all(&archive).context("Failed to create archive directory")?;
+    for (_, path) in &files {
+        let archived = archive.join(path.file_name().unwrap());
+        std::fs::rename(path, &archived).context(format!(
+            "Failed to move '{}' to '{}'",
+            path.display(),
+            archived.display()
+        ))?;
+    }
+
+    let baseline = pgm_dir_path
+        .join("migrations")
+        .join(format!("00000_squash_{:05}.sql", last_number));
+    std::fs::write(
+        &baseline,
+        format!("-- pgm: squashes=[{}]\n{}", migrations.join(", "), dump),
+    )
+    .context("Failed to write the baseline")?;
+
+    Ok(Squash {
+        migrations,
+        baseline,
+        archive,
+    })
+}
+
+/// Returns the names of the migrations recorded as applied in the database.
+fn applied_migrations(database_url: Option<&str>) -> Result<HashSet<String>> {
+    let output = psql(
+        database_url,
+        &["-t", "-A"],
+        "SELECT name FROM pgm_migration",
+    )
+    .context("Failed to execute psql command to get the applied migrations")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to read the applied migrations: {}",
+            redact(String::from_utf8_lossy(&output.stderr).trim())
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
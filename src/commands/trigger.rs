@@ -0,0 +1,106 @@
+use super::apply::track_dollar_quote;
+
+/// A `CREATE TRIGGER` statement in a trigger file, binding the trigger
+/// function to a table.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Binding {
+    pub(crate) name: String,
+    pub(crate) table: String,
+    pub(crate) statement: String,
+}
+
+impl Binding {
+    /// Drops and recreates the trigger, since triggers cannot be replaced
+    /// before Postgres 14. `IF EXISTS` also skips a table that does not
+    /// exist yet.
+    pub(crate) fn replace_sql(&self) -> String {
+        format!(
+            "DROP TRIGGER IF EXISTS {} ON {};\n{}",
+            self.name, self.table, self.statement
+        )
+    }
+}
+
+/// Splits the content of a trigger file into the rest of it, usually the
+/// trigger function, and its top-level `CREATE TRIGGER` statements, which
+/// are recognized at the start of a line.
+pub(crate) fn split_bindings(content: &str) -> (String, Vec<Binding>) {
+    let mut rest = String::with_capacity(content.len());
+    let mut bindings = Vec::new();
+    let mut dollar_quote: Option<String> = None;
+    let mut statement: Option<String> = None;
+
+    for line in content.split_inclusive('\n') {
+        let top_level = dollar_quote.is_none();
+        dollar_quote = track_dollar_quote(line, dollar_quote);
+
+        if statement.is_none() && top_level && is_create_trigger(line) {
+            statement = Some(String::new());
+        }
+        let Some(mut current) = statement.take() else {
+            rest.push_str(line);
+            continue;
+        };
+        current.push_str(line);
+        let code = line.split("--").next().unwrap_or_default();
+        if code.trim_end().ends_with(';') {
+            match parse_binding(&current) {
+                Some(binding) => bindings.push(binding),
+                None => rest.push_str(&current),
+            }
+        } else {
+            statement = Some(current);
+        }
+    }
+    if let Some(current) = statement {
+        match parse_binding(&current) {
+            Some(binding) => bindings.push(binding),
+            None => rest.push_str(&current),
+        }
+    }
+    (rest, bindings)
+}
+
+fn is_create_trigger(line: &str) -> bool {
+    let words = line
+        .split_whitespace()
+        .take(5)
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+    matches!(
+        words.as_slice(),
+        ["create", "trigger", ..]
+            | ["create", "constraint", "trigger", ..]
+            | ["create", "or", "replace", "trigger", ..]
+            | ["create", "or", "replace", "constraint", "trigger", ..]
+    )
+}
+
+/// Reads the trigger and table names of a `CREATE TRIGGER` statement.
+fn parse_binding(statement: &str) -> Option<Binding> {
+    let words = statement
+        .lines()
+        .map(|line| line.split("--").next().unwrap_or_default())
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>();
+    let trigger = words
+        .iter()
+        .position(|word| word.eq_ignore_ascii_case("trigger"))?;
+    let name = words.get(trigger + 1)?;
+    let on = trigger
+        + 2
+        + words[trigger + 2..]
+            .iter()
+            .position(|word| word.eq_ignore_ascii_case("on"))?;
+    let table = words.get(on + 1)?;
+    Some(Binding {
+        name: name.to_string(),
+        table: table.to_string(),
+        statement: if statement.ends_with('\n') {
+            statement.to_string()
+        } else {
+            format!("{statement}\n")
+        },
+    })
+}
@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::apply::{category_files, source, ApplyOptions, FileFilter};
+use super::disabled::Disabled;
+use super::metadata::Metadata;
+use super::psql::{log_command, psql_command, redact};
+
+/// A function or trigger whose definition in the database does not match its
+/// file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub category: String,
+    /// The name the object is tracked under
+    pub name: String,
+    /// The function, as `schema.name(argument types)`, if it exists
+    pub signature: Option<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.signature {
+            Some(signature) => write!(
+                f,
+                "{}/{}: the body of {} differs from the file",
+                self.category, self.name, signature
+            ),
+            None => write!(
+                f,
+                "{}/{}: no function named '{}' found in the search path or its schema",
+                self.category, self.name, self.name
+            ),
+        }
+    }
+}
+
+/// Compares the functions and triggers `options` would apply with what is
+/// live in the database, beyond the hash bookkeeping: every function named
+/// after a file must exist, and its body (`pg_proc.prosrc`) must be one of the
+/// bodies in the file, dollar-quoted or a string literal after `AS`. Catches
+/// functions changed by hand, or
+/// replaced by another file. Functions with SQL-standard bodies
+/// (`BEGIN ATOMIC`) or written in C cannot be compared and are skipped.
+pub fn verify(options: &ApplyOptions) -> Result<Vec<Mismatch>> {
+    let source = source(options);
+    let mut filter = FileFilter::new(source.root(), &options.include, &options.exclude)?;
+    filter.disabled = Disabled::read(&source)?;
+
+    let mut files = Vec::new();
+    let mut schemas = Vec::new();
+    for category in ["functions", "triggers"] {
+        if !options.categories.iter().any(|c| c == category) {
+            continue;
+        }
+        for (name, path) in category_files(&source, category)? {
+            if filter.allows(&path) {
+                let content = source.read(&path)?;
+                schemas.extend(Metadata::parse(&content).schema);
+                let bodies = function_bodies(&content)
+                    .iter()
+                    .map(|body| normalize_body(body))
+                    .collect::<Vec<_>>();
+                files.push((category, name, bodies));
+            }
+        }
+    }
+    if files.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let names = files
+        .iter()
+        .map(|(_, name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    let functions = get_function_bodies(&names, &schemas)?;
+
+    let mut mismatches = Vec::new();
+    for (category, name, bodies) in files {
+        let Some(functions) = functions.get(&name) else {
+            mismatches.push(Mismatch {
+                category: category.to_string(),
+                name,
+                signature: None,
+            });
+            continue;
+        };
+        for (signature, body) in functions {
+            let comparable = !body.trim().is_empty();
+            if comparable && !bodies.contains(&normalize_body(body)) {
+                mismatches.push(Mismatch {
+                    category: category.to_string(),
+                    name: name.clone(),
+                    signature: Some(signature.clone()),
+                });
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Returns the `(signature, body)` of the functions named one of `names` in
+/// the search path or in one of `schemas`, by name.
+fn get_function_bodies(
+    names: &[&str],
+    schemas: &[String],
+) -> Result<BTreeMap<String, Vec<(String, String)>>> {
+    let literals = |values: &mut dyn Iterator<Item = &str>| {
+        values
+            .map(|value| format!("'{}'", value.replace('\'', "''")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let names = literals(&mut names.iter().copied());
+    let schemas = literals(&mut schemas.iter().map(String::as_str));
+    let query = format!(
+        "SELECT p.proname, format('%I.%I(%s)', n.nspname, p.proname, pg_get_function_identity_arguments(p.oid)), \
+         CASE WHEN l.lanname IN ('c', 'internal') THEN '' ELSE p.prosrc END \
+         FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace JOIN pg_language l ON l.oid = p.prolang \
+         WHERE p.proname = ANY (ARRAY[{}]) \
+         AND n.nspname = ANY (current_schemas(false) || ARRAY[{}]::name[]) ORDER BY 2",
+        names, schemas
+    );
+
+    // Bodies span lines, so fields and rows are separated by NUL bytes
+    let mut command = psql_command();
+    command.args(["-t", "-A", "-z", "-0", "-c", &query]);
+    log_command(&command);
+    let output = command
+        .output()
+        .context("Failed to execute psql command to get function bodies")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Failed to get function bodies: {}",
+            redact(String::from_utf8_lossy(&output.stderr).trim())
+        ));
+    }
+
+    let output = String::from_utf8(output.stdout)
+        .context("Failed to convert function bodies output to UTF-8")?;
+    let fields = output
+        .strip_suffix('\0')
+        .map(|rows| rows.split('\0').collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut functions = BTreeMap::<String, Vec<(String, String)>>::new();
+    for row in fields.chunks(3) {
+        if let [name, signature, body] = row {
+            functions
+                .entry(name.to_string())
+                .or_default()
+                .push((signature.to_string(), body.to_string()));
+        }
+    }
+    Ok(functions)
+}
+
+/// Returns the content of every dollar-quoted string in `sql`, and of every
+/// single-quoted string following `AS`, such as the body of
+/// `LANGUAGE sql AS 'SELECT 1'`, with its doubled quotes undone. Comments
+/// and other single-quoted strings are skipped.
+fn function_bodies(sql: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut rest = sql;
+    while let Some(start) = rest.find(['$', '\'', '-']) {
+        let after = &rest[start + 1..];
+        match rest.as_bytes()[start] {
+            b'\'' => {
+                let Some(end) = string_end(after) else {
+                    break;
+                };
+                if follows_as(&rest[..start]) {
+                    strings.push(after[..end].replace("\'\'", "\'"));
+                }
+                rest = &after[end + 1..];
+            }
+            b'-' if after.starts_with('-') => {
+                rest = after.find('\n').map_or("", |end| &after[end..]);
+            }
+            b'$' => {
+                let len = after
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                if !after[len..].starts_with('$') || after.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    rest = after;
+                    continue;
+                }
+                let tag = &rest[start..start + len + 2];
+                let body = &rest[start + tag.len()..];
+                match body.find(tag) {
+                    Some(end) => {
+                        strings.push(body[..end].to_string());
+                        rest = &body[end + tag.len()..];
+                    }
+                    None => break,
+                }
+            }
+            _ => rest = after,
+        }
+    }
+    strings
+}
+
+/// Returns the position of the quote closing the single-quoted string `sql`
+/// starts inside of, skipping doubled quotes.
+fn string_end(sql: &str) -> Option<usize> {
+    let mut from = 0;
+    loop {
+        let end = from + sql[from..].find('\'')?;
+        if sql[end + 1..].starts_with('\'') {
+            from = end + 2;
+        } else {
+            return Some(end);
+        }
+    }
+}
+
+/// Whether `sql` ends with the keyword `AS`, so a string following it is a
+/// function body.
+fn follows_as(sql: &str) -> bool {
+    sql.trim_end()
+        .rsplit(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .next()
+        .is_some_and(|word| word.eq_ignore_ascii_case("as"))
+}
+
+/// Ignores differences in line endings and surrounding whitespace, which
+/// editors introduce without changing what the function does.
+fn normalize_body(body: &str) -> String {
+    body.replace("\r\n", "\n").trim().to_string()
+}
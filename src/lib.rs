@@ -0,0 +1,11 @@
+//! Library interface of pgm, for running its compile/apply logic from Rust
+//! without shelling out to the `pgm` binary. The SQL is still executed with
+//! `psql`, which has to be on the `PATH`.
+
+mod commands;
+
+pub use commands::*;
+
+/// The pgm directory used when no path is given.
+pub const DEFAULT_PGM_PATH: &str = "postgres";
+pub(crate) const INITIAL_MIGRATION_FILE_NAME: &str = "00000.sql";
@@ -1,28 +1,493 @@
-mod commands;
-
-use clap::{Arg, Command};
+use clap::{Arg, ArgGroup, ArgMatches, ColorChoice, Command};
 use dotenv::dotenv;
+use pgm::{
+    ApplyOptions, Change, DiffOptions, InitOptions, ListOptions, SeedOptions, DEFAULT_PGM_PATH,
+};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by `--quiet` and `--silent`, which leave out the messages saying what
+/// succeeded.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Whether pgm's own output, such as the unified diffs of `diff --patch`, is
+/// colored, as resolved by [`color_choice`].
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Prints a message saying what succeeded, like `println!`, unless `--quiet`
+/// or `--silent` is given. What a command is asked to print, such as a list,
+/// is printed with `println!` regardless.
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Parses a `--set NAME=VALUE` argument.
+fn parse_variable(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, got '{}'", arg))
+}
+
+/// Parses a size in bytes, optionally with a `kB`, `MB` or `GB` suffix.
+fn parse_size(arg: &str) -> Result<u64, String> {
+    let invalid = || format!("expected a size such as 500kB or 10MB, got '{}'", arg);
+    let digits = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let multiplier = match arg[digits..].trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1024,
+        "mb" => 1024 * 1024,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    arg[..digits]
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(invalid)
+}
+
+/// The arguments choosing what `apply` and `plan` compile.
+fn compile_args() -> Vec<Arg> {
+    vec![
+        Arg::new("messages")
+            .long("messages")
+            .value_name("LEVEL")
+            .help("The client_min_messages to apply with; above notice, the applied and skipped files are not shown or counted either")
+            .default_value("notice")
+            .value_parser(pgm::MESSAGE_LEVELS),
+        Arg::new("strict")
+            .long("strict")
+            .help("Fails instead of warning when an applied migration has been edited, or a function, view or trigger file creates, alters or drops a table")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("no-balance-check")
+            .long("no-balance-check")
+            .help("Skips checking that the quotes, comments and parentheses of each object file are balanced")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("strict-order")
+            .long("strict-order")
+            .help("Fails unless the migration numbers run from 1 up without gaps or duplicates")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("on-conflict")
+            .long("on-conflict")
+            .help("What to do when several migration files resolve to the same name: fail, or skip all but the first in path order with a warning")
+            .default_value("fail")
+            .value_parser(["fail", "skip"]),
+        Arg::new("max-migration-size")
+            .long("max-migration-size")
+            .value_name("SIZE")
+            .help("Runs each migration file larger than this, e.g. '10MB', in a transaction of its own, committing what comes before it first")
+            .value_parser(parse_size),
+        Arg::new("order")
+            .long("order")
+            .value_name("PHASES")
+            .help("The comma-separated order to apply the phases in, each listed once with checks after functions and triggers; defaults to PGM_APPLY_ORDER, or extensions,baselines,functions,triggers,migrations,constraints,views,checks,grants")
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("only")
+            .long("only")
+            .help("Only applies the given category (can be repeated)")
+            .conflicts_with("skip")
+            .action(clap::ArgAction::Append)
+            .value_parser(pgm::CATEGORIES),
+        Arg::new("skip")
+            .long("skip")
+            .help("Skips the given category (can be repeated)")
+            .action(clap::ArgAction::Append)
+            .value_parser(pgm::CATEGORIES),
+        Arg::new("include")
+            .long("include")
+            .help("Only applies files matching the glob, relative to the path, e.g. 'functions/billing_*' (can be repeated)")
+            .action(clap::ArgAction::Append)
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("exclude")
+            .long("exclude")
+            .help("Skips files matching the glob, relative to the path (can be repeated)")
+            .action(clap::ArgAction::Append)
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("set")
+            .long("set")
+            .value_name("NAME=VALUE")
+            .help("Sets a variable, readable with current_setting('pgm.NAME') (can be repeated)")
+            .action(clap::ArgAction::Append)
+            .value_parser(parse_variable),
+        Arg::new("bundle")
+            .long("bundle")
+            .help("Uses the files of a bundle written by 'pgm bundle' instead of the path")
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("minify")
+            .long("minify")
+            .help("Strips comments from the compiled SQL (the default, except with --dry-run)")
+            .conflicts_with("no-minify")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("no-minify")
+            .long("no-minify")
+            .help("Keeps the comments in the compiled SQL (the default with --dry-run)")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("keep-temp")
+            .long("keep-temp")
+            .help("Keeps the temporary file with the SQL psql runs and logs its path; PGM_TEMP_DIR sets where it is written")
+            .action(clap::ArgAction::SetTrue),
+        Arg::new("pretty")
+            .long("pretty")
+            .help("Keeps the -- RUN/-- DONE lines marking each file when stripping comments")
+            .action(clap::ArgAction::SetTrue),
+    ]
+}
+
+/// Reads the arguments of [`compile_args`] into options for `apply` and `plan`.
+fn compile_options(matches: &ArgMatches) -> anyhow::Result<ApplyOptions> {
+    let path = matches
+        .get_one::<String>("path")
+        .expect("Input argument is required");
+    let categories = match matches.get_many::<String>("only") {
+        Some(only) => only.cloned().collect::<Vec<_>>(),
+        None => {
+            let skip = matches
+                .get_many::<String>("skip")
+                .unwrap_or_default()
+                .collect::<Vec<_>>();
+            pgm::CATEGORIES
+                .iter()
+                .filter(|category| !skip.iter().any(|s| s == *category))
+                .map(|category| category.to_string())
+                .collect()
+        }
+    };
+    let bundle_path = matches.get_one::<String>("bundle");
+    Ok(ApplyOptions {
+        path: bundle_path.unwrap_or(path).clone(),
+        categories,
+        strict: matches.get_flag("strict"),
+        strict_order: matches.get_flag("strict-order"),
+        skip_balance_check: matches.get_flag("no-balance-check"),
+        max_migration_size: matches.get_one::<u64>("max-migration-size").copied(),
+        skip_duplicates: matches
+            .get_one::<String>("on-conflict")
+            .is_some_and(|on_conflict| on_conflict == "skip"),
+        order: match matches
+            .get_one::<String>("order")
+            .cloned()
+            .or_else(|| std::env::var("PGM_APPLY_ORDER").ok())
+            .filter(|order| !order.is_empty())
+        {
+            Some(order) => order.split(',').map(|p| p.trim().to_string()).collect(),
+            None => pgm::PHASES.iter().map(|p| p.to_string()).collect(),
+        },
+        include: matches
+            .get_many::<String>("include")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        exclude: matches
+            .get_many::<String>("exclude")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        variables: matches
+            .get_many::<(String, String)>("set")
+            .unwrap_or_default()
+            .cloned()
+            .collect(),
+        progress: !matches.get_flag("quiet"),
+        silent: matches.get_flag("silent"),
+        bundle: bundle_path
+            .map(|path| pgm::Bundle::read(path))
+            .transpose()?,
+        minify: if matches.get_flag("minify") {
+            Some(true)
+        } else if matches.get_flag("no-minify") {
+            Some(false)
+        } else {
+            None
+        },
+        pretty: matches.get_flag("pretty"),
+        keep_temp: matches.get_flag("keep-temp"),
+        messages: matches
+            .get_one::<String>("messages")
+            .expect("Has a default")
+            .clone(),
+        ..Default::default()
+    })
+}
+
+/// Reads the databases to apply to from `--database-url` and `--databases`.
+fn database_urls(matches: &ArgMatches) -> anyhow::Result<Vec<String>> {
+    let mut databases = matches
+        .get_many::<String>("database-url")
+        .unwrap_or_default()
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Some(path) = matches.get_one::<String>("databases") {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read '{}': {}", path, e))?;
+        databases.extend(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string),
+        );
+        if databases.is_empty() {
+            return Err(anyhow::anyhow!("'{}' lists no databases", path));
+        }
+    }
+    Ok(databases)
+}
+
+/// Applies to each of `databases` and reports how each one went, exiting with
+/// an error if any of them was not applied.
+fn apply_to_databases(options: &ApplyOptions, databases: &[String], matches: &ArgMatches) {
+    let keep_going = matches.get_flag("keep-going");
+    let applies = match pgm::apply_to_databases(options, databases, keep_going) {
+        Ok(applies) => applies,
+        Err(e) => {
+            eprintln!("Error applying changes:");
+            for cause in e.chain() {
+                eprintln!("  - {}", pgm::redact(&cause.to_string()));
+            }
+            std::process::exit(1);
+        }
+    };
+
+    let applied = applies.iter().filter(|apply| apply.result.is_ok()).count();
+    report!("Applied to {} of {} databases:", applied, databases.len());
+    for apply in &applies {
+        match &apply.result {
+            Ok(summary) => {
+                report!("  - {}: {}", pgm::redact(&apply.database), summary);
+                if matches.get_flag("print-checksum") {
+                    println!("    Checksum: sha256:{}", summary.checksum());
+                }
+            }
+            Err(e) => println!(
+                "  - {}: failed: {}",
+                pgm::redact(&apply.database),
+                pgm::redact(&format!("{:#}", e))
+            ),
+        }
+    }
+    for database in &databases[applies.len()..] {
+        println!(
+            "  - {}: not attempted after the failure, use --keep-going to continue past failures",
+            pgm::redact(database)
+        );
+    }
+    if applied < databases.len() {
+        std::process::exit(1);
+    }
+    if let Some(code) = matches.get_one::<u8>("unchanged-exit-code") {
+        // Every database was applied by now
+        let unchanged = applies
+            .iter()
+            .flat_map(|apply| &apply.result)
+            .all(|summary| summary.applied() == 0);
+        if unchanged {
+            std::process::exit((*code).into());
+        }
+    }
+}
+
+/// Formats a GitHub Actions workflow command annotating `path`, if given,
+/// with `message` at `level`, e.g. `::warning file=a.sql::...`, which shows
+/// inline on the pull request.
+fn github_annotation(level: &str, path: Option<&Path>, message: &str) -> String {
+    let escape = |text: &str| {
+        text.replace('%', "%25")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A")
+    };
+    let properties = path.map_or(String::new(), |path| {
+        format!(
+            " file={},line=1",
+            escape(&path.to_string_lossy())
+                .replace(':', "%3A")
+                .replace(',', "%2C")
+        )
+    });
+    format!("::{}{}::{}", level, properties, escape(message))
+}
 
-const DEFAULT_PGM_PATH: &str = "postgres";
-const INITIAL_MIGRATION_FILE_NAME: &str = "00000.sql";
+/// Formats how long ago an RFC 3339 timestamp was in its largest unit, e.g.
+/// "2 days ago".
+fn time_ago(timestamp: &str) -> String {
+    let seconds = humantime::parse_rfc3339(timestamp)
+        .ok()
+        .and_then(|time| std::time::SystemTime::now().duration_since(time).ok())
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (count, unit) = [
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (7 * 24 * 60 * 60, "week"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+        (1, "second"),
+    ]
+    .into_iter()
+    .find(|(length, _)| seconds >= *length)
+    .map_or((0, "second"), |(length, unit)| (seconds / length, unit));
+    match count {
+        0 => "just now".to_string(),
+        1 => format!("1 {} ago", unit),
+        _ => format!("{} {}s ago", count, unit),
+    }
+}
+
+/// Resolves whether to color the output: by `--color`, else never when
+/// `NO_COLOR` is set, always when `CLICOLOR_FORCE` is set to anything but
+/// `0`, and else only on a terminal. The arguments are scanned for `--color`
+/// before clap parses them, as clap colors its own help and errors by it.
+fn color_choice() -> ColorChoice {
+    let mut args = std::env::args().skip(1).take_while(|arg| arg != "--");
+    let mut flag = None;
+    while let Some(arg) = args.next() {
+        if let Some(value) = arg.strip_prefix("--color=") {
+            flag = Some(value.to_string());
+        } else if arg == "--color" {
+            flag = args.next();
+        }
+    }
+    let set = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+    match flag.as_deref() {
+        Some("always") => ColorChoice::Always,
+        Some("never") => ColorChoice::Never,
+        Some(_) => ColorChoice::Auto,
+        None if set("NO_COLOR").is_some() => ColorChoice::Never,
+        None if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") => ColorChoice::Always,
+        None => ColorChoice::Auto,
+    }
+}
+
+/// Colors the added lines of a unified diff green, the removed ones red and
+/// the hunk headers cyan.
+fn color_patch(patch: &str) -> String {
+    patch
+        .split_inclusive('\n')
+        .map(|line| {
+            let color = if line.starts_with("+++") || line.starts_with("---") {
+                "1"
+            } else if line.starts_with('+') {
+                "32"
+            } else if line.starts_with('-') {
+                "31"
+            } else if line.starts_with("@@") {
+                "36"
+            } else {
+                return line.to_string();
+            };
+            let (text, newline) = match line.strip_suffix('\n') {
+                Some(text) => (text, "\n"),
+                None => (line, ""),
+            };
+            format!("\x1b[{}m{}\x1b[0m{}", color, text, newline)
+        })
+        .collect()
+}
 
 fn main() {
-    // Load environment variables from .env file
-    dotenv().ok();
+    let color = color_choice();
+    COLOR.store(
+        match color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::io::stdout().is_terminal(),
+        },
+        Ordering::Relaxed,
+    );
 
     let matches = Command::new("pgm")
         .version(env!("CARGO_PKG_VERSION"))
+        .color(color)
         .arg_required_else_help(true)
         .about(
             "A CLI tool for managing postgres database migrations, triggers, views and functions",
         )
+        .arg(
+            Arg::new("path")
+                .long("path")
+                .help("The path to the directory containing the database files")
+                .global(true)
+                .default_value(DEFAULT_PGM_PATH)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .help("Sets the log level, overriding RUST_LOG")
+                .global(true)
+                .value_parser(["error", "warn", "info", "debug", "trace"]),
+        )
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .help("Leaves out progress bars, info logs and the messages saying what succeeded; errors and warnings are still printed")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .help("Whether to color the output: always, never, or auto, on a terminal only (the default); NO_COLOR and CLICOLOR_FORCE are honored without it")
+                .global(true)
+                .value_parser(["auto", "always", "never"]),
+        )
+        .arg(
+            Arg::new("env-file")
+                .long("env-file")
+                .value_name("PATH")
+                .help("Loads the environment variables from this file instead of .env, e.g. .env.staging")
+                .global(true)
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("silent")
+                .long("silent")
+                .help("Like --quiet, and also leaves out warnings and the NOTICEs psql prints for each file; only errors are printed")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("psql")
+                .long("psql")
+                .help("The psql binary to run, overriding PGM_PSQL")
+                .global(true)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("pg-dump")
+                .long("pg-dump")
+                .help("The pg_dump binary to run, overriding PGM_PG_DUMP")
+                .global(true)
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            Arg::new("no-emoji")
+                .long("no-emoji")
+                .help("Marks applied objects with [applied] instead of an emoji, overriding PGM_NO_EMOJI")
+                .global(true)
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("service")
+                .long("service")
+                .help("The connection service to use from pg_service.conf, overriding PGSERVICE; a --database-url takes precedence over its settings")
+                .global(true)
+                .value_parser(clap::value_parser!(String)),
+        )
         .subcommand(
             Command::new("init")
                 .about("Initializes the directory")
                 .arg(
-                    Arg::new("path")
-                        .help("The path to the directory where pgm will store its files")
-                        .default_value(DEFAULT_PGM_PATH)
+                    Arg::new("deprecated-path")
+                        .value_name("PATH")
+                        .help("Deprecated: use --path")
+                        .hide(true)
+                        .conflicts_with("path")
                         .value_parser(clap::value_parser!(String)),
                 )
                 .arg(
@@ -30,40 +495,429 @@ fn main() {
                         .long("existing-db")
                         .help("Initialize from an existing database using pg_dump")
                         .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("from-dump")
+                        .long("from-dump")
+                        .value_name("FILE")
+                        .help("Initialize from the SQL output of pg_dump, without connecting; functions, triggers and views stay in the initial migration")
+                        .conflicts_with("existing-db")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .group(ArgGroup::new("schema-source").args(["existing-db", "from-dump"]))
+                .arg(
+                    Arg::new("compress")
+                        .long("compress")
+                        .help("Writes the initial migration gzip-compressed as 00000.sql.gz")
+                        .requires("schema-source")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("The format of the initial migration: plain SQL, or a pg_dump custom-format archive written as 00000.dump")
+                        .requires("existing-db")
+                        .conflicts_with("compress")
+                        .default_value("plain")
+                        .value_parser(["plain", "custom"]),
+                )
+                .arg(
+                    Arg::new("constraints")
+                        .long("constraints")
+                        .help("Also extracts named CHECK and foreign key constraints into constraints/")
+                        .requires("existing-db")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("with-comments")
+                        .long("with-comments")
+                        .help("Also extracts the comments on tables, views and their columns into comments/, one file per table")
+                        .requires("existing-db")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("database-url")
+                        .long("database-url")
+                        .help("Connection string of the database to initialize from (defaults to the PG* environment variables)")
+                        .requires("existing-db")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("pg-dump-arg")
+                        .long("pg-dump-arg")
+                        .help("Extra argument passed through to pg_dump, e.g. --pg-dump-arg=--no-privileges (can be repeated)")
+                        .requires("existing-db")
+                        .allow_hyphen_values(true)
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
                 ),
         )
         .subcommand(
             Command::new("apply")
                 .about("Compiles the changes and applies them to the database")
-                .arg(
-                    Arg::new("path")
-                        .long("path")
-                        .help("The path to the directory containing the database files")
-                        .default_value(DEFAULT_PGM_PATH)
-                        .value_parser(clap::value_parser!(String)),
-                )
+                .args(compile_args())
                 .arg(
                     Arg::new("dry-run")
                         .long("dry-run")
                         .help("Prints the SQL that would be applied but does not apply it")
                         .action(clap::ArgAction::SetTrue),
                 )
+                .arg(
+                    Arg::new("connect")
+                        .long("connect")
+                        .help("With --dry-run, compares the files to the pgm tables of the database and lists what would be applied instead of printing the SQL")
+                        .requires("dry-run")
+                        .conflicts_with_all(["dump-only", "sql"])
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .value_name("PATH")
+                        .help("Applies only this object file, relative to the path, e.g. functions/charge.sql; the objects it depends on must already be applied")
+                        .conflicts_with_all([
+                            "fake",
+                            "dump-only",
+                            "connect",
+                            "bundle",
+                            "plan",
+                            "only",
+                            "skip",
+                            "include",
+                            "exclude",
+                            "rerun-migration",
+                        ])
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("snapshot")
+                        .long("snapshot")
+                        .help("Dumps the schema of the database to a timestamped file in .pgm-snapshots/ of the pgm directory before applying")
+                        .conflicts_with_all(["dry-run", "dump-only"])
+                        .action(clap::ArgAction::SetTrue),
+                )
                 .arg(
                     Arg::new("fake")
                         .long("fake")
                         .help("Only updates pgm_ tables without executing the actual SQL")
                         .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("role")
+                        .long("role")
+                        .help("Applies as this role, with SET ROLE, so the objects created are owned by it")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("fake-migration")
+                        .long("fake-migration")
+                        .value_name("NAME")
+                        .help("Records the migration as applied without running it, e.g. 00012, and applies everything else as usual (can be repeated)")
+                        .conflicts_with("fake")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("rerun-migration")
+                        .long("rerun-migration")
+                        .value_name("NAME")
+                        .help("Runs the migration again although it was applied, e.g. 00007 after reverting it by hand; its record is replaced in the same transaction (can be repeated)")
+                        .conflicts_with_all(["fake", "dump-only"])
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("dump-only")
+                        .long("dump-only")
+                        .help("Prints a plain schema script of the files, without the pgm tables or the checks of what was applied")
+                        .requires("dry-run")
+                        .conflicts_with_all(["fake", "fake-migration", "timing", "lock-timeout"])
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .help("Seeds the database after a successful apply (skipped with --dry-run)")
+                        .conflicts_with("fake")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("print-checksum")
+                        .long("print-checksum")
+                        .help("Prints the sha256 checksum of the applied SQL")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("lock-timeout")
+                        .long("lock-timeout")
+                        .help("Fails if another apply or a lock is waited on for longer than this, e.g. '30s' (waits indefinitely by default)")
+                        .value_parser(humantime::parse_duration),
+                )
+                .arg(
+                    Arg::new("timing")
+                        .long("timing")
+                        .help("Times every applied object and prints the slowest ones")
+                        .conflicts_with("fake")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .help("Prints the time spent reading, checking and compiling the files and executing the SQL, and the largest files")
+                        .conflicts_with_all(["dry-run", "database-url", "databases", "sql"])
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("verify")
+                        .long("verify")
+                        .help("After applying, checks that the function bodies in the database match their files")
+                        .conflicts_with("fake")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("database-url")
+                        .long("database-url")
+                        .help("Applies to this database instead of the one of the PG* environment variables (can be repeated to apply to each in turn)")
+                        .action(clap::ArgAction::Append)
+                        .conflicts_with_all(["dry-run", "seed", "verify", "plan"])
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("databases")
+                        .long("databases")
+                        .value_name("FILE")
+                        .help("Applies to each database in the file, one connection string per line, in turn")
+                        .conflicts_with_all(["dry-run", "seed", "verify", "plan"])
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("sql")
+                        .long("sql")
+                        .value_name("SQL")
+                        .help("Runs this SQL, or the SQL read from stdin for '-', in the DO block of an apply instead of the files; the pgm tables are not touched")
+                        .conflicts_with_all([
+                            "fake",
+                            "fake-migration",
+                            "seed",
+                            "bundle",
+                            "plan",
+                            "dump-only",
+                            "verify",
+                            "database-url",
+                            "databases",
+                            "post-apply",
+                            "file",
+                            "rerun-migration",
+                        ])
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("post-apply")
+                        .long("post-apply")
+                        .value_name("COMMAND")
+                        .help("A shell command to run after a successful apply, with the counts in PGM_APPLIED, PGM_SKIPPED and PGM_APPLIED_<CATEGORY>; defaults to PGM_POST_APPLY")
+                        .conflicts_with_all(["dry-run", "fake"])
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("metrics-file")
+                        .long("metrics-file")
+                        .value_name("PATH")
+                        .help("Appends a JSON line with the time, version, counts and duration of each successful apply to this file; defaults to PGM_METRICS_FILE")
+                        .conflicts_with_all(["dry-run", "fake"])
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("post-apply-fatal")
+                        .long("post-apply-fatal")
+                        .help("Fails when a post-apply hook fails instead of warning; the changes stay applied")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("repair")
+                        .long("repair")
+                        .help("Recreates the standard subdirectories missing from the pgm directory")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("allow-empty")
+                        .long("allow-empty")
+                        .help("Applies even if the directory has no files, which usually means --path is wrong")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("unchanged-exit-code")
+                        .long("unchanged-exit-code")
+                        .value_name("CODE")
+                        .help("Exits with this code instead of 0 if the apply succeeded without changing anything, i.e. every object was skipped")
+                        .conflicts_with("dry-run")
+                        .value_parser(clap::value_parser!(u8)),
+                )
+                .arg(
+                    Arg::new("keep-going")
+                        .long("keep-going")
+                        .help("Continues with the next database after one fails")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("plan")
+                        .long("plan")
+                        .help("Applies only if the files and the database are still as when the plan written by 'pgm plan' was made")
+                        .conflicts_with_all(["dry-run", "fake"])
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .mut_arg("bundle", |arg| arg.conflicts_with("seed")),
+        )
+        .subcommand(
+            Command::new("plan")
+                .about("Tries the changes in a transaction that is rolled back and writes what would be applied to a plan file")
+                .args(compile_args())
+                .arg(
+                    Arg::new("lock-timeout")
+                        .long("lock-timeout")
+                        .help("Fails if another apply or a lock is waited on for longer than this, e.g. '30s' (waits indefinitely by default)")
+                        .value_parser(humantime::parse_duration),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("The file to write the plan to")
+                        .default_value("plan.json")
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("squash")
+                .about("Replaces the migrations with a baseline dumped from a database they are all applied to")
+                .arg(
+                    Arg::new("database-url")
+                        .long("database-url")
+                        .help("Connection string of the database to dump the schema from (defaults to the PG* environment variables)")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("pg-dump-arg")
+                        .long("pg-dump-arg")
+                        .help("Extra argument passed through to pg_dump, e.g. --pg-dump-arg=--no-privileges (can be repeated)")
+                        .allow_hyphen_values(true)
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("bundle")
+                .about("Serializes the database files into a single bundle file")
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("The file to write the bundle to (defaults to stdout)")
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Lists the objects pgm manages, as found on disk")
+                .arg(
+                    Arg::new("category")
+                        .help("Only lists the given categories")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(pgm::CATEGORIES),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("The output format")
+                        .default_value("text")
+                        .value_parser(["text", "json"]),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .long("verbose")
+                        .short('v')
+                        .help("Also prints when each object was applied as a timestamp")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Reports the objects added, removed or changed between two pgm directories, or between the directory and the database")
+                .arg(
+                    Arg::new("dirs")
+                        .value_names(["FROM", "TO"])
+                        .help("The pgm directories to compare, the old one first")
+                        .num_args(2)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("against-db")
+                        .long("against-db")
+                        .help("Compares the directory at --path with the hashes the database records")
+                        .conflicts_with("dirs")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .group(
+                    ArgGroup::new("base")
+                        .args(["dirs", "against-db"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("category")
+                        .long("category")
+                        .help("Only compares the given category (can be repeated)")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(pgm::CATEGORIES),
+                )
+                .arg(
+                    Arg::new("patch")
+                        .long("patch")
+                        .short('p')
+                        .help("Also prints a unified diff of each object")
+                        .conflicts_with("against-db")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("The output format: text, or GitHub Actions annotations on the files of the objects that differ")
+                        .conflicts_with("patch")
+                        .default_value("text")
+                        .value_parser(["text", "github"]),
                 ),
         )
+        .subcommand(
+            Command::new("describe")
+                .about("Prints a JSON description of every file and what the database records for it"),
+        )
         .subcommand(
             Command::new("seed")
                 .about("Seeds the database with data")
                 .arg(
-                    Arg::new("path")
-                        .long("path")
-                        .help("The path to the directory containing the database files")
-                        .default_value(DEFAULT_PGM_PATH)
-                        .value_parser(clap::value_parser!(String)),
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Prints the seed SQL that would be executed but does not execute it")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("messages")
+                        .long("messages")
+                        .value_name("LEVEL")
+                        .help("The client_min_messages to seed with; above notice, the seeds run are not shown either")
+                        .default_value("notice")
+                        .value_parser(pgm::MESSAGE_LEVELS),
+                )
+                .arg(
+                    Arg::new("strict")
+                        .long("strict")
+                        .help("Fails instead of warning when a seed creates, alters or drops a table")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("keep-temp")
+                        .long("keep-temp")
+                        .help("Keeps the temporary file with the SQL psql runs and logs its path; PGM_TEMP_DIR sets where it is written")
+                        .action(clap::ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -74,111 +928,396 @@ fn main() {
                     Command::new("migration")
                         .about("Creates a new migration")
                         .arg(
-                            Arg::new("path")
-                                .long("path")
-                                .help("The path to the directory containing the database files")
-                                .default_value(DEFAULT_PGM_PATH)
+                            Arg::new("release")
+                                .long("release")
+                                .value_name("NAME")
+                                .help("Creates it in the release directory migrations/NAME, numbered after the migrations of that release")
                                 .value_parser(clap::value_parser!(String)),
                         ),
                 )
                 .subcommand(
                     Command::new("trigger")
                         .about("Creates a new trigger")
-                        .arg(
-                            Arg::new("path")
-                                .long("path")
-                                .help("The path to the directory containing the database files")
-                                .default_value(DEFAULT_PGM_PATH)
-                                .value_parser(clap::value_parser!(String)),
-                        )
                         .arg(
                             Arg::new("name")
                                 .help("The name of the trigger")
                                 .required(true)
                                 .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .visible_alias("yes")
+                                .help("Overwrites an existing trigger without asking, and allows a name that is a reserved keyword")
+                                .action(clap::ArgAction::SetTrue),
                         ),
                 )
                 .subcommand(
                     Command::new("view")
                         .about("Creates a new view")
-                        .arg(
-                            Arg::new("path")
-                                .long("path")
-                                .help("The path to the directory containing the database files")
-                                .default_value(DEFAULT_PGM_PATH)
-                                .value_parser(clap::value_parser!(String)),
-                        )
                         .arg(
                             Arg::new("name")
                                 .help("The name of the view")
                                 .required(true)
                                 .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .visible_alias("yes")
+                                .help("Overwrites an existing view without asking, and allows a name that is a reserved keyword")
+                                .action(clap::ArgAction::SetTrue),
                         ),
                 )
                 .subcommand(
                     Command::new("function")
                         .about("Creates a new function")
-                        .arg(
-                            Arg::new("path")
-                                .long("path")
-                                .help("The path to the directory containing the database files")
-                                .default_value(DEFAULT_PGM_PATH)
-                                .value_parser(clap::value_parser!(String)),
-                        )
                         .arg(
                             Arg::new("name")
                                 .help("The name of the function")
                                 .required(true)
                                 .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("template")
+                                .long("template")
+                                .help(format!(
+                                    "The template to start from, one of {} or a file templates/functions/<template>.sql in the path",
+                                    pgm::FUNCTION_TEMPLATES
+                                        .map(|(name, _)| name)
+                                        .join(", ")
+                                ))
+                                .default_value("plain")
+                                .value_parser(clap::value_parser!(String)),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .visible_alias("yes")
+                                .help("Overwrites an existing function without asking, and allows a name that is a reserved keyword")
+                                .action(clap::ArgAction::SetTrue),
                         ),
                 )
-                .subcommand(
-                    Command::new("seed").about("Creates a new seed").arg(
-                        Arg::new("path")
-                            .long("path")
-                            .help("The path to the directory containing the database files")
-                            .default_value(DEFAULT_PGM_PATH)
-                            .value_parser(clap::value_parser!(String)),
-                    ),
-                ),
+                .subcommand(Command::new("seed").about("Creates a new seed")),
         )
         .get_matches();
 
+    // Load environment variables from the --env-file, which must exist, or
+    // else from the .env file if there is one
+    match matches.get_one::<PathBuf>("env-file") {
+        Some(path) => {
+            if let Err(e) = dotenv::from_path(path) {
+                eprintln!("Error loading environment variables:");
+                eprintln!("  - Failed to read '{}': {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        None => {
+            dotenv().ok();
+        }
+    }
+
+    let silent = matches.get_flag("silent");
+    QUIET.store(silent || matches.get_flag("quiet"), Ordering::Relaxed);
+
+    // Log to stderr at the requested level, falling back to RUST_LOG and then
+    // info, or less with --quiet and --silent
+    let default_level = if silent {
+        "error"
+    } else if QUIET.load(Ordering::Relaxed) {
+        "warn"
+    } else {
+        "info"
+    };
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_level));
+    if let Some(log_level) = matches.get_one::<String>("log-level") {
+        logger.parse_filters(log_level);
+    }
+    logger
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "{}: {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            )
+        })
+        .init();
+
+    // The binaries are looked up wherever pgm runs them, so the flags are
+    // passed on as the environment variables they override
+    if let Some(psql) = matches.get_one::<String>("psql") {
+        std::env::set_var("PGM_PSQL", psql);
+    }
+    if let Some(pg_dump) = matches.get_one::<String>("pg-dump") {
+        std::env::set_var("PGM_PG_DUMP", pg_dump);
+    }
+    if matches.get_flag("no-emoji") {
+        std::env::set_var("PGM_NO_EMOJI", "1");
+    }
+    // psql, pg_dump and pg_restore look the service up through libpq
+    if let Some(service) = matches.get_one::<String>("service") {
+        std::env::set_var("PGSERVICE", service);
+    }
+
     match matches.subcommand() {
         Some(("init", init_matches)) => {
-            let path = init_matches
-                .get_one::<String>("path")
-                .expect("Input argument is required");
-            let existing_db = init_matches.get_flag("existing-db");
-            if let Err(e) = commands::init(path, existing_db) {
+            // init used to take the path positionally, unlike every other command
+            let path = match init_matches.get_one::<String>("deprecated-path") {
+                Some(path) => {
+                    log::warn!(
+                        "Passing the path to 'pgm init' positionally is deprecated; use 'pgm init --path {}'",
+                        path
+                    );
+                    path
+                }
+                None => init_matches
+                    .get_one::<String>("path")
+                    .expect("Input argument is required"),
+            };
+            let options = InitOptions {
+                path: path.clone(),
+                existing_db: init_matches.get_flag("existing-db"),
+                compress: init_matches.get_flag("compress"),
+                custom_format: init_matches.get_one::<String>("format").map(String::as_str)
+                    == Some("custom"),
+                constraints: init_matches.get_flag("constraints"),
+                comments: init_matches.get_flag("with-comments"),
+                database_url: init_matches.get_one::<String>("database-url").cloned(),
+                pg_dump_args: init_matches
+                    .get_many::<String>("pg-dump-arg")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+                from_dump: init_matches.get_one::<String>("from-dump").cloned(),
+            };
+            if let Err(e) = pgm::init(&options) {
                 eprintln!("Error during initialization:");
                 for cause in e.chain() {
-                    eprintln!("  - {}", cause);
+                    eprintln!("  - {}", pgm::redact(&cause.to_string()));
                 }
             } else {
-                println!("Initialized successfully");
+                report!("Initialized successfully");
             }
         }
         Some(("apply", apply_matches)) => {
-            let path = apply_matches
-                .get_one::<String>("path")
-                .expect("Input argument is required");
             let dry_run = apply_matches.get_flag("dry-run");
-            let fake = apply_matches.get_flag("fake");
-
-            match commands::apply(path, dry_run, fake) {
-                Ok(_) => {
-                    if !dry_run {
-                        println!("Changes applied successfully");
+            let options = compile_options(apply_matches).and_then(|options| {
+                if apply_matches.contains_id("unchanged-exit-code")
+                    && !["debug", "log", "notice"].contains(&options.messages.as_str())
+                {
+                    return Err(anyhow::anyhow!(
+                        "--unchanged-exit-code needs the applied files to be counted, which --messages above notice prevents"
+                    ));
+                }
+                Ok(ApplyOptions {
+                    dry_run,
+                    fake: apply_matches.get_flag("fake"),
+                    lock_timeout: apply_matches
+                        .get_one::<std::time::Duration>("lock-timeout")
+                        .copied(),
+                    timing: apply_matches.get_flag("timing"),
+                    profile: apply_matches.get_flag("profile"),
+                    allow_empty: apply_matches.get_flag("allow-empty"),
+                    repair: apply_matches.get_flag("repair"),
+                    post_apply: apply_matches
+                        .get_one::<String>("post-apply")
+                        .cloned()
+                        .or_else(|| std::env::var("PGM_POST_APPLY").ok())
+                        .filter(|command| !command.is_empty()),
+                    post_apply_fatal: apply_matches.get_flag("post-apply-fatal"),
+                    file: apply_matches.get_one::<PathBuf>("file").cloned(),
+                    metrics_file: apply_matches
+                        .get_one::<PathBuf>("metrics-file")
+                        .cloned()
+                        .or_else(|| std::env::var_os("PGM_METRICS_FILE").map(PathBuf::from))
+                        .filter(|path| !path.as_os_str().is_empty()),
+                    dump_only: apply_matches.get_flag("dump-only"),
+                    snapshot: apply_matches.get_flag("snapshot"),
+                    role: apply_matches.get_one::<String>("role").cloned(),
+                    fake_migrations: apply_matches
+                        .get_many::<String>("fake-migration")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                    rerun_migrations: apply_matches
+                        .get_many::<String>("rerun-migration")
+                        .unwrap_or_default()
+                        .cloned()
+                        .collect(),
+                    plan: apply_matches
+                        .get_one::<String>("plan")
+                        .map(|path| pgm::Plan::read(path))
+                        .transpose()?,
+                    ..options
+                })
+            });
+            let options = match options {
+                Ok(options) => options,
+                Err(e) => {
+                    eprintln!("Error applying changes:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            };
+            if apply_matches.get_flag("connect") {
+                match pgm::preview(&options) {
+                    Ok(objects) => {
+                        for object in &objects {
+                            println!(
+                                "  {:<19}  {}/{}",
+                                object.action.to_string(),
+                                object.category,
+                                object.name
+                            );
+                        }
+                        let counts = [
+                            pgm::Action::New,
+                            pgm::Action::Reapply,
+                            pgm::Action::Edited,
+                            pgm::Action::Unchanged,
+                            pgm::Action::NoFile,
+                            pgm::Action::Disabled,
+                        ]
+                        .into_iter()
+                        .filter_map(|action| {
+                            let count = objects.iter().filter(|o| o.action == action).count();
+                            (count > 0).then(|| format!("{} {}", count, action))
+                        })
+                        .collect::<Vec<_>>();
+                        if counts.is_empty() {
+                            println!("No objects");
+                        } else {
+                            println!("{}", counts.join(", "));
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Error previewing the changes:");
+                        for cause in e.chain() {
+                            eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                        }
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            if let Some(sql) = apply_matches.get_one::<String>("sql") {
+                let sql = if sql == "-" {
+                    let mut sql = String::new();
+                    if let Err(e) = std::io::stdin().read_to_string(&mut sql) {
+                        eprintln!("Error reading SQL from stdin: {}", e);
+                        std::process::exit(1);
+                    }
+                    sql
+                } else {
+                    sql.clone()
+                };
+                match pgm::run_sql(&sql, &options) {
+                    Ok(Some(elapsed)) => report!("Ran the SQL in {:.1}s", elapsed.as_secs_f64()),
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("Error running SQL:");
+                        for cause in e.chain() {
+                            eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                        }
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            let databases = match database_urls(apply_matches) {
+                Ok(databases) => databases,
+                Err(e) => {
+                    eprintln!("Error applying changes:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            };
+            if !databases.is_empty() {
+                apply_to_databases(&options, &databases, apply_matches);
+                return;
+            }
+            let unchanged = match pgm::apply(&options) {
+                Ok(summary) => {
+                    if let Some(summary) = &summary {
+                        report!("{}", summary);
+                        if apply_matches.get_flag("print-checksum") {
+                            println!("Checksum: sha256:{}", summary.checksum());
+                        }
+                        if !summary.timings().is_empty() {
+                            println!("Slowest objects:");
+                            for (object, elapsed) in summary.timings().iter().take(10) {
+                                println!(
+                                    "  {:>10.1} ms  {}",
+                                    elapsed.as_secs_f64() * 1000.0,
+                                    object
+                                );
+                            }
+                        }
+                        if let Some(profile) = summary.profile() {
+                            print!("{}", profile);
+                        }
+                        if apply_matches.get_flag("verify") {
+                            match pgm::verify(&options) {
+                                Ok(mismatches) if mismatches.is_empty() => {
+                                    report!("Verified the functions and triggers in the database")
+                                }
+                                Ok(mismatches) => {
+                                    eprintln!("The database does not match the files:");
+                                    for mismatch in mismatches {
+                                        eprintln!("  - {}", mismatch);
+                                    }
+                                    std::process::exit(1);
+                                }
+                                Err(e) => {
+                                    eprintln!("Error verifying the database:");
+                                    for cause in e.chain() {
+                                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                                    }
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
                     }
+                    summary.is_some_and(|summary| summary.applied() == 0)
                 }
                 Err(e) => {
                     eprintln!("Error applying changes:");
                     for cause in e.chain() {
-                        eprintln!("  - {}", cause);
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
                     }
                     std::process::exit(1);
                 }
+            };
+
+            if apply_matches.get_flag("seed") && !dry_run {
+                let options = SeedOptions {
+                    path: options.path.clone(),
+                    dry_run: false,
+                    progress: !apply_matches.get_flag("quiet"),
+                    silent: options.silent,
+                    strict: options.strict,
+                    keep_temp: options.keep_temp,
+                    messages: options.messages.clone(),
+                };
+                if let Err(e) = pgm::seed(&options) {
+                    eprintln!("Error seeding database:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+                report!("Database seeded successfully");
+            }
+            if let Some(code) = apply_matches.get_one::<u8>("unchanged-exit-code") {
+                if unchanged {
+                    std::process::exit((*code).into());
+                }
             }
         }
         Some(("create", create_matches)) => match create_matches.subcommand() {
@@ -186,14 +1325,15 @@ fn main() {
                 let path = migration_matches
                     .get_one::<String>("path")
                     .expect("Input argument is required");
-                if let Err(e) = commands::create_migration(path) {
+                let release = migration_matches.get_one::<String>("release");
+                if let Err(e) = pgm::create_migration(path, release.map(String::as_str)) {
                     eprintln!("Error during migration creation:");
                     for cause in e.chain() {
-                        eprintln!("  - {}", cause);
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
                     }
                     std::process::exit(1);
                 } else {
-                    println!("Migration created successfully");
+                    report!("Migration created successfully");
                 }
             }
             Some(("trigger", trigger_matches)) => {
@@ -203,12 +1343,16 @@ fn main() {
                 let name = trigger_matches
                     .get_one::<String>("name")
                     .expect("Name argument is required");
-                if let Err(e) = commands::create_trigger(path, name) {
-                    eprintln!("Error during trigger creation:");
-                    for cause in e.chain() {
-                        eprintln!("  - {}", cause);
+                match pgm::create_trigger(path, name, trigger_matches.get_flag("force")) {
+                    Ok(true) => report!("Trigger '{}' created successfully", name),
+                    Ok(false) => report!("Trigger creation aborted."),
+                    Err(e) => {
+                        eprintln!("Error during trigger creation:");
+                        for cause in e.chain() {
+                            eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                        }
+                        std::process::exit(1);
                     }
-                    std::process::exit(1);
                 }
             }
             Some(("view", view_matches)) => {
@@ -219,12 +1363,16 @@ fn main() {
                     .get_one::<String>("name")
                     .expect("Name argument is required");
 
-                if let Err(e) = commands::create_view(path, name) {
-                    eprintln!("Error during view creation:");
-                    for cause in e.chain() {
-                        eprintln!("  - {}", cause);
+                match pgm::create_view(path, name, view_matches.get_flag("force")) {
+                    Ok(true) => report!("View '{}' created successfully", name),
+                    Ok(false) => report!("View creation aborted."),
+                    Err(e) => {
+                        eprintln!("Error during view creation:");
+                        for cause in e.chain() {
+                            eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                        }
+                        std::process::exit(1);
                     }
-                    std::process::exit(1);
                 }
             }
             Some(("function", function_matches)) => {
@@ -235,26 +1383,35 @@ fn main() {
                     .get_one::<String>("name")
                     .expect("Name argument is required");
 
-                if let Err(e) = commands::create_function(path, name) {
-                    eprintln!("Error during function creation:");
-                    for cause in e.chain() {
-                        eprintln!("  - {}", cause);
+                let template = function_matches
+                    .get_one::<String>("template")
+                    .expect("Template argument has a default");
+
+                let force = function_matches.get_flag("force");
+                match pgm::create_function(path, name, template, force) {
+                    Ok(true) => report!("Function '{}' created successfully", name),
+                    Ok(false) => report!("Function creation aborted."),
+                    Err(e) => {
+                        eprintln!("Error during function creation:");
+                        for cause in e.chain() {
+                            eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                        }
+                        std::process::exit(1);
                     }
-                    std::process::exit(1);
                 }
             }
             Some(("seed", seed_matches)) => {
                 let path = seed_matches
                     .get_one::<String>("path")
                     .expect("Input argument is required");
-                if let Err(e) = commands::create_seed(path) {
+                if let Err(e) = pgm::create_seed(path) {
                     eprintln!("Error during seed creation:");
                     for cause in e.chain() {
-                        eprintln!("  - {}", cause);
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
                     }
                     std::process::exit(1);
                 } else {
-                    println!("Seed created successfully");
+                    report!("Seed created successfully");
                 }
             }
             _ => {}
@@ -263,14 +1420,285 @@ fn main() {
             let path = seed_matches
                 .get_one::<String>("path")
                 .expect("Input argument is required");
-            if let Err(e) = commands::seed(path) {
+            let dry_run = seed_matches.get_flag("dry-run");
+            let options = SeedOptions {
+                path: path.clone(),
+                dry_run,
+                progress: !seed_matches.get_flag("quiet"),
+                silent: seed_matches.get_flag("silent"),
+                strict: seed_matches.get_flag("strict"),
+                keep_temp: seed_matches.get_flag("keep-temp"),
+                messages: seed_matches
+                    .get_one::<String>("messages")
+                    .expect("Has a default")
+                    .clone(),
+            };
+            if let Err(e) = pgm::seed(&options) {
                 eprintln!("Error seeding database:");
                 for cause in e.chain() {
-                    eprintln!("  - {}", cause);
+                    eprintln!("  - {}", pgm::redact(&cause.to_string()));
                 }
                 std::process::exit(1);
+            } else if !dry_run {
+                report!("Database seeded successfully");
+            }
+        }
+        Some(("plan", plan_matches)) => {
+            let output = plan_matches
+                .get_one::<String>("output")
+                .expect("Output argument has a default");
+            let result = compile_options(plan_matches)
+                .and_then(|options| {
+                    pgm::plan(&ApplyOptions {
+                        lock_timeout: plan_matches
+                            .get_one::<std::time::Duration>("lock-timeout")
+                            .copied(),
+                        ..options
+                    })
+                })
+                .and_then(|plan| {
+                    std::fs::write(output, plan.to_json()? + "\n")
+                        .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", output, e))?;
+                    Ok(plan)
+                });
+            match result {
+                Ok(_) => report!("Plan written to {}", output),
+                Err(e) => {
+                    eprintln!("Error making plan:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("squash", squash_matches)) => {
+            let options = pgm::SquashOptions {
+                path: squash_matches
+                    .get_one::<String>("path")
+                    .expect("Input argument is required")
+                    .clone(),
+                database_url: squash_matches.get_one::<String>("database-url").cloned(),
+                pg_dump_args: squash_matches
+                    .get_many::<String>("pg-dump-arg")
+                    .unwrap_or_default()
+                    .cloned()
+                    .collect(),
+            };
+            match pgm::squash(&options) {
+                Ok(squash) => report!(
+                    "Squashed {} migrations into {}; the old files are in {}",
+                    squash.migrations.len(),
+                    squash.baseline.display(),
+                    squash.archive.display()
+                ),
+                Err(e) => {
+                    eprintln!("Error squashing migrations:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("bundle", bundle_matches)) => {
+            let path = bundle_matches
+                .get_one::<String>("path")
+                .expect("Input argument is required");
+            let result = pgm::bundle(path)
+                .and_then(|bundle| bundle.to_json())
+                .and_then(|json| match bundle_matches.get_one::<String>("output") {
+                    Some(output) => std::fs::write(output, json + "\n")
+                        .map_err(|e| anyhow::anyhow!("Failed to write '{}': {}", output, e)),
+                    None => {
+                        println!("{}", json);
+                        Ok(())
+                    }
+                });
+            if let Err(e) = result {
+                eprintln!("Error creating bundle:");
+                for cause in e.chain() {
+                    eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                }
+                std::process::exit(1);
+            }
+        }
+        Some(("describe", describe_matches)) => {
+            let path = describe_matches
+                .get_one::<String>("path")
+                .expect("Input argument is required");
+            match pgm::describe(path) {
+                Ok(description) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&description)
+                        .expect("Description serializes to JSON")
+                ),
+                Err(e) => {
+                    eprintln!("Error describing the directory:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(("diff", diff_matches)) => {
+            let dirs = diff_matches
+                .get_many::<String>("dirs")
+                .map(|dirs| dirs.cloned().collect::<Vec<_>>());
+            let options = DiffOptions {
+                path: match &dirs {
+                    Some(dirs) => dirs[1].clone(),
+                    None => diff_matches
+                        .get_one::<String>("path")
+                        .expect("Input argument is required")
+                        .clone(),
+                },
+                base: dirs.map(|dirs| dirs[0].clone()),
+                categories: match diff_matches.get_many::<String>("category") {
+                    Some(categories) => categories.cloned().collect(),
+                    None => DiffOptions::default().categories,
+                },
+            };
+            let github =
+                diff_matches.get_one::<String>("format").map(String::as_str) == Some("github");
+            let diffs = match pgm::diff(&options) {
+                Ok(diffs) => diffs,
+                Err(e) if github => {
+                    let causes = e
+                        .chain()
+                        .map(|cause| pgm::redact(&cause.to_string()))
+                        .collect::<Vec<_>>();
+                    println!(
+                        "{}",
+                        github_annotation(
+                            "error",
+                            None,
+                            &format!("Error comparing: {}", causes.join(": "))
+                        )
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    eprintln!("Error comparing:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            };
+            if github {
+                let against_db = options.base.is_none();
+                for diff in &diffs {
+                    let object = format!("{}/{}", diff.category, diff.name);
+                    let (level, message) = match diff.change {
+                        Change::Added if against_db => {
+                            ("warning", format!("{} is not applied yet", object))
+                        }
+                        // Applied migrations never run again
+                        Change::Changed if against_db && diff.category == "migrations" => (
+                            "error",
+                            format!(
+                                "{} was edited after it was applied, so the edit will never run",
+                                object
+                            ),
+                        ),
+                        Change::Changed if against_db => (
+                            "warning",
+                            format!("{} changed since it was applied", object),
+                        ),
+                        Change::Removed if against_db => (
+                            "warning",
+                            format!("{} is recorded in the database but has no file", object),
+                        ),
+                        change => ("notice", format!("{} {}", object, change)),
+                    };
+                    println!(
+                        "{}",
+                        github_annotation(level, diff.path.as_deref(), &message)
+                    );
+                }
+                return;
+            }
+            if diffs.is_empty() {
+                println!("No differences");
+            }
+            for category in pgm::CATEGORIES {
+                let diffs = diffs
+                    .iter()
+                    .filter(|diff| diff.category == category)
+                    .collect::<Vec<_>>();
+                if diffs.is_empty() {
+                    continue;
+                }
+                let counts = [Change::Added, Change::Changed, Change::Removed]
+                    .into_iter()
+                    .filter_map(|change| {
+                        let count = diffs.iter().filter(|diff| diff.change == change).count();
+                        (count > 0).then(|| format!("{} {}", count, change))
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}: {}", category, counts.join(", "));
+                for diff in diffs {
+                    let sign = match diff.change {
+                        Change::Added => '+',
+                        Change::Changed => '~',
+                        Change::Removed => '-',
+                    };
+                    println!("  {} {}", sign, diff.name);
+                    if diff_matches.get_flag("patch") {
+                        if let Some(text_diff) = &diff.text_diff {
+                            if COLOR.load(Ordering::Relaxed) {
+                                print!("{}", color_patch(text_diff));
+                            } else {
+                                print!("{}", text_diff);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Some(("list", list_matches)) => {
+            let options = ListOptions {
+                path: list_matches
+                    .get_one::<String>("path")
+                    .expect("Input argument is required")
+                    .clone(),
+                categories: match list_matches.get_many::<String>("category") {
+                    Some(categories) => categories.cloned().collect(),
+                    None => ListOptions::default().categories,
+                },
+            };
+            let objects = match pgm::list(&options) {
+                Ok(objects) => objects,
+                Err(e) => {
+                    eprintln!("Error listing objects:");
+                    for cause in e.chain() {
+                        eprintln!("  - {}", pgm::redact(&cause.to_string()));
+                    }
+                    std::process::exit(1);
+                }
+            };
+            if list_matches.get_one::<String>("format").map(String::as_str) == Some("json") {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&objects).expect("Objects serialize to JSON")
+                );
             } else {
-                println!("Database seeded successfully");
+                let verbose = list_matches.get_flag("verbose");
+                for object in objects {
+                    let status = match (object.applied, &object.applied_at) {
+                        (Some(false), _) => " (pending)".to_string(),
+                        (_, Some(applied_at)) if verbose => {
+                            format!(" (applied {}, {})", time_ago(applied_at), applied_at)
+                        }
+                        (_, Some(applied_at)) => format!(" (applied {})", time_ago(applied_at)),
+                        (Some(true), None) => " (applied)".to_string(),
+                        (None, None) => String::new(),
+                    };
+                    let disabled = if object.disabled { " (disabled)" } else { "" };
+                    println!("{}/{}{}{}", object.category, object.name, status, disabled);
+                }
             }
         }
         _ => {}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+
+fn write(dir: &Path, file: &str, content: &str) {
+    std::fs::write(dir.join(file), content).unwrap();
+}
+
+/// Initializes a directory, applies it, edits a function and applies again,
+/// which re-runs only the function.
+#[test]
+fn reapplies_only_what_changed() {
+    let Some(database) = TestDatabase::create("apply") else {
+        return;
+    };
+    let root = tempfile::tempdir().unwrap();
+    let dir = root.path().join("db");
+
+    let output = database.pgm(&["init"], &dir);
+    assert!(output.status.success(), "{output:?}");
+    let output = database.pgm(&["create", "migration"], &dir);
+    assert!(output.status.success(), "{output:?}");
+    write(
+        &dir,
+        "migrations/00001.sql",
+        "CREATE TABLE items (id int, price int);\n",
+    );
+    write(
+        &dir,
+        "functions/total.sql",
+        "CREATE OR REPLACE FUNCTION total() RETURNS bigint AS $$ SELECT sum(price) FROM items $$ LANGUAGE sql;\n",
+    );
+    write(
+        &dir,
+        "views/totals.sql",
+        "CREATE OR REPLACE VIEW totals AS SELECT total();\n",
+    );
+
+    let output = database.pgm(&["apply"], &dir);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Applied 1 of 1 migrations, 1 of 1 functions, 1 of 1 views"),
+        "{stdout}"
+    );
+    assert_eq!(database.query("SELECT name FROM pgm_migration"), "00001");
+    assert_eq!(database.query("SELECT name FROM pgm_function"), "total");
+    assert_eq!(database.query("SELECT name FROM pgm_view"), "totals");
+    database.query("INSERT INTO items VALUES (1, 2), (2, 3)");
+    assert_eq!(database.query("SELECT * FROM totals"), "5");
+    let hash = database.query("SELECT hash FROM pgm_function");
+
+    write(
+        &dir,
+        "functions/total.sql",
+        "CREATE OR REPLACE FUNCTION total() RETURNS bigint AS $$ SELECT sum(price) * 2 FROM items $$ LANGUAGE sql;\n",
+    );
+    let output = database.pgm(&["apply"], &dir);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Applied 0 of 1 migrations, 1 of 1 functions, 0 of 1 views"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("- Skipped migration: 00001"), "{stdout}");
+    assert_ne!(database.query("SELECT hash FROM pgm_function"), hash);
+    assert_eq!(database.query("SELECT * FROM totals"), "10");
+
+    let output = database.pgm(&["apply"], &dir);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("; nothing changed"), "{stdout}");
+}
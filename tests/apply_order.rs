@@ -0,0 +1,71 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+const VIEWS_FIRST: &str =
+    "extensions,baselines,functions,triggers,views,migrations,constraints,checks,grants";
+
+#[test]
+fn applies_views_before_the_migrations_using_them() {
+    let Some(db) = TestDatabase::create("apply_order") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "views"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    std::fs::write(
+        dir.path().join("migrations/00000_initial.sql"),
+        "CREATE TABLE users (id int, active bool);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001_active_users.sql"),
+        "CREATE TABLE active_user_ids AS SELECT id FROM active_users;\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("views/active_users.sql"),
+        "CREATE OR REPLACE VIEW active_users AS SELECT * FROM users WHERE active;\n",
+    )
+    .unwrap();
+
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+
+    let output = db.pgm(&["apply", "--order", VIEWS_FIRST], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        db.query("SELECT count(*) FROM pgm_migration WHERE name = '00001_active_users'"),
+        "1"
+    );
+}
+
+#[test]
+fn rejects_an_order_checking_function_bodies_too_early() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("functions")).unwrap();
+    std::fs::write(
+        dir.path().join("functions/f.sql"),
+        "CREATE FUNCTION f() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--path"])
+        .arg(dir.path())
+        .env(
+            "PGM_APPLY_ORDER",
+            "extensions,baselines,checks,functions,triggers,migrations,views,grants",
+        )
+        .output()
+        .expect("Failed to run pgm");
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'functions' must come before 'checks'"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("'constraints' is missing"), "{stderr}");
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn fails_early_on_truncated_object_files() {
+    let Some(db) = TestDatabase::create("balance_check") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions", "views"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id int, name text);\n",
+    );
+    // Quotes, comments and parameters that only look unbalanced
+    write(
+        "functions/greet.sql",
+        "CREATE OR REPLACE FUNCTION greet(name text) RETURNS text LANGUAGE plpgsql AS $body$
+BEGIN
+    -- Say hi :)
+    /* nested /* comment ( */ */
+    RETURN 'it''s ' || $1 || E'\\'s (';
+END;
+$body$;
+",
+    );
+    write(
+        "views/user_names.sql",
+        "CREATE OR REPLACE VIEW user_names AS SELECT \"name\" FROM users WHERE (id > 0);\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    for (sql, problem) in [
+        (
+            "CREATE OR REPLACE FUNCTION greet(name text) RETURNS text LANGUAGE plpgsql AS $body$\nBEGIN\n",
+            "the dollar quote $body$ opened on line 1 is never closed",
+        ),
+        (
+            "CREATE OR REPLACE FUNCTION greet(name text) RETURNS text LANGUAGE sql AS 'SELECT name",
+            "the string opened on line 1 is never closed",
+        ),
+        (
+            "CREATE OR REPLACE FUNCTION greet(\n  name text",
+            "the parenthesis opened on line 1 is never closed",
+        ),
+    ] {
+        write("functions/greet.sql", sql);
+        let output = db.pgm(&["apply"], dir.path());
+        assert!(!output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("functions/greet.sql', "), "{stderr}");
+        assert!(stderr.contains(problem), "{stderr}");
+        assert!(stderr.contains("may be truncated or malformed"), "{stderr}");
+    }
+
+    // Without the check, psql is left to fail on it
+    let output = db.pgm(&["apply", "--no-balance-check"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("may be truncated"), "{stderr}");
+    assert_eq!(db.query("SELECT greet('x')"), "it's x's (");
+}
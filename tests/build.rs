@@ -0,0 +1,321 @@
+use pgm::{build, bundle, checksum, ApplyOptions, Bundle};
+
+/// Creates a pgm directory with a migration, a function and a view.
+fn pgm_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE t (id int);\n"),
+        (
+            "functions/f.sql",
+            "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+        ),
+        ("views/v.sql", "CREATE OR REPLACE VIEW v AS SELECT f();\n"),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// Whether `sql` has a `-- <kind> <tag> <file> --` marker line.
+fn has_marker(sql: &str, kind: &str, file: &str) -> bool {
+    sql.lines().any(|line| {
+        line.strip_prefix(&format!("-- {kind} "))
+            .and_then(|rest| rest.split_once(' '))
+            .is_some_and(|(_, rest)| rest == format!("{file} --"))
+    })
+}
+
+fn options(dir: &tempfile::TempDir) -> ApplyOptions {
+    ApplyOptions {
+        path: dir.path().to_str().unwrap().to_string(),
+        dry_run: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn compiles_every_category_into_one_do_block() {
+    let dir = pgm_dir();
+    let sql = build(&options(&dir)).unwrap();
+
+    assert!(sql.contains("\nDO $pgm$ BEGIN"), "{sql}");
+    assert!(sql.trim_end().ends_with("END $pgm$;"), "{sql}");
+    assert!(sql.contains("CREATE TABLE t (id int);"), "{sql}");
+    assert!(sql.contains("CREATE OR REPLACE FUNCTION f()"), "{sql}");
+    assert!(sql.contains("CREATE OR REPLACE VIEW v"), "{sql}");
+}
+
+#[test]
+fn header_records_a_deterministic_checksum_of_the_body() {
+    let dir = pgm_dir();
+    let sql = build(&options(&dir)).unwrap();
+    let header = sql.lines().take(3).collect::<Vec<_>>();
+    let body = sql.splitn(4, '\n').last().unwrap();
+
+    assert!(header[0].starts_with("-- Compiled by pgm "), "{sql}");
+    assert!(header[1].starts_with("-- Compiled at "), "{sql}");
+    assert_eq!(header[2], format!("-- sha256: {}", checksum(body)));
+
+    let rebuilt = build(&options(&dir)).unwrap();
+    assert_eq!(rebuilt.lines().nth(2), Some(header[2]));
+}
+
+#[test]
+fn leaves_out_categories_not_selected() {
+    let dir = pgm_dir();
+    let sql = build(&ApplyOptions {
+        categories: vec!["views".to_string()],
+        ..options(&dir)
+    })
+    .unwrap();
+
+    assert!(sql.contains("CREATE OR REPLACE VIEW v"), "{sql}");
+    assert!(!sql.contains("CREATE TABLE t"), "{sql}");
+    assert!(!sql.contains("FUNCTION f()"), "{sql}");
+}
+
+#[test]
+fn fails_on_files_resolving_to_the_same_name() {
+    let dir = pgm_dir();
+    std::fs::write(dir.path().join("functions/F.SQL"), "SELECT 1;\n").unwrap();
+    let error = build(&options(&dir)).unwrap_err();
+
+    assert!(format!("{error:#}").contains("functions/f: "), "{error:#}");
+}
+
+#[test]
+fn fails_on_a_missing_directory() {
+    let dir = pgm_dir();
+    let error = build(&ApplyOptions {
+        path: dir.path().join("missing").to_str().unwrap().to_string(),
+        ..options(&dir)
+    })
+    .unwrap_err();
+
+    assert!(format!("{error:#}").contains("not found"), "{error:#}");
+}
+
+#[test]
+fn fails_on_a_directory_without_files_unless_allowed() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    let error = build(&options(&dir)).unwrap_err();
+    assert!(
+        error.to_string().contains("No objects found in "),
+        "{error:#}"
+    );
+
+    let sql = build(&ApplyOptions {
+        allow_empty: true,
+        ..options(&dir)
+    })
+    .unwrap();
+    assert!(sql.contains("DO $pgm$ BEGIN"), "{sql}");
+}
+
+#[test]
+fn compiles_a_bundle_like_the_directory_it_was_made_from() {
+    let dir = pgm_dir();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut gz, b"CREATE TABLE u (id int);\n").unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00002.sql.gz"),
+        gz.finish().unwrap(),
+    )
+    .unwrap();
+
+    let bundle = Bundle::from_slice(
+        bundle(dir.path().to_str().unwrap())
+            .unwrap()
+            .to_json()
+            .unwrap()
+            .as_bytes(),
+    )
+    .unwrap();
+    assert!(bundle.files.contains_key("migrations/00002.sql.gz"));
+
+    let from_dir = build(&options(&dir)).unwrap();
+    let from_bundle = build(&ApplyOptions {
+        bundle: Some(bundle),
+        ..options(&dir)
+    })
+    .unwrap();
+    assert!(
+        from_bundle.contains("CREATE TABLE u (id int);"),
+        "{from_bundle}"
+    );
+    assert_eq!(from_dir.lines().nth(2), from_bundle.lines().nth(2));
+}
+
+#[test]
+fn leaves_out_the_files_listed_in_pgmignore() {
+    let dir = pgm_dir();
+    std::fs::write(
+        dir.path().join("functions/scratch.draft.sql"),
+        "SELECT broken(;\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join(".pgmignore"), "*.draft.sql\nviews/\n").unwrap();
+
+    let sql = build(&options(&dir)).unwrap();
+    assert!(has_marker(&sql, "RUN", "00001.sql"), "{sql}");
+    assert!(sql.contains("functions/f --"), "{sql}");
+    assert!(!sql.contains("scratch"), "{sql}");
+    assert!(!sql.contains("VIEW v"), "{sql}");
+
+    let bundle = bundle(dir.path().to_str().unwrap()).unwrap();
+    assert!(bundle.files.contains_key("functions/f.sql"));
+    assert!(!bundle.files.contains_key("functions/scratch.draft.sql"));
+    assert!(!bundle.files.contains_key("views/v.sql"));
+}
+
+#[test]
+fn times_every_object_only_when_asked() {
+    let dir = pgm_dir();
+    let sql = build(&options(&dir)).unwrap();
+    assert!(!sql.contains("⏱"), "{sql}");
+
+    let sql = build(&ApplyOptions {
+        timing: true,
+        ..options(&dir)
+    })
+    .unwrap();
+    for object in ["migrations/00001", "functions/f", "views/v"] {
+        assert!(
+            sql.contains(&format!("RAISE NOTICE '⏱ {object}: % ms'")),
+            "{sql}"
+        );
+    }
+}
+
+#[test]
+fn minifies_independently_of_dry_run() {
+    let dir = pgm_dir();
+    // A line of a file looking like a marker is still a comment
+    std::fs::write(
+        dir.path().join("functions/g.sql"),
+        "CREATE OR REPLACE FUNCTION g() RETURNS int AS $$\n-- RUN 00001.sql --\nSELECT 1\n$$ LANGUAGE sql;\n",
+    )
+    .unwrap();
+    let body = |sql: String| sql.lines().skip(3).collect::<Vec<_>>().join("\n");
+    let applied = body(
+        build(&ApplyOptions {
+            dry_run: false,
+            ..options(&dir)
+        })
+        .unwrap(),
+    );
+    let minified = body(
+        build(&ApplyOptions {
+            minify: Some(true),
+            ..options(&dir)
+        })
+        .unwrap(),
+    );
+    assert_eq!(minified, applied);
+    assert!(!minified.contains("-- RUN"), "{minified}");
+
+    let pretty = body(
+        build(&ApplyOptions {
+            minify: Some(true),
+            pretty: true,
+            ..options(&dir)
+        })
+        .unwrap(),
+    );
+    assert!(has_marker(&pretty, "RUN", "00001.sql"), "{pretty}");
+    assert!(has_marker(&pretty, "DONE", "00001.sql"), "{pretty}");
+    assert!(!pretty.contains("-- RUN 00001.sql --"), "{pretty}");
+    assert!(!pretty.contains("-- Create tables"), "{pretty}");
+
+    let unminified = build(&ApplyOptions {
+        dry_run: false,
+        minify: Some(false),
+        ..options(&dir)
+    })
+    .unwrap();
+    assert!(unminified.contains("-- Create tables"), "{unminified}");
+}
+
+#[test]
+fn orders_files_after_the_files_they_depend_on() {
+    let dir = pgm_dir();
+    std::fs::write(
+        dir.path().join("views/a.sql"),
+        "-- pgm: depends=[v]\nCREATE OR REPLACE VIEW a AS SELECT * FROM v;\n",
+    )
+    .unwrap();
+    let sql = build(&options(&dir)).unwrap();
+    let position = |view: &str| sql.find(&format!("VIEW {view} AS")).unwrap();
+    assert!(position("v") < position("a"), "{sql}");
+
+    std::fs::write(
+        dir.path().join("views/v.sql"),
+        "-- pgm: depends=[a]\nCREATE OR REPLACE VIEW v AS SELECT 1;\n",
+    )
+    .unwrap();
+    let error = build(&options(&dir)).unwrap_err();
+    assert!(
+        format!("{error:#}").contains("cycle: a -> v -> a"),
+        "{error:#}"
+    );
+}
+
+#[test]
+fn fails_on_files_asking_to_run_outside_of_a_transaction() {
+    let dir = pgm_dir();
+    std::fs::write(
+        dir.path().join("migrations/00002.sql"),
+        "-- pgm: no-transaction\nCREATE INDEX CONCURRENTLY i ON t (id);\n",
+    )
+    .unwrap();
+    let error = build(&options(&dir)).unwrap_err();
+    assert!(
+        format!("{error:#}").contains("'no-transaction' is not supported"),
+        "{error:#}"
+    );
+}
+
+#[test]
+fn compiles_crlf_files_like_their_lf_versions() {
+    let lf_dir = pgm_dir();
+    let crlf_dir = pgm_dir();
+    for file in ["migrations/00001.sql", "functions/f.sql", "views/v.sql"] {
+        let path = crlf_dir.path().join(file);
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replace('\n', "\r\n")).unwrap();
+    }
+
+    // Paths show up in notices, so they are left out of the comparison
+    let body = |dir: &tempfile::TempDir| {
+        let sql = build(&options(dir)).unwrap();
+        sql.splitn(4, '\n')
+            .last()
+            .unwrap()
+            .replace(dir.path().to_str().unwrap(), "<dir>")
+    };
+    let crlf_body = body(&crlf_dir);
+    assert!(!crlf_body.contains('\r'), "{crlf_body}");
+    assert_eq!(crlf_body, body(&lf_dir));
+}
+
+#[test]
+fn drops_a_byte_order_mark_and_rejects_invalid_utf8() {
+    let dir = pgm_dir();
+    let plain = build(&options(&dir)).unwrap();
+    let path = dir.path().join("functions/f.sql");
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, format!("\u{feff}{content}")).unwrap();
+    let sql = build(&options(&dir)).unwrap();
+    assert!(!sql.contains('\u{feff}'), "{sql}");
+    assert_eq!(sql.lines().nth(2), plain.lines().nth(2));
+
+    std::fs::write(&path, b"SELECT '\xe9t\xe9';\n").unwrap();
+    let error = build(&options(&dir)).unwrap_err();
+    assert!(
+        format!("{error:#}").contains("f.sql' is not valid UTF-8 (at byte 8)"),
+        "{error:#}"
+    );
+}
@@ -0,0 +1,81 @@
+// Each test binary compiles this module on its own and uses only part of it
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Creates a temporary pgm directory holding `files`, each a path relative to
+/// it and its content.
+pub fn pgm_dir(files: &[(&str, &str)]) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in files {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// A scratch database, created through the database named by
+/// `PGM_TEST_DATABASE` (with the other PG* variables as usual) and dropped
+/// again when this is dropped. Tests needing Postgres are skipped when
+/// `PGM_TEST_DATABASE` is not set.
+pub struct TestDatabase {
+    pub name: String,
+    admin_database: String,
+}
+
+impl TestDatabase {
+    pub fn create(test_name: &str) -> Option<Self> {
+        let Ok(admin_database) = std::env::var("PGM_TEST_DATABASE") else {
+            eprintln!("Skipping {test_name}: PGM_TEST_DATABASE is not set");
+            return None;
+        };
+        let database = Self {
+            name: format!("pgm_test_{}_{}", test_name, std::process::id()),
+            admin_database,
+        };
+        database.admin(&format!("DROP DATABASE IF EXISTS {}", database.name));
+        database.admin(&format!("CREATE DATABASE {}", database.name));
+        Some(database)
+    }
+
+    fn admin(&self, sql: &str) {
+        let output = Command::new("psql")
+            .args(["-X", "-q", "-d", &self.admin_database, "-c", sql])
+            .output()
+            .expect("Failed to run psql");
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    /// Runs `pgm` with `args` against this database.
+    pub fn pgm(&self, args: &[&str], pgm_dir: &Path) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_pgm"))
+            .args(args)
+            .arg("--path")
+            .arg(pgm_dir)
+            .env("PGDATABASE", &self.name)
+            .output()
+            .expect("Failed to run pgm")
+    }
+
+    /// Runs `sql` in this database and returns its unaligned output.
+    pub fn query(&self, sql: &str) -> String {
+        let output = Command::new("psql")
+            .args(["-X", "-q", "-A", "-t", "-d", &self.name, "-c", sql])
+            .output()
+            .expect("Failed to run psql");
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        // Not asserted, as panicking while a failed test unwinds would abort
+        let drop_database = format!("DROP DATABASE IF EXISTS {}", self.name);
+        let _ = Command::new("psql")
+            .args(["-X", "-q", "-d", &self.admin_database, "-c", &drop_database])
+            .output();
+    }
+}
@@ -0,0 +1,33 @@
+use pgm::create_function;
+
+#[test]
+fn creates_functions_from_built_in_and_project_templates() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+
+    create_function(path, "elevated", "security-definer", true).unwrap();
+    let content = std::fs::read_to_string(dir.path().join("functions/elevated.sql")).unwrap();
+    assert!(
+        content.starts_with("CREATE OR REPLACE FUNCTION elevated ("),
+        "{content}"
+    );
+    assert!(content.contains("SECURITY DEFINER"), "{content}");
+
+    let error = create_function(path, "other", "aggregate", true).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Unknown function template 'aggregate', use one of: plain, trigger, security-definer"
+    );
+
+    std::fs::create_dir_all(dir.path().join("templates/functions")).unwrap();
+    std::fs::write(
+        dir.path().join("templates/functions/plain.sql"),
+        "-- Our own <name_placeholder>\n",
+    )
+    .unwrap();
+    create_function(path, "mine", "plain", true).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("functions/mine.sql")).unwrap(),
+        "-- Our own mine\n"
+    );
+}
@@ -0,0 +1,22 @@
+use pgm::{create_function, create_view};
+
+#[test]
+fn refuses_reserved_keywords_without_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().to_str().unwrap();
+
+    let error = create_view(path, "order", false).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "'order' is a reserved keyword in Postgres, so the view would have to be quoted everywhere it is used; consider renaming it, or pass --force to create it anyway"
+    );
+    assert!(!dir.path().join("views/order.sql").exists());
+    let error = create_function(path, "billing.Select", "plain", false).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("'Select' is a reserved keyword"));
+
+    assert!(create_view(path, "order", true).unwrap());
+    assert!(dir.path().join("views/order.sql").exists());
+    assert!(create_view(path, "orders", false).unwrap());
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn applies_a_custom_format_baseline_then_the_text_migrations() {
+    let Some(source) = TestDatabase::create("custom_format_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("custom_format_target") else {
+        return;
+    };
+    source.query("CREATE TABLE users (id int PRIMARY KEY, name text)");
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db", "--format", "custom", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &source.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let baseline = std::fs::read(pgm_dir.join("migrations/00000.dump")).unwrap();
+    assert!(baseline.starts_with(b"PGDMP"));
+    assert!(!pgm_dir.join("migrations/00000.sql").exists());
+
+    std::fs::write(
+        pgm_dir.join("migrations/00001_add_email.sql"),
+        "ALTER TABLE users ADD COLUMN email text;",
+    )
+    .unwrap();
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        target.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00000,00001_add_email"
+    );
+    assert_eq!(
+        target.query(
+            "SELECT string_agg(column_name, ',' ORDER BY ordinal_position) FROM information_schema.columns WHERE table_name = 'users'"
+        ),
+        "id,name,email"
+    );
+
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("; nothing changed"), "{stdout}");
+}
+
+/// Writes a pg_restore at `path` that logs its arguments to `runs` and then
+/// runs `command`, e.g. the real pg_restore.
+fn stub_pg_restore(path: &Path, runs: &Path, command: &str) {
+    std::fs::write(
+        path,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> '{}'\n{}\n",
+            runs.display(),
+            command
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+}
+
+fn init_custom_format(source: &TestDatabase, pgm_dir: &Path) {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db", "--format", "custom", "--path"])
+        .arg(pgm_dir)
+        .env("PGDATABASE", &source.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+}
+
+#[test]
+fn restores_the_baseline_with_pg_restore() {
+    let Some(source) = TestDatabase::create("custom_format_restore_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("custom_format_restore_target") else {
+        return;
+    };
+    source.query("CREATE TABLE users (id int PRIMARY KEY)");
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+    init_custom_format(&source, &pgm_dir);
+    let runs = dir.path().join("runs");
+    let pg_restore = dir.path().join("pg_restore");
+    stub_pg_restore(&pg_restore, &runs, "exec pg_restore \"$@\"");
+
+    // Only reading the baseline does not restore it
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--strict-order", "--path"])
+        .arg(&pgm_dir)
+        .env("PGM_PG_RESTORE", &pg_restore)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    assert!(!runs.exists());
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(sql.contains("-- pgm: restore 00000 "), "{sql}");
+    assert!(!sql.contains("CREATE TABLE public.users"), "{sql}");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &target.name)
+        .env("PGM_PG_RESTORE", &pg_restore)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let restored = std::fs::read_to_string(&runs).unwrap();
+    let restores = restored
+        .lines()
+        .filter(|line| line.contains("--single-transaction"))
+        .count();
+    assert_eq!(restores, 1, "{restored}");
+    assert_eq!(target.query("SELECT count(*) FROM users"), "0");
+    let baseline = std::fs::read(pgm_dir.join("migrations/00000.dump")).unwrap();
+    assert_eq!(
+        target.query("SELECT hash FROM pgm_migration WHERE name = '00000'"),
+        format!("{:x}", md5::compute(baseline))
+    );
+
+    // An applied baseline is not restored again
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &target.name)
+        .env("PGM_PG_RESTORE", &pg_restore)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(std::fs::read_to_string(&runs).unwrap(), restored);
+}
+
+#[test]
+fn does_not_record_a_baseline_pg_restore_failed_to_restore() {
+    let Some(source) = TestDatabase::create("custom_format_failed_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("custom_format_failed_target") else {
+        return;
+    };
+    source.query("CREATE TABLE users (id int PRIMARY KEY)");
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+    init_custom_format(&source, &pgm_dir);
+    let pg_restore = dir.path().join("pg_restore");
+    stub_pg_restore(
+        &pg_restore,
+        &dir.path().join("runs"),
+        "echo 'pg_restore: error: could not connect' >&2\nexit 1",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &target.name)
+        .env("PGM_PG_RESTORE", &pg_restore)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("pg_restore failed to restore migration 00000 with exit code 1"),
+        "{stderr}"
+    );
+    assert_eq!(
+        target.query("SELECT count(*) FROM pgm_migration WHERE name = '00000'"),
+        "0"
+    );
+}
+
+#[test]
+fn format_requires_an_existing_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--format", "custom", "--path"])
+        .arg(dir.path().join("pgm"))
+        .output()
+        .expect("Failed to run pgm");
+    assert!(!output.status.success());
+    assert!(!dir.path().join("pgm").exists());
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn describes_files_and_what_the_database_records() {
+    let Some(database) = TestDatabase::create("describe") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE t (id int);\n"),
+        (
+            "functions/f.sql",
+            "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+        ),
+        ("views/old.sql", "CREATE OR REPLACE VIEW old AS SELECT 1;\n"),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    std::fs::write(
+        dir.path().join("functions/f.sql"),
+        "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 2 $$ LANGUAGE sql;\n",
+    )
+    .unwrap();
+    std::fs::remove_file(dir.path().join("views/old.sql")).unwrap();
+    std::fs::write(
+        dir.path().join("views/new.sql"),
+        "CREATE OR REPLACE VIEW new AS SELECT 1;\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["describe"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let description: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(description["format"], 1);
+    assert_eq!(description["database"], true);
+    let statuses = description["objects"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|object| {
+            format!(
+                "{}/{}: {}",
+                object["category"].as_str().unwrap(),
+                object["name"].as_str().unwrap(),
+                object["status"].as_str().unwrap()
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            "migrations/00001: applied",
+            "functions/f: changed",
+            "views/new: pending"
+        ]
+    );
+    assert_eq!(
+        description["objects"][1]["recorded"]["hash"],
+        database.query("SELECT hash FROM pgm_function WHERE name = 'f'")
+    );
+    let orphans = description["orphans"].as_array().unwrap();
+    assert_eq!(orphans.len(), 1, "{description}");
+    assert_eq!(orphans[0]["name"], "old");
+    assert!(orphans[0]["recorded"]["applied_at"]
+        .as_str()
+        .unwrap()
+        .ends_with('Z'));
+}
+
+#[test]
+fn reads_a_database_missing_the_newer_pgm_tables() {
+    let Some(database) = TestDatabase::create("describe_older") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE t (id int);\n"),
+        (
+            "functions/f.sql",
+            "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+        ),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    let describe = || {
+        let output = database.pgm(&["describe"], dir.path());
+        assert!(output.status.success(), "{output:?}");
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+    };
+
+    // Never applied, so everything is pending
+    let description = describe();
+    assert_eq!(description["database"], true);
+    assert_eq!(description["objects"][0]["status"], "pending");
+
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    // As left by a pgm from before comments, grants and constraints
+    database.query("DROP TABLE pgm_comment, pgm_grant, pgm_constraint, pgm_extension");
+
+    let description = describe();
+    assert_eq!(description["database"], true);
+    assert_eq!(description["objects"][0]["status"], "applied");
+    assert_eq!(description["objects"][1]["status"], "applied");
+
+    let output = database.pgm(&["list"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("migrations/00001 (applied "), "{stdout}");
+
+    let output = database.pgm(&["diff", "--against-db"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "No differences\n");
+
+    let output = database.pgm(&["apply", "--dry-run", "--connect"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2 unchanged"), "{stdout}");
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+use std::process::Command;
+
+fn write(dir: &Path, files: &[(&str, &str)]) {
+    for (file, content) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn reports_the_changes_between_two_directories() {
+    let dir = tempfile::tempdir().unwrap();
+    let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+    write(
+        &from,
+        &[
+            ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+            ("functions/f.sql", "SELECT 1;\n"),
+            ("views/v.sql", "SELECT 2;\n"),
+        ],
+    );
+    write(
+        &to,
+        &[
+            ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+            ("migrations/00002.sql", "CREATE TABLE b (id int);\n"),
+            ("functions/f.sql", "SELECT 3;\n"),
+        ],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["diff", "--patch"])
+        .args([&from, &to])
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("migrations: 1 added\n  + 00002\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("functions: 1 changed\n  ~ f\n"), "{stdout}");
+    assert!(stdout.contains("-SELECT 1;\n+SELECT 3;\n"), "{stdout}");
+    assert!(stdout.contains("views: 1 removed\n  - v\n"), "{stdout}");
+    assert!(!stdout.contains("00001"), "{stdout}");
+}
+
+#[test]
+fn colors_the_patch_only_when_asked() {
+    let dir = tempfile::tempdir().unwrap();
+    let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+    write(&from, &[("functions/f.sql", "SELECT 1;\n")]);
+    write(&to, &[("functions/f.sql", "SELECT 3;\n")]);
+    let diff = |args: &[&str], env: &[(&str, &str)]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+            .args(["diff", "--patch"])
+            .args(args)
+            .args([&from, &to])
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .envs(env.iter().copied())
+            .output()
+            .expect("Failed to run pgm");
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let plain = diff(&[], &[]);
+    assert!(!plain.contains('\x1b'), "{plain}");
+    assert_eq!(
+        diff(&["--color", "never"], &[("CLICOLOR_FORCE", "1")]),
+        plain
+    );
+    let colored = diff(&["--color=always"], &[("NO_COLOR", "1")]);
+    assert!(
+        colored.contains("\x1b[31m-SELECT 1;\x1b[0m\n\x1b[32m+SELECT 3;\x1b[0m\n"),
+        "{colored:?}"
+    );
+    assert_eq!(diff(&[], &[("CLICOLOR_FORCE", "1")]), colored);
+    assert_eq!(
+        diff(&[], &[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]),
+        plain
+    );
+}
+
+#[test]
+fn compares_the_directory_with_the_database() {
+    let Some(database) = TestDatabase::create("diff_against_db") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        &[
+            ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+            (
+                "functions/f.sql",
+                "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+            ),
+            (
+                "functions/g.sql",
+                "CREATE OR REPLACE FUNCTION g() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+            ),
+        ],
+    );
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT count(*) FROM pgm_function"), "2");
+
+    let output = database.pgm(&["diff", "--against-db"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "No differences\n");
+
+    write(
+        dir.path(),
+        &[
+            ("migrations/00002.sql", "CREATE TABLE b (id int);\n"),
+            (
+                "functions/f.sql",
+                "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 2 $$ LANGUAGE sql;\n",
+            ),
+        ],
+    );
+    std::fs::remove_file(dir.path().join("functions/g.sql")).unwrap();
+    let output = database.pgm(&["diff", "--against-db"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "migrations: 1 added\n  + 00002\nfunctions: 1 changed, 1 removed\n  ~ f\n  - g\n"
+    );
+    let output = database.pgm(&["diff", "--against-db", "--format", "github"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let path = |file: &str| dir.path().join(file).display().to_string();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "::warning file={},line=1::migrations/00002 is not applied yet
+::warning file={},line=1::functions/f changed since it was applied
+::warning::functions/g is recorded in the database but has no file
+",
+            path("migrations/00002.sql"),
+            path("functions/f.sql")
+        )
+    );
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn leaves_disabled_objects_out() {
+    let Some(db) = TestDatabase::create("disabled") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id int);\n",
+    );
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    );
+    write(
+        "functions/experimental_two.sql",
+        "CREATE OR REPLACE FUNCTION experimental_two() RETURNS int LANGUAGE sql AS 'SELECT 2';\n",
+    );
+    write(
+        "disabled.txt",
+        "# Not ready yet\nfunctions/experimental_*\n",
+    );
+
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        db.query("SELECT count(*) FROM pg_proc WHERE proname = 'experimental_two'"),
+        "0"
+    );
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',') FROM pgm_function"),
+        "one"
+    );
+
+    let output = db.pgm(&["list"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("functions/experimental_two (disabled)\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("functions/one (applied"), "{stdout}");
+
+    let output = db.pgm(&["apply", "--dry-run", "--connect"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("  disabled             functions/experimental_two\n"),
+        "{stdout}"
+    );
+}
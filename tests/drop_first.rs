@@ -0,0 +1,98 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+
+fn write(dir: &Path, file: &str, content: &str) {
+    let path = dir.join(file);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn drops_functions_and_views_before_recreating_them() {
+    let Some(database) = TestDatabase::create("drop_first") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "functions/answer.sql",
+        "CREATE OR REPLACE FUNCTION answer(x int) RETURNS int AS $$ SELECT 42 $$ LANGUAGE sql;\n",
+    );
+    write(
+        dir.path(),
+        "views/numbers.sql",
+        "CREATE OR REPLACE VIEW numbers AS SELECT 1 AS one, 2 AS two;\n",
+    );
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    // Changing the return type or dropping a column needs a drop
+    let function = "CREATE OR REPLACE FUNCTION answer(x text) RETURNS text AS $$ SELECT 'forty-two' $$ LANGUAGE sql;\n";
+    let view = "CREATE OR REPLACE VIEW numbers AS SELECT 1 AS one;\n";
+    write(dir.path(), "functions/answer.sql", function);
+    write(dir.path(), "views/numbers.sql", view);
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+
+    write(
+        dir.path(),
+        "functions/answer.sql",
+        &format!("-- pgm: drop-first\n{function}"),
+    );
+    write(
+        dir.path(),
+        "views/numbers.sql",
+        &format!("-- pgm: drop-first\n{view}"),
+    );
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT string_agg(p.oid::regprocedure::text, ',') FROM pg_proc p WHERE proname = 'answer'"),
+        "answer(text)"
+    );
+    assert_eq!(database.query("SELECT * FROM numbers"), "1");
+}
+
+#[test]
+fn drops_dependent_objects_only_with_cascade() {
+    let Some(database) = TestDatabase::create("drop_first_cascade") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "functions/answer.sql",
+        "CREATE OR REPLACE FUNCTION answer() RETURNS int AS $$ SELECT 42 $$ LANGUAGE sql;\n",
+    );
+    write(
+        dir.path(),
+        "views/answers.sql",
+        "CREATE OR REPLACE VIEW answers AS SELECT answer();\n",
+    );
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    let function = "CREATE OR REPLACE FUNCTION answer() RETURNS bigint AS $$ SELECT 42::bigint $$ LANGUAGE sql;\n";
+    write(
+        dir.path(),
+        "functions/answer.sql",
+        &format!("-- pgm: drop-first\n{function}"),
+    );
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("other objects depend on it"),
+        "{output:?}"
+    );
+
+    write(
+        dir.path(),
+        "functions/answer.sql",
+        &format!("-- pgm: drop-first cascade\n{function}"),
+    );
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT to_regclass('answers') IS NULL"), "t");
+}
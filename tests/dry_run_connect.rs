@@ -0,0 +1,65 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn lists_what_an_apply_would_do() {
+    let Some(db) = TestDatabase::create("dry_run_connect") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions", "views"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id int);\n",
+    );
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    );
+    write(
+        "functions/two.sql",
+        "CREATE OR REPLACE FUNCTION two() RETURNS int LANGUAGE sql AS 'SELECT 2';\n",
+    );
+    write(
+        "views/user_ids.sql",
+        "CREATE OR REPLACE VIEW user_ids AS SELECT id FROM users;\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id bigint);\n",
+    );
+    write(
+        "migrations/00002_orders.sql",
+        "CREATE TABLE orders (id int);\n",
+    );
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1 + 0';\n",
+    );
+    std::fs::remove_file(dir.path().join("views/user_ids.sql")).unwrap();
+
+    let output = db.pgm(&["apply", "--dry-run", "--connect"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "  edited (not re-run)  migrations/00001_users
+  new                  migrations/00002_orders
+  would apply          functions/one
+  unchanged            functions/two
+  no file (kept)       views/user_ids
+1 new, 1 would apply, 1 edited (not re-run), 1 unchanged, 1 no file (kept)
+"
+    );
+    assert_eq!(
+        db.query("SELECT count(*) FROM pgm_migration"),
+        "1",
+        "nothing is applied"
+    );
+}
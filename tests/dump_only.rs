@@ -0,0 +1,54 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn dump_only_compiles_a_plain_schema_script() {
+    let Some(database) = TestDatabase::create("dump_only") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00000.sql", "CREATE TABLE items (id int);\n"),
+        (
+            "migrations/00001.sql",
+            "ALTER TABLE items ADD COLUMN touched boolean;\n",
+        ),
+        (
+            "functions/item_count.sql",
+            "CREATE OR REPLACE FUNCTION item_count() RETURNS bigint AS $$ SELECT count(*) FROM items $$ LANGUAGE sql;\n",
+        ),
+        (
+            "triggers/touch.sql",
+            "CREATE OR REPLACE FUNCTION touch() RETURNS trigger AS $$ BEGIN NEW.touched := true; RETURN NEW; END $$ LANGUAGE plpgsql;\nCREATE TRIGGER touch BEFORE INSERT ON items FOR EACH ROW EXECUTE FUNCTION touch();\n",
+        ),
+        (
+            "views/item_counts.sql",
+            "CREATE OR REPLACE VIEW item_counts AS SELECT item_count();\n",
+        ),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let output = database.pgm(&["apply", "--dump-only"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+
+    let output = database.pgm(&["apply", "--dry-run", "--dump-only"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let dump = String::from_utf8_lossy(&output.stdout);
+    assert!(!dump.contains("pgm_"), "{dump}");
+    assert!(!dump.contains("DO $pgm$"), "{dump}");
+
+    database.query(&dump);
+    assert_eq!(
+        database.query("INSERT INTO items (id) VALUES (1); SELECT touched FROM items"),
+        "t"
+    );
+    assert_eq!(database.query("SELECT * FROM item_counts"), "1");
+    assert_eq!(
+        database.query("SELECT to_regclass('pgm_migration') IS NULL"),
+        "t"
+    );
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::TestDatabase;
+use std::io::Write;
+use std::path::Path;
+
+fn write_colliding_migrations(dir: &Path) {
+    std::fs::create_dir_all(dir.join("migrations")).unwrap();
+    std::fs::write(
+        dir.join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(b"CREATE TABLE customers (id int);\n").unwrap();
+    std::fs::write(
+        dir.join("migrations/00001_users.sql.gz"),
+        gz.finish().unwrap(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn fails_on_migrations_with_the_same_name() {
+    let dir = tempfile::tempdir().unwrap();
+    write_colliding_migrations(dir.path());
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--path"])
+        .arg(dir.path())
+        .output()
+        .expect("Failed to run pgm");
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Several files resolve to the same name"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("migrations/00001_users: "), "{stderr}");
+}
+
+#[test]
+fn skips_the_later_migration_on_conflict_skip() {
+    let Some(db) = TestDatabase::create("duplicate_names") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write_colliding_migrations(dir.path());
+
+    let output = db.pgm(&["apply", "--on-conflict", "skip"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Skipping"), "{stderr}");
+    assert!(stderr.contains("00001_users.sql.gz"), "{stderr}");
+    assert_eq!(db.query("SELECT to_regclass('users') IS NOT NULL"), "t");
+    assert_eq!(db.query("SELECT to_regclass('customers') IS NULL"), "t");
+    assert_eq!(
+        db.query("SELECT name FROM pgm_migration ORDER BY name"),
+        "00001_users"
+    );
+}
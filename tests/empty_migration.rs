@@ -0,0 +1,29 @@
+mod common;
+
+use common::{pgm_dir, TestDatabase};
+
+#[test]
+fn empty_migration_runs_once_it_has_content() {
+    let Some(database) = TestDatabase::create("empty_migration") else {
+        return;
+    };
+    let dir = pgm_dir(&[("migrations/00001.sql", "-- TODO\n\n")]);
+    let migration = dir.path().join("migrations/00001.sql");
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Skipping empty migration"),
+        "{output:?}"
+    );
+    assert_eq!(database.query("SELECT count(*) FROM pgm_migration"), "0");
+
+    std::fs::write(
+        &migration,
+        "CREATE TABLE t (id int);\nINSERT INTO t VALUES (1);\n",
+    )
+    .unwrap();
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT count(*) FROM t"), "1");
+    assert_eq!(database.query("SELECT name FROM pgm_migration"), "00001");
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn loads_the_given_env_file() {
+    let Some(db) = TestDatabase::create("env_file") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    let metrics = dir.path().join("staging.log");
+    let env_file = dir.path().join(".env.staging");
+    std::fs::write(
+        &env_file,
+        format!("PGM_METRICS_FILE={}\n", metrics.display()),
+    )
+    .unwrap();
+
+    let output = db.pgm(
+        &["apply", "--env-file", env_file.to_str().unwrap()],
+        dir.path(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(metrics.is_file());
+    assert_eq!(db.query("SELECT count(*) FROM pgm_migration"), "1");
+
+    let missing = dir.path().join(".env.production");
+    let output = db.pgm(
+        &["apply", "--env-file", missing.to_str().unwrap()],
+        dir.path(),
+    );
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("Failed to read '{}'", missing.display())),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn creates_and_updates_extensions_before_everything_else() {
+    let Some(database) = TestDatabase::create("extensions") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("extensions/uuid-ossp.sql", "-- pgm: schema=ext\n"),
+        ("extensions/hstore.sql", "-- pgm: version=1.7\n"),
+        (
+            "migrations/00000.sql",
+            "CREATE TABLE t (id uuid DEFAULT ext.uuid_generate_v4(), tags hstore);\n",
+        ),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    database.query("CREATE SCHEMA ext");
+
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied 2 of 2 extensions"), "{stdout}");
+    assert_eq!(
+        database.query(
+            "SELECT string_agg(extname || ':' || extnamespace::regnamespace || ':' || extversion, ',' ORDER BY extname) FROM pg_extension WHERE extname <> 'plpgsql'"
+        ),
+        "hstore:public:1.7,uuid-ossp:ext:1.1"
+    );
+
+    std::fs::write(
+        dir.path().join("extensions/hstore.sql"),
+        "-- pgm: version=1.8\n",
+    )
+    .unwrap();
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT extversion FROM pg_extension WHERE extname = 'hstore'"),
+        "1.8"
+    );
+
+    let extracted = dir.path().join("extracted");
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db"])
+        .arg(&extracted)
+        .env("PGDATABASE", &database.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        std::fs::read_to_string(extracted.join("extensions/hstore.sql")).unwrap(),
+        "-- pgm: schema=public; version=1.8\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(extracted.join("extensions/uuid-ossp.sql")).unwrap(),
+        "-- pgm: schema=ext; version=1.1\n"
+    );
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn fake_migration_records_only_the_named_migrations() {
+    let Some(database) = TestDatabase::create("fake_migration") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+        ("migrations/00002_manual.sql", "CREATE TABLE b (id int);\n"),
+        ("migrations/00003.sql", "CREATE TABLE c (id int);\n"),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let output = database.pgm(&["apply", "--fake-migration", "00004"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No migration '00004'"), "{stderr}");
+
+    let output = database.pgm(&["apply", "--fake-migration", "00002"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00001,00002_manual,00003"
+    );
+    assert_eq!(
+        database.query(
+            "SELECT string_agg(relname, ',' ORDER BY relname) FROM pg_class WHERE relname IN ('a', 'b', 'c')"
+        ),
+        "a,c"
+    );
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn applies_to_every_database_continuing_past_failures_when_asked() {
+    let Some(first) = TestDatabase::create("fan_out_first") else {
+        return;
+    };
+    let Some(second) = TestDatabase::create("fan_out_second") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE t (id int);\n",
+    )
+    .unwrap();
+    let missing = format!("dbname={}_missing", first.name);
+    std::fs::write(
+        dir.path().join("databases"),
+        format!(
+            "# tenants\ndbname={}\n{}\ndbname={}\n",
+            first.name, missing, second.name
+        ),
+    )
+    .unwrap();
+    let databases = dir.path().join("databases");
+    let databases = databases.to_str().unwrap();
+
+    let output = first.pgm(&["apply", "--databases", databases], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied to 1 of 3 databases"), "{stdout}");
+    assert!(
+        stdout.contains("not attempted after the failure"),
+        "{stdout}"
+    );
+    assert_eq!(
+        second.query("SELECT count(*) FROM pg_tables WHERE tablename = 't'"),
+        "0"
+    );
+
+    let output = first.pgm(
+        &["apply", "--databases", databases, "--keep-going"],
+        dir.path(),
+    );
+    assert!(!output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied to 2 of 3 databases"), "{stdout}");
+    assert!(stdout.contains(&format!("{missing}: failed")), "{stdout}");
+    assert_eq!(first.query("SELECT name FROM pgm_migration"), "00001");
+    assert_eq!(second.query("SELECT name FROM pgm_migration"), "00001");
+}
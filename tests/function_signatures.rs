@@ -0,0 +1,26 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn records_the_signature_of_every_overload() {
+    let Some(database) = TestDatabase::create("function_signatures") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let function = dir.path().join("functions/add.sql");
+    std::fs::create_dir_all(function.parent().unwrap()).unwrap();
+    std::fs::write(
+        &function,
+        "CREATE OR REPLACE FUNCTION add(a int, b int) RETURNS int AS $$ SELECT a + b $$ LANGUAGE sql;\n\
+         CREATE OR REPLACE FUNCTION add(a text, b text) RETURNS text AS $$ SELECT a || b $$ LANGUAGE sql;\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT signatures FROM pgm_function WHERE name = 'add'"),
+        r#"{"public.add(a integer, b integer)","public.add(a text, b text)"}"#
+    );
+}
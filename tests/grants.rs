@@ -0,0 +1,49 @@
+mod common;
+
+use common::{pgm_dir, TestDatabase};
+
+#[test]
+fn applies_grants_files_again_only_when_they_change() {
+    let Some(database) = TestDatabase::create("grants") else {
+        return;
+    };
+    let role = format!("pgm_test_reader_{}", std::process::id());
+    database.query(&format!("CREATE ROLE {role}"));
+    let grants = format!("GRANT SELECT ON items TO {role};\n");
+    let dir = pgm_dir(&[
+        ("migrations/00001.sql", "CREATE TABLE items (id int);\n"),
+        ("grants/readers.sql", &grants),
+    ]);
+    let privileges = format!(
+        "SELECT has_table_privilege('{role}', 'items', 'SELECT'), has_table_privilege('{role}', 'items', 'INSERT')"
+    );
+
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1 of 1 grants"), "{stdout}");
+    assert_eq!(database.query(&privileges), "t|f");
+    assert_eq!(database.query("SELECT name FROM pgm_grant"), "readers");
+    let hash = database.query("SELECT hash FROM pgm_grant");
+
+    // Revoked by hand, which an unchanged file does not undo
+    database.query(&format!("REVOKE SELECT ON items FROM {role}"));
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("0 of 1 grants"), "{stdout}");
+    assert_eq!(database.query(&privileges), "f|f");
+
+    std::fs::write(
+        dir.path().join("grants/readers.sql"),
+        format!("GRANT SELECT, INSERT ON items TO {role};\n"),
+    )
+    .unwrap();
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query(&privileges), "t|t");
+    assert_eq!(database.query("SELECT count(*) FROM pgm_grant"), "1");
+    assert_ne!(database.query("SELECT hash FROM pgm_grant"), hash);
+
+    database.query(&format!("DROP OWNED BY {role}; DROP ROLE {role}"));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn expands_includes_and_reapplies_on_change() {
+    let Some(db) = TestDatabase::create("includes") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions", "shared"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "shared/audit.sql",
+        "CREATE TABLE audit (at timestamptz DEFAULT now());\n",
+    );
+    write(
+        "shared/answer.sql",
+        "CREATE OR REPLACE FUNCTION answer() RETURNS int LANGUAGE sql AS 'SELECT 42';",
+    );
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id int);\n-- pgm:include ../shared/audit.sql\n",
+    );
+    write(
+        "functions/answer.sql",
+        "-- pgm:include ../shared/answer.sql\n",
+    );
+
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT to_regclass('audit') IS NOT NULL"), "t");
+    assert_eq!(db.query("SELECT answer()"), "42");
+
+    // Changing the included file changes the including one
+    write(
+        "shared/answer.sql",
+        "CREATE OR REPLACE FUNCTION answer() RETURNS int LANGUAGE sql AS 'SELECT 43';",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT answer()"), "43");
+
+    write(
+        "shared/answer.sql",
+        "-- pgm:include ../functions/answer.sql\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Files include each other in a cycle: "),
+        "{stderr}"
+    );
+
+    write("shared/answer.sql", "-- pgm:include missing.sql\n");
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Failed to include 'missing.sql' in '"),
+        "{stderr}"
+    );
+}
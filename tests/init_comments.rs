@@ -0,0 +1,61 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn extracts_comments_and_reapplies_them_on_change() {
+    let Some(source) = TestDatabase::create("init_comments_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("init_comments_target") else {
+        return;
+    };
+    source.query(
+        "CREATE TABLE users (id int, email text);
+         CREATE TABLE orders (id int);
+         COMMENT ON TABLE users IS 'Everyone who signed up';
+         COMMENT ON COLUMN users.email IS E'Lowercased,\nnever shown to others';",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db", "--with-comments"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &source.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let files = std::fs::read_dir(pgm_dir.join("comments"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(files, ["users.sql"]);
+    assert_eq!(
+        std::fs::read_to_string(pgm_dir.join("comments/users.sql")).unwrap(),
+        "COMMENT ON TABLE users IS 'Everyone who signed up';
+COMMENT ON COLUMN users.email IS 'Lowercased,
+never shown to others';
+"
+    );
+
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        target.query("SELECT count(*) FROM pgm_comment WHERE name = 'users'"),
+        "1"
+    );
+
+    std::fs::write(
+        pgm_dir.join("comments/users.sql"),
+        "COMMENT ON TABLE users IS 'Everyone who signed up, including deleted accounts';\n",
+    )
+    .unwrap();
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        target.query("SELECT obj_description('users'::regclass, 'pg_class')"),
+        "Everyone who signed up, including deleted accounts"
+    );
+}
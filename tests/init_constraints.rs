@@ -0,0 +1,77 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+const CONSTRAINTS: &str = "SELECT string_agg(conname || ': ' || pg_get_constraintdef(oid), ', ' ORDER BY conname) FROM pg_constraint WHERE conrelid = 'orders'::regclass AND contype IN ('c', 'f')";
+
+#[test]
+fn extracts_constraints_and_readds_them_on_change() {
+    let Some(source) = TestDatabase::create("init_constraints_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("init_constraints_target") else {
+        return;
+    };
+    source.query(
+        "CREATE TABLE users (id int PRIMARY KEY);
+         CREATE TABLE orders (
+             id int CONSTRAINT \"PosId\" CHECK (id > 0),
+             user_id int CONSTRAINT orders_user_fk REFERENCES users,
+             CONSTRAINT \"v1.small\" CHECK (id < 1000)
+         );",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db", "--constraints", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &source.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let read =
+        |file: &str| std::fs::read_to_string(pgm_dir.join("constraints").join(file)).unwrap();
+    assert_eq!(
+        read("orders.\"PosId\".sql"),
+        "ALTER TABLE IF EXISTS orders DROP CONSTRAINT IF EXISTS \"PosId\";
+ALTER TABLE orders ADD CONSTRAINT \"PosId\" CHECK ((id > 0));
+"
+    );
+    assert_eq!(
+        read("orders.\"v1.small\".sql"),
+        "ALTER TABLE IF EXISTS orders DROP CONSTRAINT IF EXISTS \"v1.small\";
+ALTER TABLE orders ADD CONSTRAINT \"v1.small\" CHECK ((id < 1000));
+"
+    );
+    assert!(read("orders.orders_user_fk.sql")
+        .contains("ADD CONSTRAINT orders_user_fk FOREIGN KEY (user_id) REFERENCES users(id);\n"));
+
+    // The baseline already created them, so the files replace them
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(target.query(CONSTRAINTS), source.query(CONSTRAINTS));
+    assert_eq!(target.query("SELECT count(*) FROM pgm_constraint"), "3");
+    let hashes = "SELECT string_agg(name || ' ' || hash, ',' ORDER BY name) FROM pgm_constraint";
+    let hashes_before = target.query(hashes);
+
+    std::fs::write(
+        pgm_dir.join("constraints/orders.\"PosId\".sql"),
+        read("orders.\"PosId\".sql").replace("id > 0", "id > 1"),
+    )
+    .unwrap();
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        target.query(CONSTRAINTS),
+        "PosId: CHECK ((id > 1)), orders_user_fk: FOREIGN KEY (user_id) REFERENCES users(id), v1.small: CHECK ((id < 1000))"
+    );
+    let hashes_after = target.query(hashes);
+    assert_ne!(hashes_after, hashes_before);
+    assert_eq!(
+        hashes_after.split(',').skip(1).collect::<Vec<_>>(),
+        hashes_before.split(',').skip(1).collect::<Vec<_>>(),
+        "only the changed file is re-run"
+    );
+}
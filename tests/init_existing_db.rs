@@ -0,0 +1,63 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn extracts_functions_triggers_and_views() {
+    let Some(source) = TestDatabase::create("init_existing_db_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("init_existing_db_target") else {
+        return;
+    };
+    source.query(
+        "CREATE TABLE items (id int, touched bool);
+         CREATE FUNCTION double(x int) RETURNS int LANGUAGE sql AS $$ SELECT x * 2 $$;
+         CREATE FUNCTION touch() RETURNS trigger LANGUAGE plpgsql
+             AS $$ BEGIN NEW.touched := true; RETURN NEW; END $$;
+         CREATE TRIGGER items_touch BEFORE INSERT ON items
+             FOR EACH ROW EXECUTE FUNCTION touch();
+         CREATE VIEW doubled AS SELECT id, double(id) FROM items WHERE touched;",
+    );
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &source.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let read = |file: &str| std::fs::read_to_string(pgm_dir.join(file)).unwrap();
+    assert_eq!(
+        read("functions/double.sql"),
+        "CREATE OR REPLACE FUNCTION public.double(x integer)
+ RETURNS integer
+ LANGUAGE sql
+AS $function$ SELECT x * 2 $function$;
+"
+    );
+    assert_eq!(
+        read("triggers/touch.sql"),
+        "CREATE OR REPLACE FUNCTION public.touch()
+ RETURNS trigger
+ LANGUAGE plpgsql
+AS $function$ BEGIN NEW.touched := true; RETURN NEW; END $function$;
+
+CREATE TRIGGER items_touch BEFORE INSERT ON public.items FOR EACH ROW EXECUTE FUNCTION touch();"
+    );
+    // The pretty-printed view varies between Postgres versions
+    let view = read("views/doubled.sql");
+    assert!(
+        view.starts_with("CREATE OR REPLACE VIEW doubled AS\n"),
+        "{view}"
+    );
+    assert!(view.ends_with("touched;\n"), "{view}");
+
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    target.query("INSERT INTO items (id) VALUES (21)");
+    assert_eq!(target.query("SELECT * FROM doubled"), "21|42");
+}
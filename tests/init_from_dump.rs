@@ -0,0 +1,79 @@
+use pgm::{init, InitOptions};
+
+#[test]
+fn initializes_from_a_dump_file_without_connecting() {
+    let dir = tempfile::tempdir().unwrap();
+    let dump_path = dir.path().join("dump.sql");
+    std::fs::write(
+        &dump_path,
+        "\\restrict abc\nSET client_min_messages = warning;\nSELECT pg_catalog.set_config('search_path', '', false);\nCREATE TABLE public.t (id integer);\n\\unrestrict abc\n",
+    )
+    .unwrap();
+    let pgm_dir = dir.path().join("postgres");
+
+    init(&InitOptions {
+        path: pgm_dir.to_str().unwrap().to_string(),
+        from_dump: Some(dump_path.to_str().unwrap().to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(pgm_dir.join("migrations/00000.sql")).unwrap(),
+        "SET client_min_messages = notice;\nCREATE TABLE public.t (id integer);\n"
+    );
+    assert!(pgm_dir.join("functions").is_dir());
+    assert_eq!(
+        std::fs::read_dir(pgm_dir.join("functions"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+fn init_from_dump(dump: &str) -> (std::process::Output, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let dump_path = dir.path().join("dump.sql");
+    std::fs::write(&dump_path, dump).unwrap();
+    let pgm_dir = dir.path().join("postgres");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--from-dump"])
+        .arg(&dump_path)
+        .arg("--path")
+        .arg(&pgm_dir)
+        .output()
+        .expect("Failed to run pgm");
+    let migration =
+        std::fs::read_to_string(pgm_dir.join("migrations/00000.sql")).unwrap_or_default();
+    (output, migration)
+}
+
+#[test]
+fn turns_top_level_selects_into_performs() {
+    let (output, migration) = init_from_dump(
+        "select  pg_catalog.set_config( 'search_path', '', false );\nSELECT pg_catalog.setval('public.s', 5, true);\nSELECT\n    pg_catalog.lo_create('16385');\nCREATE FUNCTION public.f() RETURNS integer\n    LANGUAGE plpgsql\n    AS $_$\nBEGIN\nSELECT 1;\nRETURN 1;\nEND\n$_$;\n",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        migration,
+        "PERFORM pg_catalog.setval('public.s', 5, true);\nPERFORM\n    pg_catalog.lo_create('16385');\nCREATE FUNCTION public.f() RETURNS integer\n    LANGUAGE plpgsql\n    AS $_$\nBEGIN\nSELECT 1;\nRETURN 1;\nEND\n$_$;\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("search_path"), "{stderr}");
+}
+
+#[test]
+fn warns_without_a_search_path_reset() {
+    let (output, migration) =
+        init_from_dump("SET search_path = public, pg_catalog;\nCREATE TABLE t (id integer);\n");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        migration,
+        "SET search_path = public, pg_catalog;\nCREATE TABLE t (id integer);\n"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("does not reset search_path the way pg_dump does"),
+        "{stderr}"
+    );
+}
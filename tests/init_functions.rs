@@ -0,0 +1,45 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn extracts_function_bodies_with_trailing_whitespace_unchanged() {
+    let Some(source) = TestDatabase::create("init_functions_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("init_functions_target") else {
+        return;
+    };
+    // The body ends lines with spaces and a tab, which the result keeps
+    source.query(
+        "CREATE FUNCTION banner() RETURNS text AS $$\nSELECT 'line one   \nline two\t\n'\n$$ LANGUAGE sql",
+    );
+    let body = source.query("SELECT prosrc FROM pg_proc WHERE proname = 'banner'");
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db", "--path"])
+        .arg(&pgm_dir)
+        .env("PGDATABASE", &source.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let function = std::fs::read_to_string(pgm_dir.join("functions/banner.sql")).unwrap();
+    assert!(
+        function.contains("SELECT 'line one   \nline two\t\n'\n"),
+        "{function:?}"
+    );
+
+    let output = target.pgm(&["apply"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        target.query("SELECT prosrc FROM pg_proc WHERE proname = 'banner'"),
+        body
+    );
+    assert_eq!(
+        target.query("SELECT banner()"),
+        source.query("SELECT banner()")
+    );
+}
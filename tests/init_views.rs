@@ -0,0 +1,61 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs `pgm init --existing-db` against `database` into `pgm_dir`.
+fn init_from(database: &TestDatabase, pgm_dir: &Path) {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["init", "--existing-db"])
+        .arg(pgm_dir)
+        .env("PGDATABASE", &database.name)
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+}
+
+fn read_views(pgm_dir: &Path) -> Vec<(String, String)> {
+    let mut views = std::fs::read_dir(pgm_dir.join("views"))
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            (name, std::fs::read_to_string(path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    views.sort();
+    views
+}
+
+#[test]
+fn extracts_views_that_extract_the_same_once_applied() {
+    let Some(source) = TestDatabase::create("init_views_source") else {
+        return;
+    };
+    let Some(target) = TestDatabase::create("init_views_target") else {
+        return;
+    };
+    source.query(
+        "CREATE TABLE orders (id int PRIMARY KEY, amount numeric, status text);
+         CREATE VIEW open_orders AS SELECT id, amount * 1.25 AS gross FROM orders WHERE status IN ('new', 'paid') ORDER BY id;
+         CREATE VIEW order_totals AS SELECT status, count(*) AS orders, CASE WHEN sum(amount) > 100 THEN 'large' ELSE 'small' END AS size FROM open_orders JOIN orders USING (id) GROUP BY status",
+    );
+    let dir = tempfile::tempdir().unwrap();
+
+    init_from(&source, &dir.path().join("first"));
+    let views = read_views(&dir.path().join("first"));
+    assert_eq!(views.len(), 2, "{views:?}");
+    assert!(
+        views[0]
+            .1
+            .starts_with("CREATE OR REPLACE VIEW open_orders AS\n SELECT ")
+            && views[0].1.ends_with(";\n"),
+        "{views:?}"
+    );
+
+    let output = target.pgm(&["apply"], &dir.path().join("first"));
+    assert!(output.status.success(), "{output:?}");
+    init_from(&target, &dir.path().join("second"));
+    assert_eq!(read_views(&dir.path().join("second")), views);
+}
@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn pgm(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(args)
+        .arg("--path")
+        .arg(dir)
+        .env("PGM_TEST_ADMIN_EMAIL", "admin@example.com")
+        .env_remove("PGM_TEST_BASE_URL")
+        .env_remove("PGM_TEST_MISSING")
+        .output()
+        .expect("Failed to run pgm")
+}
+
+fn write(dir: &Path, file: &str, content: &str) {
+    let path = dir.join(file);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+#[test]
+fn seeds_expand_environment_variables() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "seeds/users.sql",
+        "INSERT INTO users (email, url, note) VALUES ('${PGM_TEST_ADMIN_EMAIL}', '${PGM_TEST_BASE_URL:-http://localhost}', '\\${PGM_TEST_ADMIN_EMAIL}');\nSELECT $${\"a\": 1}$$::jsonb;\n",
+    );
+
+    let output = pgm(&["seed", "--dry-run"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        sql.contains("VALUES ('admin@example.com', 'http://localhost', '${PGM_TEST_ADMIN_EMAIL}')"),
+        "{sql}"
+    );
+    assert!(sql.contains("SELECT $${\"a\": 1}$$::jsonb;"), "{sql}");
+
+    write(
+        dir.path(),
+        "seeds/more.sql",
+        "SELECT '${PGM_TEST_MISSING}', '${PGM_TEST_BASE_URL}';\n",
+    );
+    let output = pgm(&["seed", "--dry-run"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("not set in the environment: PGM_TEST_MISSING, PGM_TEST_BASE_URL"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn only_migrations_asking_for_it_expand_environment_variables() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "migrations/00001.sql",
+        "-- pgm: interpolate\nCREATE TABLE a (email text DEFAULT '${PGM_TEST_ADMIN_EMAIL}');\n",
+    );
+    write(
+        dir.path(),
+        "migrations/00002.sql",
+        "CREATE TABLE b (email text DEFAULT '${PGM_TEST_ADMIN_EMAIL}');\n",
+    );
+
+    let output = pgm(&["apply", "--dry-run"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        sql.contains("CREATE TABLE a (email text DEFAULT 'admin@example.com');"),
+        "{sql}"
+    );
+    assert!(
+        sql.contains("CREATE TABLE b (email text DEFAULT '${PGM_TEST_ADMIN_EMAIL}');"),
+        "{sql}"
+    );
+}
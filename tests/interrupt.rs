@@ -0,0 +1,60 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[test]
+fn reports_the_committed_transactions_on_ctrl_c() {
+    let Some(db) = TestDatabase::create("interrupt") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    // Over the maximum size, so it runs in a transaction of its own
+    std::fs::write(
+        dir.path().join("migrations/00002_slow.sql"),
+        "CREATE TABLE orders (id int);\nPERFORM pg_sleep(30);\n",
+    )
+    .unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--max-migration-size", "40", "--path"])
+        .arg(dir.path())
+        .env("PGDATABASE", &db.name)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run pgm");
+
+    // Wait for the first transaction to commit and the slow one to start
+    let started = Instant::now();
+    while db.query("SELECT count(*) FROM pg_stat_activity WHERE query LIKE '%pg_sleep(30)%' AND pid <> pg_backend_pid()") != "1" {
+        assert!(started.elapsed() < Duration::from_secs(20), "The migration never started");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let status = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Interrupted. psql was stopped and its transaction rolled back, but the transactions before it were committed. Committed: migration: 00001_users."),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("nothing was applied"), "{stderr}");
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00001_users"
+    );
+    assert_eq!(db.query("SELECT to_regclass('orders') IS NULL"), "t");
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn apply(db: &TestDatabase, pgm_dir: &Path, temp_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .arg("apply")
+        .args(args)
+        .arg("--path")
+        .arg(pgm_dir)
+        .env("PGDATABASE", &db.name)
+        .env("PGM_TEMP_DIR", temp_dir)
+        .output()
+        .expect("Failed to run pgm")
+}
+
+#[test]
+fn keeps_the_sql_in_the_temp_dir() {
+    let Some(db) = TestDatabase::create("keep_temp") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+    let temp_dir = dir.path().join("tmp");
+    std::fs::create_dir_all(pgm_dir.join("migrations")).unwrap();
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    std::fs::write(
+        pgm_dir.join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+
+    let output = apply(&db, &pgm_dir, &temp_dir, &[]);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT count(*) FROM pgm_migration"), "1");
+    assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+    std::fs::write(
+        pgm_dir.join("migrations/00002_broken.sql"),
+        "SELECT * FROM missing;\n",
+    )
+    .unwrap();
+    let output = apply(&db, &pgm_dir, &temp_dir, &["--keep-temp"]);
+    assert!(!output.status.success(), "{output:?}");
+    let kept = std::fs::read_dir(&temp_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(kept.len(), 1, "{kept:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&kept[0].display().to_string()), "{stderr}");
+    let sql = std::fs::read_to_string(&kept[0]).unwrap();
+    assert!(sql.contains("SELECT * FROM missing;"), "{sql}");
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::pgm_dir;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Runs `pgm list` with `args` against a server that is not there.
+fn list(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .arg("list")
+        .args(args)
+        .arg("--path")
+        .arg(dir)
+        .env("PGHOST", dir.join("no-server"))
+        .output()
+        .expect("Failed to run pgm")
+}
+
+#[test]
+fn lists_the_files_without_a_database() {
+    let dir = pgm_dir(&[
+        ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+        ("migrations/00002_b.sql", "CREATE TABLE b (id int);\n"),
+        ("functions/f.sql", "SELECT 1;\n"),
+        ("functions/README.md", "Not SQL\n"),
+        ("views/v.sql", "SELECT 2;\n"),
+    ]);
+
+    let output = list(&[], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "migrations/00001\nmigrations/00002_b\nfunctions/f\nviews/v\n"
+    );
+
+    let output = list(&["views", "functions"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "functions/f\nviews/v\n"
+    );
+
+    let output = list(&["--format", "json", "migrations"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let objects: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        objects,
+        serde_json::json!([
+            {
+                "category": "migrations",
+                "name": "00001",
+                "path": dir.path().join("migrations/00001.sql"),
+                "disabled": false,
+            },
+            {
+                "category": "migrations",
+                "name": "00002_b",
+                "path": dir.path().join("migrations/00002_b.sql"),
+                "disabled": false,
+            },
+        ])
+    );
+
+    let output = list(&[], &dir.path().join("missing"));
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Have you run 'pgm init'?"),
+        "{output:?}"
+    );
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn list_shows_when_objects_were_applied() {
+    let Some(database) = TestDatabase::create("list_applied_at") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+        ("migrations/00002.sql", "CREATE TABLE b (id int);\n"),
+        (
+            "functions/f.sql",
+            "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+        ),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    // Backdate one migration and add another that is not applied yet
+    database.query(
+        "UPDATE pgm_migration SET applied_at = now() - interval '2 days 1 hour' WHERE name = '00001'",
+    );
+    std::fs::write(
+        dir.path().join("migrations/00003.sql"),
+        "CREATE TABLE c (id int);\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["list"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("migrations/00001 (applied 2 days ago)\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("migrations/00003 (pending)\n"), "{stdout}");
+    assert!(stdout.contains("functions/f (applied "), "{stdout}");
+
+    let output = database.pgm(&["list", "--verbose", "migrations"], dir.path());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let applied_at = database.query(
+        "SELECT to_char(applied_at AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"') FROM pgm_migration WHERE name = '00001'",
+    );
+    assert!(
+        stdout.contains(&format!("(applied 2 days ago, {applied_at})")),
+        "{stdout}"
+    );
+}
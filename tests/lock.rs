@@ -0,0 +1,41 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn fails_when_another_apply_holds_the_lock_past_the_timeout() {
+    let Some(database) = TestDatabase::create("lock") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE t (id int);\n",
+    )
+    .unwrap();
+
+    // Stands in for an apply in progress, holding the lock pgm takes
+    let mut other_apply = Command::new("psql")
+        .args(["-X", "-q", "-d", &database.name, "-c"])
+        .arg("BEGIN; SELECT pg_advisory_xact_lock(7366509); SELECT pg_sleep(3); COMMIT;")
+        .spawn()
+        .expect("Failed to run psql");
+    let held = "SELECT count(*) FROM pg_locks WHERE locktype = 'advisory' AND objid = 7366509";
+    while database.query(held) == "0" {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+
+    let output = database.pgm(&["apply", "--lock-timeout", "200ms"], dir.path());
+    other_apply.wait().unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("Another apply is in progress"),
+        "{output:?}"
+    );
+
+    let output = database.pgm(&["apply", "--lock-timeout", "200ms"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT name FROM pgm_migration"), "00001");
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+use std::process::Command;
+
+fn write_migrations(dir: &Path) {
+    std::fs::create_dir_all(dir.join("migrations")).unwrap();
+    std::fs::write(
+        dir.join("migrations/00000.sql"),
+        "CREATE TABLE users (id int, email text, created_at timestamptz);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("migrations/00001_index.sql"),
+        "CREATE INDEX ON users (email);\n",
+    )
+    .unwrap();
+}
+
+#[test]
+fn runs_large_migrations_in_their_own_transaction() {
+    let Some(db) = TestDatabase::create("max_migration_size") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write_migrations(dir.path());
+
+    let output = db.pgm(
+        &["apply", "--dry-run", "--max-migration-size", "40"],
+        dir.path(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(sql.matches("-- pgm: new transaction\n").count(), 2, "{sql}");
+    assert_eq!(sql.matches("DO $pgm$ BEGIN").count(), 3, "{sql}");
+
+    // A later failure leaves the large migration applied, as it was committed
+    std::fs::write(
+        dir.path().join("migrations/00002_broken.sql"),
+        "SELECT * FROM missing;\n",
+    )
+    .unwrap();
+    let output = db.pgm(&["apply", "--max-migration-size", "40"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Transaction 3 of 3 failed; the ones before it were committed"),
+        "{stderr}"
+    );
+    assert_eq!(db.query("SELECT to_regclass('users') IS NOT NULL"), "t");
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00000"
+    );
+
+    std::fs::remove_file(dir.path().join("migrations/00002_broken.sql")).unwrap();
+    let output = db.pgm(&["apply", "--max-migration-size", "40"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00000,00001_index"
+    );
+}
+
+#[test]
+fn rejects_a_size_that_overflows() {
+    let dir = tempfile::tempdir().unwrap();
+    write_migrations(dir.path());
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args([
+            "apply",
+            "--dry-run",
+            "--max-migration-size",
+            "99999999999GB",
+            "--path",
+        ])
+        .arg(dir.path())
+        .output()
+        .expect("Failed to run pgm");
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("expected a size such as 500kB or 10MB, got '99999999999GB'"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn names_what_was_committed_when_a_later_transaction_fails() {
+    let Some(db) = TestDatabase::create("max_migration_size_failure") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00002_broken.sql"),
+        "CREATE TABLE orders (id int);\nSELECT * FROM missing;\n",
+    )
+    .unwrap();
+
+    let output = db.pgm(&["apply", "--max-migration-size", "40"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Transaction 2 of 3 failed; the ones before it were committed. Applied: migration: 00001_users."),
+        "{stderr}"
+    );
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00001_users"
+    );
+}
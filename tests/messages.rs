@@ -0,0 +1,48 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn messages_above_notice_hide_the_progress() {
+    let Some(database) = TestDatabase::create("messages") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        (
+            "migrations/00001.sql",
+            "CREATE TABLE a (id int);\nRAISE WARNING 'careful';\n",
+        ),
+        ("seeds/a.sql", "INSERT INTO a VALUES (1);\n"),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let output = database.pgm(
+        &[
+            "apply",
+            "--messages",
+            "warning",
+            "--unchanged-exit-code",
+            "3",
+        ],
+        dir.path(),
+    );
+    assert!(!output.status.success(), "{output:?}");
+
+    let output = database.pgm(&["apply", "--messages", "warning", "--seed"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("Applied migration"), "{stdout}");
+    assert!(!stdout.contains("Applied seed"), "{stdout}");
+    assert!(stdout.contains("WARNING:  careful"), "{stdout}");
+    assert!(stdout.contains("\nApplied in "), "{stdout}");
+    assert_eq!(database.query("SELECT count(*) FROM a"), "1");
+
+    let output = database.pgm(&["seed"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied seed: a"), "{stdout}");
+}
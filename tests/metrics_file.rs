@@ -0,0 +1,45 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn appends_a_line_per_apply() {
+    let Some(db) = TestDatabase::create("metrics_file") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("functions/one.sql"),
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    )
+    .unwrap();
+    let metrics = dir.path().join("deploy.log");
+    let metrics_arg = metrics.to_str().unwrap();
+
+    for _ in 0..2 {
+        let output = db.pgm(&["apply", "--metrics-file", metrics_arg], dir.path());
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    let lines = std::fs::read_to_string(&metrics)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(lines.len(), 2, "{lines:?}");
+    assert_eq!(lines[0]["version"], env!("CARGO_PKG_VERSION"));
+    assert!(lines[0]["duration_ms"].is_u64(), "{lines:?}");
+    assert_eq!(lines[0]["applied"], 2);
+    assert_eq!(lines[0]["categories"]["migrations"]["applied"], 1);
+    assert_eq!(lines[1]["applied"], 0);
+    assert_eq!(lines[1]["categories"]["functions"]["skipped"], 1);
+    assert_eq!(db.query("SELECT count(*) FROM pgm_migration"), "1");
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use common::TestDatabase;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn pgm(db: &TestDatabase, args: &[&str], pgm_dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(args)
+        .arg("--path")
+        .arg(pgm_dir)
+        .env("PGDATABASE", &db.name)
+        .env("PGM_MIGRATION_RELEASES", "1")
+        .output()
+        .expect("Failed to run pgm")
+}
+
+fn write_releases(dir: &Path) {
+    let write = |file: &str, sql: &str| {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, sql).unwrap();
+    };
+    write(
+        "migrations/00001_log.sql",
+        "CREATE TABLE log (id serial, migration text);\n",
+    );
+    for migration in ["v1/00001", "v1/00002", "v2/00001"] {
+        write(
+            &format!("migrations/{migration}.sql"),
+            &format!("INSERT INTO log (migration) VALUES ('{migration}');\n"),
+        );
+    }
+    write("migrations/.old/00001.sql", "SELECT * FROM missing;\n");
+}
+
+#[test]
+fn applies_release_directories_in_order() {
+    let Some(db) = TestDatabase::create("migration_releases") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write_releases(dir.path());
+
+    // Release directories are left alone unless enabled
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT count(*) FROM log"), "0");
+
+    let output = pgm(&db, &["apply", "--strict-order"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        db.query("SELECT string_agg(migration, ',' ORDER BY id) FROM log"),
+        "v1/00001,v1/00002,v2/00001"
+    );
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00001_log,v1/00001,v1/00002,v2/00001"
+    );
+
+    let output = pgm(&db, &["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT count(*) FROM log"), "3");
+
+    let output = pgm(&db, &["create", "migration", "--release", "v2"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.path().join("migrations/v2/00002.sql").is_file());
+}
+
+#[test]
+fn fakes_release_directories() {
+    let Some(db) = TestDatabase::create("migration_releases_fake") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    write_releases(dir.path());
+
+    let output = pgm(&db, &["apply", "--fake"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00001_log,v1/00001,v1/00002,v2/00001"
+    );
+
+    let output = pgm(&db, &["apply", "--rerun-migration", "00001"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("'00001' matches several migrations"),
+        "{stderr}"
+    );
+}
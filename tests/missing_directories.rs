@@ -0,0 +1,54 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dry_run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--path"])
+        .arg(dir)
+        .args(args)
+        .output()
+        .expect("Failed to run pgm")
+}
+
+#[test]
+fn warns_about_missing_directories_until_repaired() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE a (id int);\n",
+    )
+    .unwrap();
+
+    let output = dry_run(dir.path(), &[]);
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No functions/ directory found"), "{stderr}");
+    assert!(!stderr.contains("No migrations/"), "{stderr}");
+
+    let output = dry_run(dir.path(), &["--skip", "functions"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("No functions/"), "{stderr}");
+    assert!(stderr.contains("No views/ directory found"), "{stderr}");
+
+    let output = dry_run(dir.path(), &["--repair"]);
+    assert!(output.status.success(), "{output:?}");
+    for subdir in ["triggers", "views", "functions", "seeds"] {
+        assert!(dir.path().join(subdir).is_dir(), "{subdir}");
+    }
+    let output = dry_run(dir.path(), &[]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("directory found"), "{stderr}");
+}
+
+#[test]
+fn creates_a_migration_without_a_migrations_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["create", "migration", "--path"])
+        .arg(dir.path())
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.path().join("migrations/00001.sql").is_file());
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use common::TestDatabase;
+
+const FUNCTION: &str = "CREATE OR REPLACE FUNCTION count_later() RETURNS bigint AS $$
+SELECT count(*) FROM created_later;
+$$ LANGUAGE sql;
+";
+
+#[test]
+fn skips_the_body_check_of_functions_with_the_setting() {
+    let Some(database) = TestDatabase::create("no_body_check") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("functions")).unwrap();
+    std::fs::write(dir.path().join("functions/count_later.sql"), FUNCTION).unwrap();
+
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT to_regproc('count_later') IS NULL"),
+        "t"
+    );
+
+    std::fs::write(
+        dir.path().join("functions/count_later.sql"),
+        format!("-- pgm: no-body-check\n{FUNCTION}"),
+    )
+    .unwrap();
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT name FROM pgm_function"),
+        "count_later"
+    );
+    assert_eq!(
+        database.query("SELECT to_regproc('count_later') IS NULL"),
+        "f"
+    );
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn marks_applied_objects_without_emoji() {
+    let Some(db) = TestDatabase::create("no_emoji") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions", "seeds"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("functions/one.sql"),
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("seeds/1_users.sql"),
+        "INSERT INTO users VALUES (1);\n",
+    )
+    .unwrap();
+
+    let output = db.pgm(&["apply", "--no-emoji", "--timing", "--seed"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("[applied] Applied migration: 00001_users"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("[applied] Applied seed: 1_users"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("[timing] functions/one: "), "{stdout}");
+    assert!(stdout.is_ascii(), "{stdout}");
+    // The notices are still counted
+    assert!(
+        stdout.contains("Applied 1 of 1 migrations, 1 of 1 functions"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("functions/one\n"), "{stdout}");
+    assert_eq!(db.query("SELECT count(*) FROM users"), "1");
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn pgm(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(args)
+        .arg("--path")
+        .arg(dir)
+        .output()
+        .expect("Failed to run pgm")
+}
+
+fn write(dir: &Path, files: &[(&str, &str)]) {
+    for (file, content) in files {
+        let path = dir.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn warns_about_table_ddl_in_files_that_run_again() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        &[
+            ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+            (
+                "functions/f.sql",
+                "CREATE TABLE IF NOT EXISTS log (line text);\n\
+                 CREATE OR REPLACE FUNCTION f() RETURNS void AS $$\n\
+                 BEGIN\n\
+                 CREATE TABLE scratch (id int);\n\
+                 END\n\
+                 $$ LANGUAGE plpgsql;\n",
+            ),
+            (
+                "views/v.sql",
+                "ALTER TABLE a ADD COLUMN b int;\nCREATE OR REPLACE VIEW v AS SELECT * FROM a;\n",
+            ),
+            (
+                "seeds/1.sql",
+                "drop table a;\nINSERT INTO log VALUES ('seeded');\n",
+            ),
+        ],
+    );
+
+    let output = pgm(&["apply", "--dry-run"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("v.sql:1: 'ALTER TABLE' runs again whenever the file changes"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("f.sql"), "{stderr}");
+    assert!(!stderr.contains("00001.sql"), "{stderr}");
+
+    let output = pgm(&["apply", "--dry-run", "--strict"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("move it to a migration"),
+        "{output:?}"
+    );
+
+    let output = pgm(&["seed", "--dry-run"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("1.sql:1: 'DROP TABLE' runs again on every seed"),
+        "{stderr}"
+    );
+    let output = pgm(&["seed", "--dry-run", "--strict"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+}
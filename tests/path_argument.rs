@@ -0,0 +1,40 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn pgm(args: &[&str], dir: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("Failed to run pgm")
+}
+
+#[test]
+fn path_is_accepted_before_and_after_the_subcommand() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = pgm(&["init", "--path", "db"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let output = pgm(&["--path", "db", "create", "migration"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let output = pgm(&["create", "migration", "--path", "db"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    assert!(dir.path().join("db/migrations/00001.sql").is_file());
+    assert!(dir.path().join("db/migrations/00002.sql").is_file());
+    assert!(!dir.path().join("postgres").exists());
+}
+
+#[test]
+fn positional_init_path_still_works_with_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = pgm(&["init", "db"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.path().join("db/migrations").is_dir());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("deprecated"), "{stderr}");
+
+    let output = pgm(&["init", "other", "--path", "db"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+}
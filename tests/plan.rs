@@ -0,0 +1,53 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn applies_a_plan_only_while_it_is_current() {
+    let Some(database) = TestDatabase::create("plan") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE t (id int);\n",
+    )
+    .unwrap();
+    let plan_path = dir.path().join("plan.json");
+    let plan_path = plan_path.to_str().unwrap();
+
+    let output = database.pgm(&["plan", "-o", plan_path], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let plan = pgm::Plan::read(plan_path).unwrap();
+    assert_eq!(plan.notices, ["✅ Applied migration: 00001"]);
+    // Planning changes nothing
+    assert_eq!(database.query("SELECT to_regclass('t') IS NULL"), "t");
+
+    let output = database.pgm(&["apply", "--plan", plan_path], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT name FROM pgm_migration"), "00001");
+
+    // The migration is recorded now, so the plan no longer describes the apply
+    let output = database.pgm(&["apply", "--plan", plan_path], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("The database changed since the plan"),
+        "{output:?}"
+    );
+
+    let output = database.pgm(&["plan", "-o", plan_path], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    std::fs::write(
+        dir.path().join("migrations/00002.sql"),
+        "CREATE TABLE u (id int);\n",
+    )
+    .unwrap();
+    let output = database.pgm(&["apply", "--plan", plan_path], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("The plan was made for other SQL"),
+        "{output:?}"
+    );
+    assert_eq!(database.query("SELECT count(*) FROM pgm_migration"), "1");
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn runs_the_hooks_after_a_successful_apply() {
+    let Some(database) = TestDatabase::create("post_apply") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+    std::fs::create_dir_all(pgm_dir.join("migrations")).unwrap();
+    std::fs::create_dir_all(pgm_dir.join("hooks")).unwrap();
+    std::fs::write(
+        pgm_dir.join("migrations/00001.sql"),
+        "CREATE TABLE runs (id serial, at timestamptz DEFAULT now());",
+    )
+    .unwrap();
+    std::fs::write(
+        pgm_dir.join("hooks/post_apply.sql"),
+        "INSERT INTO runs DEFAULT VALUES;",
+    )
+    .unwrap();
+    let counts = dir.path().join("counts");
+    let post_apply = format!(
+        "echo \"$PGM_APPLIED $PGM_SKIPPED $PGM_APPLIED_MIGRATIONS\" > {}",
+        counts.display()
+    );
+
+    let output = database.pgm(&["apply", "--post-apply", &post_apply], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT count(*) FROM runs"), "1");
+    assert_eq!(std::fs::read_to_string(&counts).unwrap(), "1 0 1\n");
+
+    let output = database.pgm(&["apply", "--dry-run"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    let output = database.pgm(&["apply", "--fake"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT count(*) FROM runs"), "1");
+
+    // A failing hook is only a warning unless --post-apply-fatal is given,
+    // and the changes stay applied either way
+    let output = database.pgm(&["apply", "--post-apply", "exit 3"], &pgm_dir);
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Post-apply hook failed"),
+        "{output:?}"
+    );
+    assert_eq!(database.query("SELECT count(*) FROM runs"), "2");
+
+    let output = database.pgm(
+        &["apply", "--post-apply", "exit 3", "--post-apply-fatal"],
+        &pgm_dir,
+    );
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("a post-apply hook failed"),
+        "{output:?}"
+    );
+    assert_eq!(database.query("SELECT count(*) FROM runs"), "3");
+}
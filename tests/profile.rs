@@ -0,0 +1,46 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn prints_where_the_time_went() {
+    let Some(db) = TestDatabase::create("profile") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("functions/one.sql"),
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("functions/README.md"), "Not SQL\n").unwrap();
+
+    let output = db.pgm(&["apply", "--profile"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for phase in [
+        "ms  reading files",
+        "ms  checking files",
+        "ms  compiling",
+        "ms  executing",
+    ] {
+        assert!(stdout.contains(phase), "{stdout}");
+    }
+    let largest = stdout.split("Largest files:\n").nth(1).unwrap();
+    assert_eq!(
+        largest
+            .lines()
+            .map(|line| line.split_whitespace().last().unwrap())
+            .collect::<Vec<_>>(),
+        ["functions/one.sql", "migrations/00001_users.sql"]
+    );
+    assert_eq!(db.query("SELECT one()"), "1");
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn quiet_leaves_out_the_success_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("pgm");
+    for args in [
+        &["init", "--quiet"][..],
+        &["--quiet", "create", "migration"],
+        &["create", "view", "v", "--quiet"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+            .args(args)
+            .arg("--path")
+            .arg(&pgm_dir)
+            .output()
+            .expect("Failed to run pgm");
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "", "{args:?}");
+    }
+    assert!(pgm_dir.join("migrations/00001.sql").exists());
+    assert!(pgm_dir.join("views/v.sql").exists());
+}
+
+#[test]
+fn silent_leaves_out_the_notices_but_not_errors() {
+    let Some(database) = TestDatabase::create("quiet") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE a (id int);",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["apply", "--quiet"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied migration: 00001"), "{stdout}");
+    assert!(!stdout.contains("Applied 1 of 1"), "{stdout}");
+
+    std::fs::write(
+        dir.path().join("migrations/00002.sql"),
+        "CREATE TABLE b (id int);",
+    )
+    .unwrap();
+    let output = database.pgm(&["apply", "--silent"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(database.query("SELECT count(*) FROM pgm_migration"), "2");
+
+    std::fs::write(dir.path().join("migrations/00003.sql"), "CREATE TABLE a;").unwrap();
+    let output = database.pgm(&["apply", "--silent"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Error applying changes"),
+        "{output:?}"
+    );
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn recreates_a_view_replace_refuses() {
+    let Some(db) = TestDatabase::create("recreate_view") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "views"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id int, name text);\n",
+    );
+    write(
+        "views/active_users.sql",
+        "-- pgm: recreate\nCREATE OR REPLACE VIEW active_users AS SELECT id, name FROM users;\n",
+    );
+    write(
+        "views/active_user_ids.sql",
+        "-- pgm: depends=[active_users]\nCREATE OR REPLACE VIEW active_user_ids AS SELECT id FROM active_users;\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    write(
+        "views/active_users.sql",
+        "-- pgm: recreate\nCREATE OR REPLACE VIEW active_users AS SELECT id FROM users;\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(
+            "views/active_users: cannot drop columns from view; dropping it with CASCADE"
+        ),
+        "{stdout}"
+    );
+    assert_eq!(
+        db.query("SELECT string_agg(attname, ',') FROM pg_attribute WHERE attrelid = 'active_users'::regclass AND attnum > 0"),
+        "id"
+    );
+    // The view dropped along with it is created again by its own file
+    assert!(
+        stdout.contains("drop cascades to view active_user_ids"),
+        "{stdout}"
+    );
+    assert_eq!(
+        db.query("SELECT to_regclass('active_user_ids') IS NOT NULL"),
+        "t"
+    );
+}
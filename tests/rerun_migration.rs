@@ -0,0 +1,47 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn reruns_a_named_migration() {
+    let Some(db) = TestDatabase::create("rerun_migration") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00002_admin.sql"),
+        "INSERT INTO users VALUES (1);\n",
+    )
+    .unwrap();
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    // Reverted by hand, then run again
+    db.query("DELETE FROM users");
+    let output = db.pgm(&["apply", "--rerun-migration", "00002"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Re-running migration: 00002_admin"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("Applied 1 of 2 migrations"), "{stdout}");
+    assert_eq!(db.query("SELECT count(*) FROM users"), "1");
+    assert_eq!(db.query("SELECT count(*) FROM pgm_migration"), "2");
+
+    // Only for that apply
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT count(*) FROM users"), "1");
+
+    let output = db.pgm(&["apply", "--rerun-migration", "00003"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No migration '00003'"), "{stderr}");
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn reapplies_only_what_a_failed_apply_left_out() {
+    let Some(db) = TestDatabase::create("resume") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "migrations/00001_runs.sql",
+        "CREATE TABLE runs (migration text);\nINSERT INTO runs VALUES ('00001');\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    write(
+        "migrations/00002_more.sql",
+        "INSERT INTO runs VALUES ('00002');\n",
+    );
+    write(
+        "migrations/00003_broken.sql",
+        "INSERT INTO runs VALUES ('00003');\nSELECT * FROM missing;\n",
+    );
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    // The failed apply is rolled back as a whole, so nothing of it is recorded
+    assert_eq!(
+        db.query("SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration"),
+        "00001_runs"
+    );
+    assert_eq!(db.query("SELECT count(*) FROM pgm_function"), "0");
+
+    write(
+        "migrations/00003_broken.sql",
+        "INSERT INTO runs VALUES ('00003');\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        db.query("SELECT string_agg(migration, ',' ORDER BY migration) FROM runs"),
+        "00001,00002,00003",
+        "each migration ran once"
+    );
+    assert_eq!(db.query("SELECT count(*) FROM pgm_function"), "1");
+}
@@ -0,0 +1,44 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn role_owns_the_applied_objects() {
+    let Some(database) = TestDatabase::create("role") else {
+        return;
+    };
+    let role = format!("pgm_test_app_{}", std::process::id());
+    database.query(&format!(
+        "CREATE ROLE {role}; GRANT CREATE ON SCHEMA public TO {role}"
+    ));
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE items (id int);\n"),
+        (
+            "functions/f.sql",
+            "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+        ),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let output = database.pgm(&["apply", "--role", "app; DROP TABLE x"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid role name"), "{stderr}");
+
+    let output = database.pgm(&["apply", "--role", &role], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT tableowner FROM pg_tables WHERE tablename = 'items'"),
+        role
+    );
+    assert_eq!(
+        database.query("SELECT pg_get_userbyid(proowner) FROM pg_proc WHERE proname = 'f'"),
+        role
+    );
+
+    database.query(&format!("DROP OWNED BY {role}; DROP ROLE {role}"));
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::TestDatabase;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn runs_sql_given_inline_or_on_stdin_without_the_pgm_tables() {
+    let Some(database) = TestDatabase::create("run_sql") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = database.pgm(
+        &["apply", "--sql", "CREATE TABLE notes (body text);"],
+        dir.path(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("Ran the SQL in"),
+        "{output:?}"
+    );
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--sql", "-", "--set", "body=hello"])
+        .env("PGDATABASE", &database.name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run pgm");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"BEGIN;\nINSERT INTO notes VALUES (current_setting('pgm.body'));\nCOMMIT;\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    assert_eq!(database.query("SELECT body FROM notes"), "hello");
+    assert_eq!(
+        database.query("SELECT to_regclass('pgm_migration') IS NULL"),
+        "t"
+    );
+
+    let output = database.pgm(&["apply", "--sql", "DROP TABLE nope;"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Error running SQL"),
+        "{output:?}"
+    );
+}
+
+#[test]
+fn prints_the_wrapped_sql_on_a_dry_run() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--sql", "PERFORM 1;"])
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(sql.contains("DO $pgm$ BEGIN\n"), "{sql}");
+    assert!(sql.contains("<sql> --\nPERFORM 1;\n"), "{sql}");
+    assert!(!sql.contains("pgm_migration"), "{sql}");
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::TestDatabase;
+use std::process::Command;
+
+#[test]
+fn connects_through_a_service() {
+    let Some(db) = TestDatabase::create("service") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("pgm/migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("pgm/migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    let service_file = dir.path().join("pg_service.conf");
+    std::fs::write(&service_file, format!("[pgm_test]\ndbname={}\n", db.name)).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--service", "pgm_test", "--path"])
+        .arg(dir.path().join("pgm"))
+        .env("PGSERVICEFILE", &service_file)
+        .env_remove("PGDATABASE")
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(db.query("SELECT count(*) FROM pgm_migration"), "1");
+}
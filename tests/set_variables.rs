@@ -0,0 +1,44 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn migration_reads_variables_passed_with_set() {
+    let Some(database) = TestDatabase::create("set_variables") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let migration = dir.path().join("migrations/00001.sql");
+    std::fs::create_dir_all(migration.parent().unwrap()).unwrap();
+    std::fs::write(
+        &migration,
+        "CREATE TABLE settings AS SELECT current_setting('pgm.tablespace') AS tablespace, current_setting('pgm.flag') AS flag;\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(
+        &["apply", "--set", "tablespace=fast's", "--set", "flag=on"],
+        dir.path(),
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        database.query("SELECT tablespace || '|' || flag FROM settings"),
+        "fast's|on"
+    );
+}
+
+#[test]
+fn set_rejects_invalid_variable_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--set", "bad-name=1", "--path"])
+        .arg(dir.path())
+        .output()
+        .expect("Failed to run pgm");
+
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Invalid variable name 'bad-name'"),
+        "{output:?}"
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn applies_only_the_given_file() {
+    let Some(db) = TestDatabase::create("single_file") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for subdir in ["migrations", "functions", "views"] {
+        std::fs::create_dir_all(dir.path().join(subdir)).unwrap();
+    }
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "migrations/00001_users.sql",
+        "CREATE TABLE users (id int);\n",
+    );
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    );
+    write(
+        "functions/two.sql",
+        "CREATE OR REPLACE FUNCTION two() RETURNS int LANGUAGE sql AS 'SELECT 2';\n",
+    );
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 11';\n",
+    );
+    write(
+        "functions/two.sql",
+        "CREATE OR REPLACE FUNCTION two() RETURNS int LANGUAGE sql AS 'SELECT 22';\n",
+    );
+    write(
+        "migrations/00002_orders.sql",
+        "CREATE TABLE orders (id int);\n",
+    );
+    let output = db.pgm(&["apply", "--file", "functions/one.sql"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied 1 of 1 functions"), "{stdout}");
+    assert_eq!(db.query("SELECT one(), two()"), "11|2");
+    assert_eq!(db.query("SELECT to_regclass('orders') IS NULL"), "t");
+
+    // Unchanged, it is skipped like in a full apply
+    let output = db.pgm(&["apply", "--file", "functions/one.sql"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied 0 of 1 functions"), "{stdout}");
+
+    for (file, error) in [
+        ("migrations/00002_orders.sql", "is a migration"),
+        ("seeds/one.sql", "is not a SQL file in one of the"),
+        ("functions/three.sql", "not found"),
+    ] {
+        let output = db.pgm(&["apply", "--file", file], dir.path());
+        assert!(!output.status.success(), "{output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{stderr}");
+    }
+}
+
+#[test]
+fn checks_only_the_given_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("functions")).unwrap();
+    let write = |file: &str, sql: &str| std::fs::write(dir.path().join(file), sql).unwrap();
+    write(
+        "functions/one.sql",
+        "CREATE OR REPLACE FUNCTION one() RETURNS int LANGUAGE sql AS 'SELECT 1';\n",
+    );
+    write(
+        "functions/broken.sql",
+        "CREATE OR REPLACE FUNCTION broken() RETURNS int LANGUAGE sql AS $$ SELECT (1;\n",
+    );
+    write("functions/BROKEN.SQL", "-- pgm:no-transaction\n");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args([
+            "apply",
+            "--dry-run",
+            "--file",
+            "functions/one.sql",
+            "--path",
+        ])
+        .arg(dir.path())
+        .output()
+        .expect("Failed to run pgm");
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(sql.contains("one()"), "{sql}");
+    assert!(!sql.contains("broken()"), "{sql}");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--path"])
+        .arg(dir.path())
+        .output()
+        .expect("Failed to run pgm");
+    assert!(!output.status.success(), "{output:?}");
+}
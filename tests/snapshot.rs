@@ -0,0 +1,42 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn snapshots_the_schema_before_applying() {
+    let Some(db) = TestDatabase::create("snapshot") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001_users.sql"),
+        "CREATE TABLE users (id int);\n",
+    )
+    .unwrap();
+    let output = db.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert!(!dir.path().join(".pgm-snapshots").exists());
+
+    std::fs::write(
+        dir.path().join("migrations/00002_orders.sql"),
+        "CREATE TABLE orders (id int);\n",
+    )
+    .unwrap();
+    let output = db.pgm(&["apply", "--snapshot"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let snapshots = std::fs::read_dir(dir.path().join(".pgm-snapshots"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(snapshots.len(), 1, "{snapshots:?}");
+    let name = snapshots[0].file_name().unwrap().to_string_lossy();
+    assert!(name.ends_with(&format!("_{}.sql", db.name)), "{name}");
+    let snapshot = std::fs::read_to_string(&snapshots[0]).unwrap();
+    assert!(snapshot.contains("CREATE TABLE public.users"), "{snapshot}");
+    assert!(
+        !snapshot.contains("CREATE TABLE public.orders"),
+        "{snapshot}"
+    );
+    assert_eq!(db.query("SELECT to_regclass('orders') IS NOT NULL"), "t");
+}
@@ -0,0 +1,61 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dry_run(dir: &Path, extensions: Option<&str>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pgm"));
+    command.args(["apply", "--dry-run", "--path"]).arg(dir);
+    match extensions {
+        Some(extensions) => command.env("PGM_SQL_EXTENSIONS", extensions),
+        None => command.env_remove("PGM_SQL_EXTENSIONS"),
+    };
+    command.output().expect("Failed to run pgm")
+}
+
+#[test]
+fn compiles_files_with_the_configured_extensions() {
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        ("migrations/00001.sql", "CREATE TABLE a (id int);\n"),
+        ("migrations/00002.pgsql", "CREATE TABLE b (id int);\n"),
+        (
+            "functions/f.pgsql",
+            "CREATE OR REPLACE FUNCTION f() RETURNS int AS $$ SELECT 1 $$ LANGUAGE sql;\n",
+        ),
+        ("views/v.psql", "CREATE OR REPLACE VIEW v AS SELECT f();\n"),
+        ("views/notes.txt", "not SQL\n"),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    let output = dry_run(dir.path(), None);
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(sql.contains("CREATE TABLE a"), "{sql}");
+    assert!(!sql.contains("CREATE TABLE b"), "{sql}");
+    assert!(!sql.contains("FUNCTION f()"), "{sql}");
+
+    let output = dry_run(dir.path(), Some("sql, pgsql,.psql"));
+    assert!(output.status.success(), "{output:?}");
+    let sql = String::from_utf8_lossy(&output.stdout);
+    assert!(sql.contains("WHERE name = '00002')"), "{sql}");
+    assert!(sql.contains("WHERE name = 'f')"), "{sql}");
+    assert!(sql.contains("WHERE name = 'v')"), "{sql}");
+    assert!(!sql.contains("not SQL"), "{sql}");
+}
+
+#[test]
+fn fails_on_files_with_the_same_name_and_different_extensions() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("views")).unwrap();
+    std::fs::write(dir.path().join("views/v.sql"), "SELECT 1;\n").unwrap();
+    std::fs::write(dir.path().join("views/v.pgsql"), "SELECT 2;\n").unwrap();
+
+    let output = dry_run(dir.path(), Some("sql,pgsql"));
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("views/v: "),
+        "{output:?}"
+    );
+}
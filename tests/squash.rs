@@ -0,0 +1,92 @@
+mod common;
+
+use common::TestDatabase;
+
+const MIGRATIONS: &str = "SELECT string_agg(name, ',' ORDER BY name) FROM pgm_migration";
+const COLUMNS: &str =
+    "SELECT string_agg(table_name || '.' || column_name, ',' ORDER BY table_name, column_name)
+    FROM information_schema.columns WHERE table_schema = 'public' AND table_name NOT LIKE 'pgm_%'";
+
+#[test]
+fn squashed_migrations_converge_on_existing_and_new_databases() {
+    let Some(existing) = TestDatabase::create("squash_existing") else {
+        return;
+    };
+    let Some(new) = TestDatabase::create("squash_new") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    for (file, content) in [
+        ("00000.sql", "CREATE TABLE a (id int);\n"),
+        ("00001.sql", "ALTER TABLE a ADD COLUMN name text;\n"),
+        ("00002_b.sql", "CREATE TABLE b (id int);\n"),
+        ("00003.sql", "-- not written yet\n"),
+    ] {
+        std::fs::write(dir.path().join("migrations").join(file), content).unwrap();
+    }
+    let output = existing.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    let output = existing.pgm(&["squash"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Squashed 3 migrations into "), "{stdout}");
+    let mut files = std::fs::read_dir(dir.path().join("migrations"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    files.sort();
+    assert_eq!(files, ["00000_squash_00002.sql", "00003.sql"]);
+    assert!(dir
+        .path()
+        .join("archive/squashed_00002/00002_b.sql")
+        .is_file());
+
+    let output = existing.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("nothing changed"), "{stdout}");
+
+    let output = new.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(new.query(COLUMNS), "a.id,a.name,b.id");
+    assert_eq!(new.query(COLUMNS), existing.query(COLUMNS));
+    assert_eq!(
+        new.query(MIGRATIONS),
+        "00000,00000_squash_00002,00001,00002_b"
+    );
+    assert_eq!(new.query(MIGRATIONS), existing.query(MIGRATIONS));
+
+    let output = new.pgm(&["create", "migration"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert!(dir.path().join("migrations/00004.sql").is_file());
+}
+
+#[test]
+fn refuses_to_squash_migrations_that_are_not_applied() {
+    let Some(database) = TestDatabase::create("squash_unapplied") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE a (id int);\n",
+    )
+    .unwrap();
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    std::fs::write(
+        dir.path().join("migrations/00002.sql"),
+        "CREATE TABLE b (id int);\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["squash"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not applied: 00002"), "{stderr}");
+    assert!(dir.path().join("migrations/00001.sql").is_file());
+    assert_eq!(database.query(MIGRATIONS), "00001");
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn dry_run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--path"])
+        .arg(dir)
+        .args(args)
+        .output()
+        .expect("Failed to run pgm")
+}
+
+fn write_migrations(dir: &Path, files: &[&str]) {
+    std::fs::create_dir_all(dir.join("migrations")).unwrap();
+    for file in files {
+        std::fs::write(dir.join("migrations").join(file), "SELECT 1;\n").unwrap();
+    }
+}
+
+#[test]
+fn lists_gaps_and_duplicates() {
+    let dir = tempfile::tempdir().unwrap();
+    write_migrations(
+        dir.path(),
+        &[
+            "00001.sql",
+            "00002.sql",
+            "00005_add_users.sql",
+            "00005_add_orders.sql",
+        ],
+    );
+
+    let output = dry_run(dir.path(), &[]);
+    assert!(output.status.success(), "{output:?}");
+
+    let output = dry_run(dir.path(), &["--strict-order"]);
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("missing: 00003, 00004"), "{stderr}");
+    assert!(
+        stderr.contains("00005 used by: 00005_add_orders, 00005_add_users"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn counts_the_migrations_a_baseline_squashes() {
+    let dir = tempfile::tempdir().unwrap();
+    write_migrations(dir.path(), &["00003.sql"]);
+    std::fs::write(
+        dir.path().join("migrations/00000_squash_00002.sql"),
+        "-- pgm: squashes=[00001, 00002]\nSELECT 1;\n",
+    )
+    .unwrap();
+
+    let output = dry_run(dir.path(), &["--strict-order"]);
+    assert!(output.status.success(), "{output:?}");
+}
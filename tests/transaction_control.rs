@@ -0,0 +1,96 @@
+use std::process::{Command, Output};
+
+/// Creates a pgm directory with a single migration and compiles it with
+/// `pgm apply --dry-run`, which does not need a database.
+fn dry_run(migration: &str) -> Output {
+    let dir = tempfile::tempdir().unwrap();
+    let pgm_dir = dir.path().join("postgres");
+    std::fs::create_dir_all(pgm_dir.join("migrations")).unwrap();
+    std::fs::write(pgm_dir.join("migrations/00001.sql"), migration).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_pgm"))
+        .args(["apply", "--dry-run", "--path"])
+        .arg(&pgm_dir)
+        .output()
+        .expect("Failed to run pgm")
+}
+
+#[test]
+fn strips_top_level_begin_and_commit_with_a_warning() {
+    let output = dry_run("BEGIN;\nCREATE TABLE t (id int);\nCOMMIT;\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(stdout.contains("CREATE TABLE t (id int);"), "{stdout}");
+    assert!(!stdout.contains("BEGIN;"), "{stdout}");
+    assert!(!stdout.contains("COMMIT;"), "{stdout}");
+    assert!(stderr.contains("00001.sql:1: removed 'BEGIN;'"), "{stderr}");
+    assert!(
+        stderr.contains("00001.sql:3: removed 'COMMIT;'"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn strips_transaction_variants() {
+    let output = dry_run(
+        "begin transaction isolation level serializable;\nSELECT 1;\nCommit Work;\nSTART TRANSACTION;\nSELECT 2;\nCOMMIT;\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout).to_lowercase();
+
+    assert!(output.status.success());
+    assert!(!stdout.contains("begin transaction"), "{stdout}");
+    assert!(!stdout.contains("commit"), "{stdout}");
+    assert!(!stdout.contains("start transaction"), "{stdout}");
+}
+
+#[test]
+fn keeps_transaction_control_inside_dollar_quotes() {
+    let procedure =
+        "CREATE PROCEDURE p() LANGUAGE plpgsql AS $body$\nBEGIN\n  COMMIT;\nEND\n$body$;\n";
+    let output = dry_run(procedure);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(stdout.contains(procedure), "{stdout}");
+    assert!(!stderr.contains("removed"), "{stderr}");
+}
+
+#[test]
+fn rollback_is_an_error_naming_file_and_line() {
+    let output = dry_run("BEGIN;\nDELETE FROM t;\nROLLBACK;\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(!output.status.success());
+    assert!(
+        stderr.contains("00001.sql:3: 'ROLLBACK;' is not supported"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn keeps_transaction_control_inside_strings_and_comments() {
+    let migration = "INSERT INTO notes (body) VALUES ('first line\nCOMMIT;\nit''s still the string');\nINSERT INTO notes (body) VALUES (E'escaped \\'\nBEGIN;\n');\n/* Commented out:\nROLLBACK;\n/* nested */\nSTART TRANSACTION;\n*/\nSELECT 1;\n";
+    let output = dry_run(migration);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(stdout.contains(migration), "{stdout}");
+    assert!(!stderr.contains("removed"), "{stderr}");
+}
+
+#[test]
+fn strips_transaction_control_after_a_closed_string() {
+    let output = dry_run("BEGIN;\nINSERT INTO notes (body) VALUES ('a\nb');\nCOMMIT;\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{stderr}");
+    assert!(!stdout.contains("COMMIT;"), "{stdout}");
+    assert!(
+        stderr.contains("00001.sql:4: removed 'COMMIT;'"),
+        "{stderr}"
+    );
+}
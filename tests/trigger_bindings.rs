@@ -0,0 +1,53 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn recreates_the_triggers_of_a_changed_trigger_file() {
+    let Some(database) = TestDatabase::create("trigger_bindings") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    for (file, content) in [
+        (
+            "migrations/00001.sql",
+            "CREATE TABLE items (id int, touched bool);\n",
+        ),
+        (
+            "triggers/touch.sql",
+            "CREATE OR REPLACE FUNCTION touch() RETURNS trigger AS $$
+BEGIN
+    NEW.touched := true;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER items_touch
+    BEFORE INSERT ON items
+    FOR EACH ROW EXECUTE FUNCTION touch();
+",
+        ),
+    ] {
+        let path = dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    // The table only exists once the migration ran, after the functions
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    database.query("INSERT INTO items (id) VALUES (1)");
+    assert_eq!(database.query("SELECT touched FROM items"), "t");
+
+    let path = dir.path().join("triggers/touch.sql");
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, content.replace("INSERT", "INSERT OR UPDATE")).unwrap();
+    let output = database.pgm(&["apply"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    database.query("UPDATE items SET touched = false");
+    assert_eq!(database.query("SELECT touched FROM items"), "t");
+    assert_eq!(
+        database.query("SELECT count(*) FROM pg_trigger WHERE tgname = 'items_touch'"),
+        "1"
+    );
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn exits_with_the_given_code_when_nothing_changed() {
+    let Some(database) = TestDatabase::create("unchanged_exit_code") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("migrations")).unwrap();
+    std::fs::write(
+        dir.path().join("migrations/00001.sql"),
+        "CREATE TABLE t (id int);\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["apply", "--unchanged-exit-code", "3"], dir.path());
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+
+    let output = database.pgm(&["apply", "--unchanged-exit-code", "3"], dir.path());
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Applied 0 of 1 migrations in "), "{stdout}");
+    assert!(stdout.contains("s; nothing changed"), "{stdout}");
+    assert_eq!(database.query("SELECT count(*) FROM pgm_migration"), "1");
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::TestDatabase;
+
+#[test]
+fn reports_functions_changed_outside_of_pgm() {
+    let Some(database) = TestDatabase::create("verify") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("functions")).unwrap();
+    std::fs::write(
+        dir.path().join("functions/answer.sql"),
+        "CREATE OR REPLACE FUNCTION answer() RETURNS int AS $$\r\n  SELECT 42\r\n$$ LANGUAGE sql;\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["apply", "--verify"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+
+    database
+        .query("CREATE OR REPLACE FUNCTION answer() RETURNS int AS $$ SELECT 43 $$ LANGUAGE sql");
+    let output = database.pgm(&["apply", "--verify"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("functions/answer: the body of public.answer() differs from the file"),
+        "{output:?}"
+    );
+}
+
+#[test]
+fn compares_single_quoted_bodies() {
+    let Some(database) = TestDatabase::create("verify_single_quoted") else {
+        return;
+    };
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("functions")).unwrap();
+    std::fs::write(
+        dir.path().join("functions/greeting.sql"),
+        "CREATE OR REPLACE FUNCTION greeting() RETURNS text LANGUAGE sql\n    AS 'SELECT ''it''''s me''';\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("functions/answer.sql"),
+        "-- Not the body: 'SELECT 0'\nCREATE OR REPLACE FUNCTION answer() RETURNS int SET search_path = 'public' LANGUAGE sql as\n'SELECT 42';\n",
+    )
+    .unwrap();
+
+    let output = database.pgm(&["apply", "--verify"], dir.path());
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(database.query("SELECT greeting()"), "it's me");
+
+    database.query(
+        "CREATE OR REPLACE FUNCTION greeting() RETURNS text LANGUAGE sql AS 'SELECT ''hi'''",
+    );
+    let output = database.pgm(&["apply", "--verify"], dir.path());
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("functions/greeting: the body of public.greeting() differs from the file"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("functions/answer"), "{stderr}");
+}